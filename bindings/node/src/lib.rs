@@ -0,0 +1,147 @@
+//! Node.js bindings of the payload builder and the USB and BLE transports
+//!
+//! ```js
+//! const { Payload, writeUsb } = require("badgemagic");
+//! const payload = new Payload();
+//! payload.addText("Hello", { mode: "center", blink: true });
+//! writeUsb(payload);
+//! ```
+
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(clippy::missing_errors_doc, clippy::needless_pass_by_value)]
+
+use badgemagic::{
+    ble,
+    config::{Config, Message},
+    protocol::{Mode, PayloadBuffer, Speed},
+    usb_hid,
+};
+use napi::{bindgen_prelude::AsyncTask, Env, Error, JsBuffer, Result, Task};
+use napi_derive::napi;
+
+/// Options of a text message
+#[napi(object)]
+pub struct MessageOptions {
+    /// Display mode, e.g. `"left"` or `"center"`
+    pub mode: Option<String>,
+
+    /// Animation speed from 0 (slowest) to 7 (fastest)
+    pub speed: Option<u8>,
+
+    /// Enable blink mode
+    pub blink: Option<bool>,
+
+    /// Show an animated border
+    pub border: Option<bool>,
+}
+
+/// Messages to be written to a badge
+#[napi]
+#[derive(Default)]
+pub struct Payload {
+    config: Config,
+}
+
+#[napi]
+impl Payload {
+    /// Create an empty payload
+    #[napi(constructor)]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a config in the format of the CLI (`"toml"` or `"json"`)
+    #[napi(factory)]
+    pub fn from_config(source: String, format: Option<String>) -> Result<Self> {
+        let format = format.as_deref().unwrap_or("toml");
+        let config = Config::parse(&source, format).map_err(to_napi)?;
+        Ok(Self { config })
+    }
+
+    /// Append a text message
+    #[napi]
+    pub fn add_text(&mut self, text: String, options: Option<MessageOptions>) -> Result<()> {
+        let mut message = Message::text(text);
+        if let Some(options) = options {
+            if let Some(mode) = options.mode {
+                let mode: Mode = serde_json::from_value(mode.into())
+                    .map_err(|err| Error::from_reason(format!("invalid mode: {err}")))?;
+                message = message.mode(mode);
+            }
+            if let Some(speed) = options.speed {
+                let speed = Speed::try_from(speed)
+                    .map_err(|_| Error::from_reason(format!("invalid speed: {speed}")))?;
+                message = message.speed(speed);
+            }
+            if options.blink == Some(true) {
+                message = message.blink();
+            }
+            if options.border == Some(true) {
+                message = message.border();
+            }
+        }
+        self.config = std::mem::take(&mut self.config).message(message);
+        Ok(())
+    }
+
+    /// Render the messages into the bytes sent to the badge
+    #[napi]
+    pub fn to_buffer(&self, env: Env) -> Result<JsBuffer> {
+        let payload = self.build()?;
+        Ok(env
+            .create_buffer_with_data(payload.as_bytes().to_vec())?
+            .into_raw())
+    }
+
+    fn build(&self) -> Result<PayloadBuffer> {
+        self.config.build_payload().map_err(to_napi)
+    }
+}
+
+/// List the badges connected over USB
+#[napi]
+pub fn list_usb_devices() -> Result<Vec<String>> {
+    usb_hid::Device::list_all().map_err(to_napi)
+}
+
+/// Write the payload to the single badge connected over USB
+#[napi]
+pub fn write_usb(payload: &Payload) -> Result<()> {
+    let payload = payload.build()?;
+    usb_hid::Device::single()
+        .and_then(|device| device.write(payload))
+        .map_err(to_napi)
+}
+
+/// Write the payload to the single badge in range over Bluetooth Low Energy
+///
+/// Scanning and writing runs on a worker thread, the returned promise resolves when it is done.
+#[napi(ts_return_type = "Promise<void>")]
+pub fn write_ble(payload: &Payload) -> Result<AsyncTask<WriteBle>> {
+    Ok(AsyncTask::new(WriteBle(Some(payload.build()?))))
+}
+
+pub struct WriteBle(Option<PayloadBuffer>);
+
+impl Task for WriteBle {
+    type Output = ();
+    type JsValue = ();
+
+    fn compute(&mut self) -> Result<()> {
+        let payload = self.0.take().expect("task is only run once");
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async { ble::Device::single().await?.write(payload).await })
+            .map_err(to_napi)
+    }
+
+    fn resolve(&mut self, _env: Env, (): ()) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn to_napi(err: impl Into<anyhow::Error>) -> Error {
+    Error::from_reason(format!("{:#}", err.into()))
+}
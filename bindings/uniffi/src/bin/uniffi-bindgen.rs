@@ -0,0 +1,5 @@
+//! Generate the Kotlin and Swift bindings, see the crate documentation
+
+fn main() {
+    uniffi::uniffi_bindgen_main();
+}
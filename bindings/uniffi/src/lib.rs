@@ -0,0 +1,254 @@
+//! Kotlin and Swift bindings of the payload builder
+//!
+//! The apps keep using their native Bluetooth stack, they implement [`Transport`]
+//! to receive the chunks to be written to the badge characteristic.
+//!
+//! Generate the bindings with
+//! `cargo run -p badgemagic-uniffi -F bindgen --bin uniffi-bindgen -- generate --library <library> --language kotlin --out-dir <dir>`.
+
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc,
+    clippy::needless_pass_by_value
+)]
+
+use std::sync::{Arc, Mutex};
+
+use badgemagic::{
+    config::{Config, Message},
+    protocol::{self, PayloadBuffer, BLE_CHUNK_SIZE, MAX_PAYLOAD_SIZE},
+};
+
+uniffi::setup_scaffolding!();
+
+/// Service UUID of the badge
+const BADGE_SERVICE_UUID: &str = "0000fee0-0000-1000-8000-00805f9b34fb";
+
+/// UUID of the characteristic the payload is written to
+const BADGE_CHAR_UUID: &str = "0000fee1-0000-1000-8000-00805f9b34fb";
+
+/// Display mode, see [`protocol::Mode`]
+#[derive(uniffi::Enum)]
+pub enum Mode {
+    Left,
+    Right,
+    Up,
+    Down,
+    Center,
+    Fast,
+    Drop,
+    Curtain,
+    Laser,
+}
+
+/// Animation speed, see [`protocol::Speed`]
+#[derive(uniffi::Enum)]
+pub enum Speed {
+    Fps1_2,
+    Fps1_3,
+    Fps2,
+    Fps2_4,
+    Fps2_8,
+    Fps4_5,
+    Fps7_5,
+    Fps15,
+}
+
+impl From<Mode> for protocol::Mode {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Left => Self::Left,
+            Mode::Right => Self::Right,
+            Mode::Up => Self::Up,
+            Mode::Down => Self::Down,
+            Mode::Center => Self::Center,
+            Mode::Fast => Self::Fast,
+            Mode::Drop => Self::Drop,
+            Mode::Curtain => Self::Curtain,
+            Mode::Laser => Self::Laser,
+        }
+    }
+}
+
+impl From<Speed> for protocol::Speed {
+    fn from(speed: Speed) -> Self {
+        match speed {
+            Speed::Fps1_2 => Self::Fps1_2,
+            Speed::Fps1_3 => Self::Fps1_3,
+            Speed::Fps2 => Self::Fps2,
+            Speed::Fps2_4 => Self::Fps2_4,
+            Speed::Fps2_8 => Self::Fps2_8,
+            Speed::Fps4_5 => Self::Fps4_5,
+            Speed::Fps7_5 => Self::Fps7_5,
+            Speed::Fps15 => Self::Fps15,
+        }
+    }
+}
+
+/// Style of a text message
+#[derive(uniffi::Record)]
+pub struct TextStyle {
+    pub mode: Mode,
+    pub speed: Speed,
+    pub blink: bool,
+    pub border: bool,
+}
+
+/// Errors returned to the app
+#[derive(Debug, thiserror::Error, uniffi::Error)]
+pub enum BadgeError {
+    /// The config could not be parsed
+    #[error("invalid config: {message}")]
+    InvalidConfig { message: String },
+
+    /// The messages could not be rendered, e.g. because there are more than 8
+    #[error("render messages: {message}")]
+    Render { message: String },
+
+    /// The transport of the app failed
+    #[error("transport: {message}")]
+    Transport { message: String },
+}
+
+/// Writes the payload to the badge, implemented by the app
+#[uniffi::export(with_foreign)]
+pub trait Transport: Send + Sync {
+    /// Write one chunk to the badge characteristic (write without response)
+    fn write_chunk(&self, chunk: Vec<u8>) -> Result<(), BadgeError>;
+}
+
+/// Service UUID to scan for badges
+#[uniffi::export]
+#[must_use]
+pub fn badge_service_uuid() -> String {
+    BADGE_SERVICE_UUID.into()
+}
+
+/// UUID of the characteristic the chunks are written to
+#[uniffi::export]
+#[must_use]
+pub fn badge_characteristic_uuid() -> String {
+    BADGE_CHAR_UUID.into()
+}
+
+/// Messages to be written to a badge
+#[derive(uniffi::Object)]
+pub struct Payload {
+    config: Mutex<Config>,
+}
+
+#[uniffi::export]
+impl Payload {
+    /// Create an empty payload
+    #[uniffi::constructor]
+    #[must_use]
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            config: Mutex::default(),
+        })
+    }
+
+    /// Parse a config in the format of the CLI (`"toml"` or `"json"`)
+    #[uniffi::constructor]
+    pub fn from_config(source: String, format: String) -> Result<Arc<Self>, BadgeError> {
+        let config = Config::parse(&source, &format).map_err(|err| BadgeError::InvalidConfig {
+            message: format!("{err:#}"),
+        })?;
+        Ok(Arc::new(Self {
+            config: Mutex::new(config),
+        }))
+    }
+
+    /// Append a text message
+    pub fn add_text(&self, text: String, style: TextStyle) {
+        let mut message = Message::text(text)
+            .mode(protocol::Mode::from(style.mode))
+            .speed(style.speed.into());
+        if style.blink {
+            message = message.blink();
+        }
+        if style.border {
+            message = message.border();
+        }
+        let mut config = self.config.lock().unwrap();
+        *config = std::mem::take(&mut *config).message(message);
+    }
+
+    /// Render the messages into the bytes sent to the badge
+    pub fn to_bytes(&self) -> Result<Vec<u8>, BadgeError> {
+        Ok(self.build()?.as_bytes().to_vec())
+    }
+
+    /// Split the padded payload into the chunks written to the badge characteristic
+    pub fn ble_chunks(&self) -> Result<Vec<Vec<u8>>, BadgeError> {
+        let data = self.build()?.into_padded_bytes();
+        let data = data.as_ref();
+        if data.len() > MAX_PAYLOAD_SIZE {
+            return Err(BadgeError::Render {
+                message: format!("payload too long (max {MAX_PAYLOAD_SIZE} bytes)"),
+            });
+        }
+        Ok(data.chunks(BLE_CHUNK_SIZE).map(<[u8]>::to_vec).collect())
+    }
+
+    /// Write the payload to the badge with the transport of the app
+    pub fn write(&self, transport: Arc<dyn Transport>) -> Result<(), BadgeError> {
+        for chunk in self.ble_chunks()? {
+            transport.write_chunk(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl Payload {
+    fn build(&self) -> Result<PayloadBuffer, BadgeError> {
+        self.config
+            .lock()
+            .unwrap()
+            .build_payload()
+            .map_err(|err| BadgeError::Render {
+                message: format!("{err:#}"),
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::{BadgeError, Mode, Payload, Speed, TextStyle, Transport};
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<Vec<u8>>>);
+
+    impl Transport for Recorder {
+        fn write_chunk(&self, chunk: Vec<u8>) -> Result<(), BadgeError> {
+            self.0.lock().unwrap().push(chunk);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_chunks() {
+        let payload = Payload::new();
+        payload.add_text(
+            "Hi".into(),
+            TextStyle {
+                mode: Mode::Center,
+                speed: Speed::Fps2_4,
+                blink: false,
+                border: false,
+            },
+        );
+        let recorder = Arc::new(Recorder::default());
+        payload.write(recorder.clone()).unwrap();
+        let chunks = recorder.0.lock().unwrap();
+        assert_eq!(chunks.len(), 8);
+        assert!(chunks.iter().all(|chunk| chunk.len() == 16));
+        assert_eq!(
+            chunks.concat()[..payload.to_bytes().unwrap().len()],
+            payload.to_bytes().unwrap()
+        );
+    }
+}
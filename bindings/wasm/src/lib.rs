@@ -0,0 +1,263 @@
+//! WebAssembly bindings of the payload builder with Web Bluetooth and `WebHID` transports
+//!
+//! Build the npm package with `wasm-pack build --target web bindings/wasm`.
+//! ```js
+//! import init, { Payload, writeBluetooth } from "badgemagic-wasm";
+//! await init();
+//! const payload = new Payload();
+//! payload.addText("Hello", { mode: "center", blink: true });
+//! await writeBluetooth(payload);
+//! ```
+//!
+//! Both transports have to be called from a user gesture (e.g. a click handler),
+//! as the browser asks the user to select the badge.
+
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(clippy::missing_errors_doc, clippy::needless_pass_by_value)]
+
+use badgemagic::{
+    config::{Config, Message},
+    protocol::{Mode, PayloadBuffer, Speed, BLE_CHUNK_SIZE, MAX_PAYLOAD_SIZE, USB_REPORT_SIZE},
+};
+use js_sys::{Promise, Reflect, Uint8Array, JSON};
+use serde::Deserialize;
+use wasm_bindgen::{prelude::*, JsCast};
+use wasm_bindgen_futures::JsFuture;
+
+/// Service of the badge (`0xfee0`)
+const BADGE_SERVICE_UUID: u32 = 0xfee0;
+
+/// Characteristic the payload is written to (`0xfee1`)
+const BADGE_CHAR_UUID: u32 = 0xfee1;
+
+#[wasm_bindgen(typescript_custom_section)]
+const MESSAGE_OPTIONS: &str = r#"
+export interface MessageOptions {
+    /** Display mode, e.g. `"left"` or `"center"` */
+    mode?: "left" | "right" | "up" | "down" | "center" | "fast" | "drop" | "curtain" | "laser";
+    /** Animation speed from 0 (slowest) to 7 (fastest) */
+    speed?: number;
+    /** Enable blink mode */
+    blink?: boolean;
+    /** Show an animated border */
+    border?: boolean;
+}
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(typescript_type = "MessageOptions")]
+    pub type MessageOptionsObject;
+
+    type Bluetooth;
+    #[wasm_bindgen(method, js_name = requestDevice)]
+    fn request_device(this: &Bluetooth, options: &JsValue) -> Promise;
+
+    type BluetoothDevice;
+    #[wasm_bindgen(method, getter)]
+    fn gatt(this: &BluetoothDevice) -> BluetoothRemoteGattServer;
+
+    type BluetoothRemoteGattServer;
+    #[wasm_bindgen(method)]
+    fn connect(this: &BluetoothRemoteGattServer) -> Promise;
+    #[wasm_bindgen(method)]
+    fn disconnect(this: &BluetoothRemoteGattServer);
+    #[wasm_bindgen(method, js_name = getPrimaryService)]
+    fn get_primary_service(this: &BluetoothRemoteGattServer, uuid: u32) -> Promise;
+
+    type BluetoothRemoteGattService;
+    #[wasm_bindgen(method, js_name = getCharacteristic)]
+    fn get_characteristic(this: &BluetoothRemoteGattService, uuid: u32) -> Promise;
+
+    type BluetoothRemoteGattCharacteristic;
+    #[wasm_bindgen(method, js_name = writeValueWithoutResponse)]
+    fn write_value_without_response(
+        this: &BluetoothRemoteGattCharacteristic,
+        value: &Uint8Array,
+    ) -> Promise;
+
+    type Hid;
+    #[wasm_bindgen(method, js_name = requestDevice)]
+    fn request_device(this: &Hid, options: &JsValue) -> Promise;
+
+    type HidDevice;
+    #[wasm_bindgen(method)]
+    fn open(this: &HidDevice) -> Promise;
+    #[wasm_bindgen(method)]
+    fn close(this: &HidDevice) -> Promise;
+    #[wasm_bindgen(method, js_name = sendReport)]
+    fn send_report(this: &HidDevice, report_id: u8, data: &Uint8Array) -> Promise;
+}
+
+/// Options of a text message, deserialized from [`MessageOptionsObject`]
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MessageOptions {
+    mode: Option<Mode>,
+    speed: Option<u8>,
+    #[serde(default)]
+    blink: bool,
+    #[serde(default)]
+    border: bool,
+}
+
+/// Messages to be written to a badge
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct Payload {
+    config: Config,
+}
+
+#[wasm_bindgen]
+impl Payload {
+    /// Create an empty payload
+    #[wasm_bindgen(constructor)]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a config in the format of the CLI (`"toml"` or `"json"`)
+    #[wasm_bindgen(js_name = fromConfig)]
+    pub fn from_config(source: &str, format: Option<String>) -> Result<Payload, JsError> {
+        let format = format.as_deref().unwrap_or("toml");
+        let config = Config::parse(source, format).map_err(to_js)?;
+        Ok(Self { config })
+    }
+
+    /// Append a text message
+    #[wasm_bindgen(js_name = addText)]
+    pub fn add_text(
+        &mut self,
+        text: String,
+        options: Option<MessageOptionsObject>,
+    ) -> Result<(), JsError> {
+        let options: MessageOptions = match options {
+            Some(options) => {
+                let json: String = JSON::stringify(&options)
+                    .map_err(|_| JsError::new("invalid message options"))?
+                    .into();
+                serde_json::from_str(&json)
+                    .map_err(|err| JsError::new(&format!("invalid message options: {err}")))?
+            }
+            None => MessageOptions::default(),
+        };
+        let mut message = Message::text(text);
+        if let Some(mode) = options.mode {
+            message = message.mode(mode);
+        }
+        if let Some(speed) = options.speed {
+            let speed = Speed::try_from(speed)
+                .map_err(|_| JsError::new(&format!("invalid speed: {speed}")))?;
+            message = message.speed(speed);
+        }
+        if options.blink {
+            message = message.blink();
+        }
+        if options.border {
+            message = message.border();
+        }
+        self.config = std::mem::take(&mut self.config).message(message);
+        Ok(())
+    }
+
+    /// Render the messages into the bytes sent to the badge
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsError> {
+        Ok(self.build()?.as_bytes().to_vec())
+    }
+}
+
+impl Payload {
+    fn build(&self) -> Result<PayloadBuffer, JsError> {
+        self.config.build_payload().map_err(to_js)
+    }
+
+    /// Render the messages padded to 64 bytes, as written to the badge
+    fn padded_bytes(&self) -> Result<Vec<u8>, JsError> {
+        let data = self.build()?.into_padded_bytes().as_ref().to_vec();
+        if data.len() > MAX_PAYLOAD_SIZE {
+            return Err(JsError::new(&format!(
+                "payload too long (max {MAX_PAYLOAD_SIZE} bytes)"
+            )));
+        }
+        Ok(data)
+    }
+}
+
+/// Ask the user to select a badge with Web Bluetooth and write the payload to it
+#[wasm_bindgen(js_name = writeBluetooth)]
+pub async fn write_bluetooth(payload: &Payload) -> Result<(), JsValue> {
+    let data = payload.padded_bytes()?;
+    let bluetooth: Bluetooth = navigator_property("bluetooth")?;
+    let options = JSON::parse(&format!(
+        r#"{{"filters": [{{"services": [{BADGE_SERVICE_UUID}]}}]}}"#
+    ))?;
+    let device: BluetoothDevice = JsFuture::from(bluetooth.request_device(&options))
+        .await?
+        .unchecked_into();
+
+    let server = device.gatt();
+    JsFuture::from(server.connect()).await?;
+    let result = async {
+        let service: BluetoothRemoteGattService =
+            JsFuture::from(server.get_primary_service(BADGE_SERVICE_UUID))
+                .await?
+                .unchecked_into();
+        let characteristic: BluetoothRemoteGattCharacteristic =
+            JsFuture::from(service.get_characteristic(BADGE_CHAR_UUID))
+                .await?
+                .unchecked_into();
+        for chunk in data.chunks(BLE_CHUNK_SIZE) {
+            JsFuture::from(characteristic.write_value_without_response(&chunk.into())).await?;
+        }
+        Ok(())
+    }
+    .await;
+    server.disconnect();
+    result
+}
+
+/// Ask the user to select a badge with `WebHID` and write the payload to it
+#[wasm_bindgen(js_name = writeUsb)]
+pub async fn write_usb(payload: &Payload) -> Result<(), JsValue> {
+    let data = payload.padded_bytes()?;
+    let hid: Hid = navigator_property("hid")?;
+    let options = JSON::parse(r#"{"filters": [{"vendorId": 1046, "productId": 20512}]}"#)?;
+    let devices: js_sys::Array = JsFuture::from(hid.request_device(&options))
+        .await?
+        .unchecked_into();
+    let device = devices.get(0);
+    if device.is_undefined() {
+        return Err(JsError::new("no device selected").into());
+    }
+    let device: HidDevice = device.unchecked_into();
+
+    JsFuture::from(device.open()).await?;
+    let result = async {
+        for report in data.chunks(USB_REPORT_SIZE) {
+            JsFuture::from(device.send_report(0, &report.into())).await?;
+        }
+        Ok(())
+    }
+    .await;
+    JsFuture::from(device.close()).await?;
+    result
+}
+
+/// Get a property of `navigator`, e.g. `bluetooth` or `hid`
+fn navigator_property<T: JsCast>(name: &str) -> Result<T, JsValue> {
+    let navigator = Reflect::get(&js_sys::global(), &"navigator".into())?;
+    let value = Reflect::get(&navigator, &name.into())?;
+    if value.is_undefined() {
+        return Err(JsError::new(&format!(
+            "navigator.{name} is not supported by this browser"
+        ))
+        .into());
+    }
+    Ok(value.unchecked_into())
+}
+
+fn to_js(err: anyhow::Error) -> JsError {
+    JsError::new(&format!("{err:#}"))
+}
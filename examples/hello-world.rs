@@ -2,12 +2,8 @@
 
 use anyhow::Result;
 use badgemagic::{
-    embedded_graphics::{
-        geometry::Point, mono_font::MonoTextStyle, pixelcolor::BinaryColor, text::Text,
-    },
-    protocol::{Mode, PayloadBuffer, Style},
-    usb_hid::Device,
-    util::DrawableLayoutExt,
+    embedded_graphics::mono_font::iso_8859_1::{FONT_4X6, FONT_6X9},
+    prelude::*,
 };
 
 fn main() -> Result<()> {
@@ -18,10 +14,7 @@ fn main() -> Result<()> {
         &Text::new(
             "Hello",
             Point::new(0, 8),
-            MonoTextStyle::new(
-                &embedded_graphics::mono_font::iso_8859_1::FONT_6X9,
-                BinaryColor::On,
-            ),
+            MonoTextStyle::new(&FONT_6X9, BinaryColor::On),
         ),
     );
 
@@ -30,22 +23,16 @@ fn main() -> Result<()> {
         &Text::new(
             "Hello",
             Point::new(0, 5),
-            MonoTextStyle::new(
-                &embedded_graphics::mono_font::iso_8859_1::FONT_4X6,
-                BinaryColor::On,
-            ),
+            MonoTextStyle::new(&FONT_4X6, BinaryColor::On),
         )
         .z_stack(Text::new(
             "World",
             Point::new(23, 8),
-            MonoTextStyle::new(
-                &embedded_graphics::mono_font::iso_8859_1::FONT_4X6,
-                BinaryColor::On,
-            ),
+            MonoTextStyle::new(&FONT_4X6, BinaryColor::On),
         )),
     );
 
-    Device::single()?.write(payload)?;
+    UsbDevice::single()?.write(payload)?;
 
     Ok(())
 }
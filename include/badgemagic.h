@@ -0,0 +1,124 @@
+#ifndef BADGEMAGIC_H
+#define BADGEMAGIC_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The function succeeded
+ */
+#define BADGEMAGIC_OK 0
+
+/**
+ * A required pointer argument was null
+ */
+#define BADGEMAGIC_ERROR_NULL_POINTER -1
+
+/**
+ * An argument was invalid, e.g. a string that is not UTF-8 or an unknown mode
+ */
+#define BADGEMAGIC_ERROR_INVALID_ARGUMENT -2
+
+/**
+ * The messages could not be rendered, e.g. because there are more than 8
+ */
+#define BADGEMAGIC_ERROR_RENDER -3
+
+/**
+ * The payload could not be written to the badge
+ */
+#define BADGEMAGIC_ERROR_TRANSPORT -4
+
+/**
+ * The function panicked, which is a bug in badgemagic
+ */
+#define BADGEMAGIC_ERROR_PANIC -5
+
+/**
+ * Flag for [`badgemagic_payload_add_text`]: blink the message
+ */
+#define BADGEMAGIC_BLINK 1
+
+/**
+ * Flag for [`badgemagic_payload_add_text`]: show an animated border around the message
+ */
+#define BADGEMAGIC_BORDER 2
+
+/**
+ * Messages to be written to a badge
+ */
+typedef struct BadgemagicPayload BadgemagicPayload;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Create an empty payload, to be released with [`badgemagic_payload_free`]
+ *
+ * Returns null if creating the payload panicked.
+ */
+BadgemagicPayload *badgemagic_payload_new(void);
+
+/**
+ * Release a payload created by [`badgemagic_payload_new`]
+ *
+ * # Safety
+ *
+ * `payload` must be null or a pointer returned by [`badgemagic_payload_new`]
+ * that was not released yet.
+ */
+void badgemagic_payload_free(BadgemagicPayload *payload);
+
+/**
+ * Append a text message
+ *
+ * `mode` and `speed` are the numeric values of the protocol (`0` to `8` and `0` to `7`),
+ * `flags` is a combination of [`BADGEMAGIC_BLINK`] and [`BADGEMAGIC_BORDER`].
+ *
+ * # Safety
+ *
+ * `payload` must be null or a valid payload, `text` must be null or a nul-terminated string.
+ */
+int badgemagic_payload_add_text(BadgemagicPayload *payload,
+                                const char *text,
+                                uint8_t mode,
+                                uint8_t speed,
+                                uint32_t flags);
+
+/**
+ * Write the payload to the single badge connected over USB
+ *
+ * # Safety
+ *
+ * `payload` must be null or a valid payload.
+ */
+int badgemagic_write_usb(const BadgemagicPayload *payload);
+
+/**
+ * Write the payload to the single badge in range over Bluetooth Low Energy
+ *
+ * Blocks the calling thread while scanning for the badge and writing the payload.
+ *
+ * # Safety
+ *
+ * `payload` must be null or a valid payload.
+ */
+int badgemagic_write_ble_blocking(const BadgemagicPayload *payload);
+
+/**
+ * Description of the last error on the current thread, or null if there was none
+ *
+ * The string is valid until the next call of a `badgemagic_` function on the same thread.
+ */
+const char *badgemagic_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* BADGEMAGIC_H */
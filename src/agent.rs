@@ -0,0 +1,288 @@
+//! Agent to write payloads to a badge connected to another host
+//!
+//! Protocol (all integers big endian):
+//! 1. the client sends the magic `BMAGENT1`
+//! 2. the client sends the shared token (`u16` length and bytes)
+//! 3. the client sends the payload (`u32` length and bytes)
+//! 4. the agent answers with a status byte (0 on success) and a message (`u16` length and bytes)
+//!
+//! The token is sent in plain text, use a VPN or SSH tunnel on untrusted networks.
+
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use badgemagic::protocol::{PayloadBuffer, MAX_PAYLOAD_SIZE};
+
+use crate::{write_payload, TransportProtocol, SHUTDOWN_POLL_INTERVAL};
+
+const MAGIC: &[u8; 8] = b"BMAGENT1";
+
+/// Time a client has to send its whole request, clients are served one after another
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Minimum length of the shared token, shorter tokens are easy to guess
+const MIN_TOKEN_LEN: usize = 16;
+
+/// Accept payloads on `listener` and write them to the badge, until `shutdown` is set
+pub fn serve(
+    transport: &TransportProtocol,
+    listener: &TcpListener,
+    token: &str,
+    shutdown: &AtomicBool,
+) -> Result<()> {
+    anyhow::ensure!(
+        token.len() >= MIN_TOKEN_LEN,
+        "the token must be at least {MIN_TOKEN_LEN} bytes long, e.g. `openssl rand -hex 16`"
+    );
+    eprintln!("listening on {}", listener.local_addr()?);
+    listener.set_nonblocking(true)?;
+    while !shutdown.load(Ordering::Relaxed) {
+        let (mut stream, peer) = match listener.accept() {
+            Ok(connection) => connection,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(SHUTDOWN_POLL_INTERVAL);
+                continue;
+            }
+            Err(err) => {
+                eprintln!("accept connection: {err}");
+                continue;
+            }
+        };
+        let result =
+            receive(&mut stream, token).and_then(|payload| write_payload(transport, payload));
+        let (status, message) = match &result {
+            Ok(()) => (0, String::from("ok")),
+            Err(err) => {
+                eprintln!("{peer}: {err:#}");
+                (1, format!("{err:#}"))
+            }
+        };
+        if let Err(err) = respond(&mut stream, status, &message) {
+            eprintln!("{peer}: send response: {err:#}");
+        }
+    }
+    Ok(())
+}
+
+fn receive(stream: &mut TcpStream, token: &str) -> Result<PayloadBuffer> {
+    stream.set_nonblocking(false)?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let stream = &mut Deadline::after(stream, TIMEOUT);
+
+    let mut magic = [0; MAGIC.len()];
+    stream.read_exact(&mut magic).context("read magic")?;
+    anyhow::ensure!(&magic == MAGIC, "unsupported protocol");
+
+    let mut len = [0; 2];
+    stream.read_exact(&mut len).context("read token")?;
+    let mut received = vec![0; u16::from_be_bytes(len).into()];
+    stream.read_exact(&mut received).context("read token")?;
+    anyhow::ensure!(
+        constant_time_eq(&received, token.as_bytes()),
+        "invalid token"
+    );
+
+    let mut len = [0; 4];
+    stream.read_exact(&mut len).context("read payload")?;
+    let len = u32::from_be_bytes(len);
+    let len = len as usize;
+    anyhow::ensure!(len <= MAX_PAYLOAD_SIZE, "payload too long: {len} bytes");
+    let mut payload = vec![0; len];
+    stream.read_exact(&mut payload).context("read payload")?;
+    Ok(PayloadBuffer::from_bytes(&payload)?)
+}
+
+fn respond(stream: &mut TcpStream, status: u8, message: &str) -> Result<()> {
+    let message = &message.as_bytes()[..message.len().min(u16::MAX.into())];
+    stream.write_all(&[status])?;
+    stream.write_all(&u16::try_from(message.len())?.to_be_bytes())?;
+    stream.write_all(message)?;
+    // closing with unread data resets the connection, which can discard the response
+    // before the client read it, so the rest of the request is read first
+    stream.shutdown(Shutdown::Write)?;
+    let _ = io::copy(
+        &mut Deadline::after(stream, TIMEOUT).take(MAX_PAYLOAD_SIZE as u64),
+        &mut io::sink(),
+    );
+    Ok(())
+}
+
+/// Reader failing with a timeout once `deadline` has passed, however slowly the data arrives
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl<'a> Deadline<'a> {
+    fn after(stream: &'a TcpStream, timeout: Duration) -> Self {
+        Self {
+            stream,
+            deadline: Instant::now() + timeout,
+        }
+    }
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(ErrorKind::TimedOut.into());
+        }
+        self.stream.set_read_timeout(Some(remaining))?;
+        self.stream.read(buf)
+    }
+}
+
+/// Compare the tokens without leaking the position of the first difference
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Send `payload` to the agent at `address`
+pub fn send(address: &str, token: &str, payload: &PayloadBuffer) -> Result<()> {
+    let mut stream =
+        TcpStream::connect(address).with_context(|| format!("connect to agent {address}"))?;
+    let token = token.as_bytes();
+    let data = payload.as_bytes();
+    stream.write_all(MAGIC)?;
+    stream.write_all(
+        &u16::try_from(token.len())
+            .context("token too long")?
+            .to_be_bytes(),
+    )?;
+    stream.write_all(token)?;
+    stream.write_all(&u32::try_from(data.len())?.to_be_bytes())?;
+    stream.write_all(data)?;
+
+    let mut status = [0; 1];
+    stream
+        .read_exact(&mut status)
+        .context("read agent response")?;
+    let mut len = [0; 2];
+    stream.read_exact(&mut len).context("read agent response")?;
+    let mut message = vec![0; u16::from_be_bytes(len).into()];
+    stream
+        .read_exact(&mut message)
+        .context("read agent response")?;
+    anyhow::ensure!(
+        status[0] == 0,
+        "agent: {}",
+        String::from_utf8_lossy(&message)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{ErrorKind, Read, Write},
+        net::{TcpListener, TcpStream},
+        thread::{self, JoinHandle},
+        time::{Duration, Instant},
+    };
+
+    use anyhow::Result;
+    use badgemagic::protocol::{PayloadBuffer, State, Style, MAX_PAYLOAD_SIZE};
+
+    use super::{receive, respond, send, Deadline, MAGIC};
+
+    const TOKEN: &str = "0123456789abcdef";
+
+    /// Answer a single request on a loopback port like [`super::serve`], returning the address
+    fn agent() -> (String, JoinHandle<Result<Vec<u8>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let agent = thread::spawn(move || {
+            let (mut stream, _) = listener.accept()?;
+            let result = receive(&mut stream, TOKEN);
+            let (status, message) = match &result {
+                Ok(_) => (0, String::from("ok")),
+                Err(err) => (1, format!("{err:#}")),
+            };
+            respond(&mut stream, status, &message)?;
+            result.map(|payload| payload.as_bytes().to_vec())
+        });
+        (address, agent)
+    }
+
+    /// Send `request` as is and return the status byte of the response
+    fn request(address: &str, request: &[u8]) -> u8 {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(request).unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        response[0]
+    }
+
+    fn payload() -> PayloadBuffer {
+        let mut payload = PayloadBuffer::new();
+        payload
+            .add_message(Style::default(), 2)
+            .set((3, 5), State::On)
+            .unwrap();
+        payload
+    }
+
+    #[test]
+    fn send_payload() {
+        let (address, agent) = agent();
+        send(&address, TOKEN, &payload()).unwrap();
+        assert_eq!(agent.join().unwrap().unwrap(), payload().as_bytes());
+    }
+
+    #[test]
+    fn reject_invalid_token() {
+        let (address, agent) = agent();
+        let err = send(&address, "fedcba9876543210", &payload()).unwrap_err();
+        assert_eq!(err.to_string(), "agent: invalid token");
+        assert!(agent.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn deadline_covers_the_whole_request() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        // a client sending a byte every 20 ms would pass a timeout per read
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            while stream.write_all(b"x").is_ok() {
+                thread::sleep(Duration::from_millis(20));
+            }
+        });
+        let (stream, _) = listener.accept().unwrap();
+        let start = Instant::now();
+        let err = Deadline::after(&stream, Duration::from_millis(200))
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(2));
+        drop(stream);
+        client.join().unwrap();
+    }
+
+    #[test]
+    fn reject_invalid_magic() {
+        let (address, agent) = agent();
+        assert_eq!(request(&address, b"GET / HT"), 1);
+        let err = agent.join().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "unsupported protocol");
+    }
+
+    #[test]
+    fn reject_oversized_payload() {
+        let (address, agent) = agent();
+        let mut data = MAGIC.to_vec();
+        data.extend(u16::try_from(TOKEN.len()).unwrap().to_be_bytes());
+        data.extend(TOKEN.as_bytes());
+        data.extend(u32::try_from(MAX_PAYLOAD_SIZE + 1).unwrap().to_be_bytes());
+        assert_eq!(request(&address, &data), 1);
+        let err = agent.join().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "payload too long: 8193 bytes");
+    }
+}
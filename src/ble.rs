@@ -1,16 +1,27 @@
 //! Connect to an LED badge via Bluetooth Low Energy (BLE)
+//!
+//! The functions of this module do not depend on a specific async runtime,
+//! but btleplug needs a tokio runtime on Linux and macOS.
+//! Applications using another runtime (e.g. async-std or smol) can run them with [`compat`].
 
-use std::time::Duration;
+use std::{
+    future::Future,
+    sync::{Mutex, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
 
-use anyhow::{Context, Result};
 use btleplug::{
     api::{bleuuid, Central as _, Manager as _, Peripheral as _, ScanFilter, WriteType},
-    platform::{Manager, Peripheral},
+    platform::{Adapter, Manager, Peripheral},
 };
-use tokio::time;
+use futures_timer::Delay;
 use uuid::Uuid;
 
-use crate::protocol::PayloadBuffer;
+use crate::{
+    protocol::{PayloadBuffer, BLE_CHUNK_SIZE, MAX_PAYLOAD_SIZE},
+    record, Error, Result,
+};
 
 /// `0000fee0-0000-1000-8000-00805f9b34fb`
 const BADGE_SERVICE_UUID: Uuid = bleuuid::uuid_from_u16(0xfee0);
@@ -18,113 +29,198 @@ const BADGE_SERVICE_UUID: Uuid = bleuuid::uuid_from_u16(0xfee0);
 const BADGE_CHAR_UUID: Uuid = bleuuid::uuid_from_u16(0xfee1);
 
 const BADGE_BLE_DEVICE_NAME: &str = "LSLED";
-const BLE_CHAR_CHUNK_SIZE: usize = 16;
 
-/// A discovered BLE device
-pub struct Device {
-    peripheral: Peripheral,
+/// Bluetooth adapter shared by multiple operations
+///
+/// The first operation of a session starts a scan for all devices, which keeps running
+/// while the session exists. Later operations use the devices found so far instead of
+/// scanning again, e.g. to write to a device after listing all devices,
+/// or to write to multiple devices one after another.
+/// ```no_run
+/// use badgemagic::{ble::Session, protocol::PayloadBuffer};
+/// # async fn example() -> badgemagic::Result<()> {
+/// let session = Session::new().await?;
+/// for device in session.list_all().await? {
+///     println!("{device}");
+/// }
+/// // no second scan, the badge was already found while listing
+/// session.single().await?.write(PayloadBuffer::new()).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Session {
+    _manager: Manager,
+    adapter: Adapter,
+    scan_started: Mutex<Option<Instant>>,
+    chunk_size: usize,
 }
 
-impl Device {
-    /// Return a list of all BLE devies as a string representation.
-    pub async fn list_all() -> Result<Vec<String>> {
-        // Run device scan
-        let manager = Manager::new().await.context("create BLE manager")?;
-        let adapters = manager
+impl Session {
+    /// Connect to the first Bluetooth adapter
+    pub async fn new() -> Result<Self> {
+        let manager = Manager::new()
+            .await
+            .map_err(Error::transfer("create BLE manager"))?;
+        let adapter = manager
             .adapters()
             .await
-            .context("enumerate bluetooth adapters")?;
-        let adapter = adapters.first().context("no bluetooth adapter found")?;
+            .map_err(Error::transfer("enumerate bluetooth adapters"))?
+            .into_iter()
+            .next()
+            .ok_or(Error::NoAdapter)?;
+        Ok(Self {
+            _manager: manager,
+            adapter,
+            scan_started: Mutex::default(),
+            chunk_size: BLE_CHUNK_SIZE,
+        })
+    }
 
-        adapter
-            .start_scan(ScanFilter {
-                // don't filter by service
-                services: Vec::new(),
-            })
-            .await
-            .context("bluetooth scan start")?;
-        time::sleep(Duration::from_secs(2)).await;
+    /// Write chunks of `chunk_size` bytes to the devices found by this session,
+    /// see [`Device::chunk_size`]
+    ///
+    /// # Panics
+    /// Panics if `chunk_size` is zero.
+    #[must_use]
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must not be zero");
+        self.chunk_size = chunk_size;
+        self
+    }
 
-        let mut devices = Vec::new();
-        for peripheral in adapter
+    /// Start the scan on first use and wait until it ran for at least `duration`
+    async fn scan(&self, duration: Duration) -> Result<Vec<Peripheral>> {
+        let started = *self.scan_started.lock().unwrap();
+        let started = if let Some(started) = started {
+            started
+        } else {
+            self.adapter
+                .start_scan(ScanFilter {
+                    // don't filter by service, to list all devices
+                    services: Vec::new(),
+                })
+                .await
+                .map_err(Error::transfer("bluetooth scan start"))?;
+            *self
+                .scan_started
+                .lock()
+                .unwrap()
+                .get_or_insert_with(Instant::now)
+        };
+        Delay::new(duration.saturating_sub(started.elapsed())).await;
+        self.adapter
             .peripherals()
             .await
-            .context("enumerating bluetooth devices")?
-        {
-            let device = async {
-                let props = peripheral
-                    .properties()
-                    .await?
-                    .context("missing device info")?;
-
-                Ok(format!(
+            .map_err(Error::transfer("enumerating bluetooth devices"))
+    }
+
+    /// Return a list of all BLE devies as a string representation.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn list_all(&self) -> Result<Vec<String>> {
+        let mut devices = Vec::new();
+        for peripheral in self.scan(Duration::from_secs(2)).await? {
+            devices.push(match peripheral.properties().await {
+                Ok(Some(props)) => format!(
                     "{}: name={:?} services={:?}",
                     props.address, props.local_name, props.services
-                ))
-            };
-            devices.push(device.await.unwrap_or_else(|err: anyhow::Error| {
-                format!("{} failed to collect info: {err:?}", peripheral.address())
-            }));
+                ),
+                Ok(None) => format!(
+                    "{} failed to collect info: missing device info",
+                    peripheral.address()
+                ),
+                Err(err) => format!("{} failed to collect info: {err:?}", peripheral.address()),
+            });
         }
-
         Ok(devices)
     }
 
-    /// Return all supported devices that are found in two seconds.
+    /// Return all supported devices that are found in the first two seconds of the scan.
     ///
     /// Returns all badges that are in BLE range and are in Bluetooth transfer mode.
-    pub async fn enumerate() -> Result<Vec<Self>> {
-        Self::enumerate_duration(Duration::from_secs(2)).await
+    pub async fn enumerate(&self) -> Result<Vec<Device>> {
+        self.enumerate_duration(Duration::from_secs(2)).await
     }
 
-    /// Return all supported devices that are found in the given duration.
+    /// Return all supported devices that are found when the scan ran for the given duration.
     ///
     /// Returns all badges that are in BLE range and are in Bluetooth transfer mode.
-    /// # Panics
-    /// This function panics if it is unable to access the Bluetooth adapter.
-    pub async fn enumerate_duration(scan_duration: Duration) -> Result<Vec<Self>> {
-        // Run device scan
-        let manager = Manager::new().await.context("create BLE manager")?;
-        let adapters = manager
-            .adapters()
-            .await
-            .context("enumerate bluetooth adapters")?;
-        let adapter = adapters.first().context("no bluetooth adapter found")?;
-
-        adapter
-            .start_scan(ScanFilter {
-                services: vec![BADGE_SERVICE_UUID],
-            })
-            .await
-            .context("bluetooth scan start")?;
-        time::sleep(scan_duration).await;
-
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub async fn enumerate_duration(&self, scan_duration: Duration) -> Result<Vec<Device>> {
         // Filter for badge devices
         let mut led_badges = vec![];
-        for p in adapter
-            .peripherals()
-            .await
-            .context("enumerating bluetooth devices")?
-        {
-            if let Some(badge) = Self::from_peripheral(p).await {
+        for p in self.scan(scan_duration).await? {
+            if let Some(mut badge) = Device::from_peripheral(p).await {
+                badge.chunk_size = self.chunk_size;
+                event!(tracing::Level::DEBUG, address = %badge.address(), "found badge");
                 led_badges.push(badge);
             }
         }
 
+        event!(
+            tracing::Level::DEBUG,
+            count = led_badges.len(),
+            "scan finished"
+        );
         Ok(led_badges)
     }
 
+    /// Return the single supported device
+    ///
+    /// This function returns an error if no device could be found
+    /// or if multiple devices would match.
+    pub async fn single(&self) -> Result<Device> {
+        let mut devices = self.enumerate().await?.into_iter();
+        let device = devices.next().ok_or(Error::NoDevice)?;
+        if devices.next().is_some() {
+            return Err(Error::MultipleDevices);
+        }
+        Ok(device)
+    }
+}
+
+/// A discovered BLE device
+pub struct Device {
+    peripheral: Peripheral,
+    chunk_size: usize,
+}
+
+impl Device {
+    /// Return a list of all BLE devies as a string representation.
+    ///
+    /// Use a [`Session`] to find devices for later operations in the same scan.
+    pub async fn list_all() -> Result<Vec<String>> {
+        Session::new().await?.list_all().await
+    }
+
+    /// Return all supported devices that are found in two seconds.
+    ///
+    /// Returns all badges that are in BLE range and are in Bluetooth transfer mode.
+    pub async fn enumerate() -> Result<Vec<Self>> {
+        Self::enumerate_duration(Duration::from_secs(2)).await
+    }
+
+    /// Return all supported devices that are found in the given duration.
+    ///
+    /// Returns all badges that are in BLE range and are in Bluetooth transfer mode.
+    pub async fn enumerate_duration(scan_duration: Duration) -> Result<Vec<Self>> {
+        Session::new()
+            .await?
+            .enumerate_duration(scan_duration)
+            .await
+    }
+
     async fn from_peripheral(peripheral: Peripheral) -> Option<Self> {
-        // The existance of the service with the correct UUID
-        // exists is already checked by the scan filter.
-        // But we also need to check the device name to make sure
-        // we're talking to a badge as some devices that are not led badges
+        // Besides the service with the correct UUID, we also need to check the device name
+        // to make sure we're talking to a badge as some devices that are not led badges
         // also use the same service UUID.
         let props = peripheral.properties().await.ok()??;
         let local_name = props.local_name.as_ref()?;
 
-        if local_name == BADGE_BLE_DEVICE_NAME {
-            Some(Self { peripheral })
+        if local_name == BADGE_BLE_DEVICE_NAME && props.services.contains(&BADGE_SERVICE_UUID) {
+            Some(Self {
+                peripheral,
+                chunk_size: BLE_CHUNK_SIZE,
+            })
         } else {
             None
         }
@@ -135,13 +231,27 @@ impl Device {
     /// This function returns an error if no device could be found
     /// or if multiple devices would match.
     pub async fn single() -> Result<Self> {
-        let mut devices = Self::enumerate()
-            .await
-            .context("enumerating badges")?
-            .into_iter();
-        let device = devices.next().context("no device found")?;
-        anyhow::ensure!(devices.next().is_none(), "multiple devices found");
-        Ok(device)
+        Session::new().await?.single().await
+    }
+
+    /// Return the Bluetooth address of the device
+    #[must_use]
+    pub fn address(&self) -> String {
+        self.peripheral.address().to_string()
+    }
+
+    /// Write the payload in chunks of `chunk_size` bytes (default 16)
+    ///
+    /// Some clone firmwares expect 20 or 64 byte chunks.
+    /// The last chunk is shorter if the padded payload is not a multiple of the chunk size.
+    ///
+    /// # Panics
+    /// Panics if `chunk_size` is zero.
+    #[must_use]
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must not be zero");
+        self.chunk_size = chunk_size;
+        self
     }
 
     /// Write a payload to the device.
@@ -149,58 +259,131 @@ impl Device {
     /// This function connects to the device, writes the payload and disconnects.
     /// When the device went out of range between discovering it
     /// and writing the payload, an error is returned.
-    /// # Panics
-    /// This functions panics if the BLE device does not have the expected badge characteristic.
+    /// Devices without the badge characteristic return [`Error::MissingCharacteristic`].
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(address = %self.address()))
+    )]
     pub async fn write(&self, payload: PayloadBuffer) -> Result<()> {
+        let bytes = payload.into_padded_bytes();
+        let data = bytes.as_ref();
+
+        // the device will brick itself if the payload is too long (more then 8192 bytes)
+        if data.len() > MAX_PAYLOAD_SIZE {
+            return Err(Error::PayloadTooLarge(data.len()));
+        }
+
+        event!(
+            tracing::Level::DEBUG,
+            bytes = data.len(),
+            chunks = data.len().div_ceil(self.chunk_size),
+            "writing payload"
+        );
+        self.write_chunks(data.chunks(self.chunk_size)).await
+    }
+
+    /// Write raw chunks to the badge characteristic, e.g. to replay a recorded transfer
+    ///
+    /// Like [`Device::write`], this connects to the device, writes the chunks and disconnects.
+    pub async fn write_chunks<'a>(&self, chunks: impl IntoIterator<Item = &'a [u8]>) -> Result<()> {
         self.peripheral
             .connect()
             .await
-            .context("bluetooth device connect")?;
+            .map_err(Error::transfer("bluetooth device connect"))?;
+        event!(tracing::Level::DEBUG, "connected");
+        record::transfer(record::Transport::Ble, &self.address());
 
-        let result = self.write_connected(payload).await;
+        let result = self.write_connected(chunks).await;
         let disconnect_result = self.peripheral.disconnect().await;
 
         if result.is_ok() {
             // Write succesful, return disconnect result
-            Ok(disconnect_result?)
+            disconnect_result.map_err(Error::transfer("bluetooth device disconnect"))
         } else {
             // Write failed, return write result and ignore disconnect result
             result
         }
     }
 
-    async fn write_connected(&self, payload: PayloadBuffer) -> Result<()> {
+    async fn write_connected<'a>(&self, chunks: impl IntoIterator<Item = &'a [u8]>) -> Result<()> {
         // Get characteristic
         self.peripheral
             .discover_services()
             .await
-            .context("discovering services")?;
+            .map_err(Error::transfer("discovering services"))?;
         let characteristics = self.peripheral.characteristics();
         let badge_char = characteristics
             .iter()
             .find(|c| c.uuid == BADGE_CHAR_UUID)
-            .context("badge characteristic not found")?;
-
-        // Write payload
-        let bytes = payload.into_padded_bytes();
-        let data = bytes.as_ref();
-
-        anyhow::ensure!(
-            data.len() % BLE_CHAR_CHUNK_SIZE == 0,
-            "Payload size must be a multiple of {} bytes",
-            BLE_CHAR_CHUNK_SIZE
-        );
+            .ok_or(Error::MissingCharacteristic)?;
 
-        // the device will brick itself if the payload is too long (more then 8192 bytes)
-        anyhow::ensure!(data.len() <= 8192, "payload too long (max 8192 bytes)");
-
-        for chunk in data.chunks(BLE_CHAR_CHUNK_SIZE) {
+        // Write chunks
+        for chunk in chunks {
+            event!(tracing::Level::TRACE, ?chunk, "writing chunk");
+            record::chunk(record::Transport::Ble, chunk);
             self.peripheral
                 .write(badge_char, chunk, WriteType::WithoutResponse)
                 .await
-                .context("writing payload chunk")?;
+                .map_err(Error::transfer("writing payload chunk"))?;
         }
 
         Ok(())
     }
 }
+
+/// Run `future` on a tokio runtime in the background and wait for its output
+///
+/// The returned future can be polled by any executor.
+/// ```no_run
+/// use badgemagic::{ble, protocol::PayloadBuffer};
+/// # async fn example() -> badgemagic::Result<()> {
+/// ble::compat(async {
+///     ble::Device::single()
+///         .await?
+///         .write(PayloadBuffer::new())
+///         .await
+/// })
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+/// # Panics
+/// This function panics if the background runtime cannot be started,
+/// and resumes the panic of `future`.
+pub async fn compat<F>(future: F) -> F::Output
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    static RUNTIME: OnceLock<tokio::runtime::Handle> = OnceLock::new();
+    let handle = RUNTIME.get_or_init(|| {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("create tokio runtime");
+        let handle = runtime.handle().clone();
+        thread::Builder::new()
+            .name("badgemagic-ble".into())
+            .spawn(move || runtime.block_on(std::future::pending::<()>()))
+            .expect("spawn tokio runtime thread");
+        handle
+    });
+    match handle.spawn(future).await {
+        Ok(output) => output,
+        Err(err) => std::panic::resume_unwind(err.into_panic()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    #[test]
+    fn compat_without_tokio() {
+        let output = futures_lite::future::block_on(super::compat(async {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            42
+        }));
+        assert_eq!(output, 42);
+    }
+}
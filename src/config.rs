@@ -0,0 +1,1235 @@
+//! Configuration of the messages shown on a badge
+//!
+//! This is the data model of the TOML and JSON config files read by the CLI.
+//! ```
+//! use badgemagic::{
+//!     config::{Config, Message},
+//!     protocol::Mode,
+//! };
+//! # fn main() -> anyhow::Result<()> {
+//! let payload = Config::default()
+//!     .message(Message::text("Hello").mode(Mode::Center))
+//!     .message(Message::text("World").blink())
+//!     .build_payload()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+pub use crate::protocol::Font;
+use crate::protocol::{Mode, PayloadBuffer, Speed};
+
+pub mod app;
+pub mod cache;
+#[cfg(feature = "time")]
+mod calendar;
+mod feed;
+#[cfg(any(feature = "weather", feature = "feed"))]
+mod http;
+mod now_playing;
+pub mod registry;
+mod render;
+mod stats;
+mod weather;
+
+/// A complete config with up to 8 messages (or multiple devices)
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[must_use]
+pub struct Config {
+    /// Config files merged into this config, relative to this file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<PathBuf>,
+
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub defaults: StyleOptions,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub styles: HashMap<String, StyleOptions>,
+
+    #[serde(default, rename = "message", skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<Message>,
+
+    #[serde(default, rename = "device", skip_serializing_if = "Vec::is_empty")]
+    pub devices: Vec<DeviceConfig>,
+}
+
+impl Config {
+    /// Load a config file and all files it includes
+    ///
+    /// The file format (`toml` or `json`) is detected by the file extension,
+    /// unless it is given as `format`.
+    /// `${NAME}` in strings is replaced with the environment variable `NAME` (`$$` is a literal `$`).
+    pub fn load(path: &Path, format: Option<&str>) -> Result<Self> {
+        Self::load_recursive(path, format, &mut Vec::new())
+    }
+
+    fn load_recursive(path: &Path, format: Option<&str>, stack: &mut Vec<PathBuf>) -> Result<Self> {
+        let canonical = path
+            .canonicalize()
+            .with_context(|| format!("load config: {}", path.display()))?;
+        anyhow::ensure!(
+            !stack.contains(&canonical),
+            "config includes itself: {}",
+            path.display()
+        );
+
+        let config =
+            fs::read_to_string(path).with_context(|| format!("load config: {}", path.display()))?;
+        let extension = format
+            .map(AsRef::as_ref)
+            .or(path.extension())
+            .context("missing file extension for config file")?;
+        let mut config = Self::parse_with(&config, extension.to_str().unwrap_or_default(), true)
+            .with_context(|| format!("parse config: {}", path.display()))?;
+
+        stack.push(canonical);
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let mut base = Self::default();
+        for include in std::mem::take(&mut config.include) {
+            // included files are always detected by their extension
+            base = Self::load_recursive(&dir.join(include), None, stack)?.merge(base);
+        }
+        stack.pop();
+
+        Ok(config.merge(base))
+    }
+
+    /// Parse a config in the given `format` (`toml` or `json`)
+    ///
+    /// Unlike [`Config::load`], environment variables are not interpolated
+    /// and no local files are read (includes, `bitmap_file`, `banner_font` or calendars),
+    /// as `source` may be untrusted.
+    pub fn parse(source: &str, format: &str) -> Result<Self> {
+        let config = Self::parse_with(source, format, false)?;
+        anyhow::ensure!(
+            config.include.is_empty(),
+            "includes are only supported in config files"
+        );
+        let devices = config.devices.iter().flat_map(|d| &d.messages);
+        if let Some(file) = config
+            .messages
+            .iter()
+            .chain(devices)
+            .find_map(|message| message.content.file())
+        {
+            anyhow::bail!("files are only read by config files: {}", file.display());
+        }
+        Ok(config)
+    }
+
+    /// Parse a config, with `trusted` the environment variables are interpolated
+    fn parse_with(source: &str, format: &str, trusted: bool) -> Result<Self> {
+        let lookup = |name: &str| std::env::var(name).ok();
+        Ok(match format {
+            "json" => {
+                let mut value: serde_json::Value = serde_json::from_str(source)?;
+                if trusted {
+                    interpolate_json(&mut value, &lookup)?;
+                }
+                serde_json::from_value(value)?
+            }
+            "toml" => {
+                let mut value: toml::Value = toml::from_str(source)?;
+                if trusted {
+                    interpolate_toml(&mut value, &lookup)?;
+                }
+                toml::Value::try_into(value)?
+            }
+            _ => anyhow::bail!("unsupported config format: {format:?}"),
+        })
+    }
+
+    /// Merge this config on top of `base`
+    ///
+    /// The defaults and style presets of this config take precedence,
+    /// the messages of this config are appended to the messages of `base`.
+    pub fn merge(self, mut base: Self) -> Self {
+        base.styles.extend(self.styles);
+        base.messages.extend(self.messages);
+        base.devices.extend(self.devices);
+        Self {
+            include: Vec::new(),
+            defaults: self.defaults.or(&base.defaults),
+            styles: base.styles,
+            messages: base.messages,
+            devices: base.devices,
+        }
+    }
+
+    /// Render the messages into a payload for the badge
+    ///
+    /// Files referenced by the messages (e.g. `bitmap_file`) are loaded relative to the working directory.
+    /// Calendar messages are rendered in UTC, see [`Config::build_payload_at`] for local time.
+    pub fn build_payload(&self) -> Result<PayloadBuffer> {
+        render::build_payload(self, &self.messages, render::Now::utc(), &mut Vec::new())
+    }
+
+    /// Render the messages into a payload for the badge at `now`
+    ///
+    /// Calendar messages show the next event after `now`, in the offset of `now`.
+    #[cfg(feature = "time")]
+    pub fn build_payload_at(&self, now: time::OffsetDateTime) -> Result<PayloadBuffer> {
+        render::build_payload(self, &self.messages, render::Now::at(now), &mut Vec::new())
+    }
+
+    /// Render the messages of `device` with the defaults and style presets of this config
+    pub fn build_device_payload(&self, device: &DeviceConfig) -> Result<PayloadBuffer> {
+        render::build_payload(self, &device.messages, render::Now::utc(), &mut Vec::new())
+    }
+
+    /// Render the messages of `device` at `now`, see [`Config::build_payload_at`]
+    #[cfg(feature = "time")]
+    pub fn build_device_payload_at(
+        &self,
+        device: &DeviceConfig,
+        now: time::OffsetDateTime,
+    ) -> Result<PayloadBuffer> {
+        render::build_payload(
+            self,
+            &device.messages,
+            render::Now::at(now),
+            &mut Vec::new(),
+        )
+    }
+
+    /// Render the messages (or the messages of `device`) at `now`, see [`Config::build_payload_at`]
+    ///
+    /// Also returns the characters missing from the fonts, in the order they first appear.
+    /// They are shown as similar looking ones (e.g. `€` as `EUR`) or as `?`.
+    #[cfg(feature = "time")]
+    pub fn build_payload_with_substitutions(
+        &self,
+        device: Option<&DeviceConfig>,
+        now: time::OffsetDateTime,
+    ) -> Result<(PayloadBuffer, Vec<char>)> {
+        let messages = device.map_or(&self.messages, |device| &device.messages);
+        let mut substituted = Vec::new();
+        let payload =
+            render::build_payload(self, messages, render::Now::at(now), &mut substituted)?;
+        Ok((payload, substituted))
+    }
+
+    /// Interval after which the messages show new content (e.g. the weather) when rendered again
+    ///
+    /// `None` if the payload only depends on the config.
+    #[must_use]
+    pub fn refresh_interval(&self) -> Option<Duration> {
+        let devices = self.devices.iter().flat_map(|d| &d.messages);
+        self.messages
+            .iter()
+            .chain(devices)
+            .filter_map(|message| message.content.refresh_interval())
+            .min()
+    }
+
+    /// Set the options shared by all messages
+    pub fn defaults(mut self, defaults: StyleOptions) -> Self {
+        self.defaults = defaults;
+        self
+    }
+
+    /// Add a named style preset
+    pub fn style(mut self, name: impl Into<String>, options: StyleOptions) -> Self {
+        self.styles.insert(name.into(), options);
+        self
+    }
+
+    /// Append a message
+    pub fn message(mut self, message: Message) -> Self {
+        self.messages.push(message);
+        self
+    }
+
+    /// Append a device with its own messages
+    pub fn device(mut self, device: DeviceConfig) -> Self {
+        self.devices.push(device);
+        self
+    }
+}
+
+/// A badge with its own messages, for programming multiple badges in one run
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[must_use]
+pub struct DeviceConfig {
+    /// Bluetooth address of the badge (for BLE)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+
+    /// Serial number of the badge (for USB)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serial: Option<String>,
+
+    /// HID path of the badge (for USB), e.g. `/dev/hidraw3`, for badges without a unique serial number
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usb_path: Option<String>,
+
+    /// Position of the badge among the connected USB badges, counting from 0
+    ///
+    /// The order can change when the badges are plugged in again.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usb_index: Option<usize>,
+
+    #[serde(default, rename = "message", skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<Message>,
+}
+
+impl DeviceConfig {
+    /// Set the Bluetooth address of the badge
+    pub fn address(mut self, address: impl Into<String>) -> Self {
+        self.address = Some(address.into());
+        self
+    }
+
+    /// Set the USB serial number of the badge
+    pub fn serial(mut self, serial: impl Into<String>) -> Self {
+        self.serial = Some(serial.into());
+        self
+    }
+
+    /// Set the HID path of the badge, for USB badges without a unique serial number
+    pub fn usb_path(mut self, path: impl Into<String>) -> Self {
+        self.usb_path = Some(path.into());
+        self
+    }
+
+    /// Set the position of the badge among the connected USB badges
+    pub fn usb_index(mut self, index: usize) -> Self {
+        self.usb_index = Some(index);
+        self
+    }
+
+    /// Append a message
+    pub fn message(mut self, message: Message) -> Self {
+        self.messages.push(message);
+        self
+    }
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+/// Replace `${NAME}` with the value of the environment variable `NAME`, as returned by `lookup`
+///
+/// A literal `$` can be written as `$$`.
+fn interpolate(value: &str, lookup: &impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(index) = rest.find('$') {
+        result.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+        if let Some(tail) = rest.strip_prefix('$') {
+            result.push('$');
+            rest = tail;
+        } else if let Some(tail) = rest.strip_prefix('{') {
+            let end = tail
+                .find('}')
+                .with_context(|| format!("unterminated variable in {value:?}"))?;
+            let name = &tail[..end];
+            let var =
+                lookup(name).with_context(|| format!("environment variable not set: {name}"))?;
+            result.push_str(&var);
+            rest = &tail[end + 1..];
+        } else {
+            result.push('$');
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn interpolate_json(
+    value: &mut serde_json::Value,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> Result<()> {
+    match value {
+        serde_json::Value::String(s) => *s = interpolate(s, lookup)?,
+        serde_json::Value::Array(values) => values
+            .iter_mut()
+            .try_for_each(|value| interpolate_json(value, lookup))?,
+        serde_json::Value::Object(map) => map
+            .values_mut()
+            .try_for_each(|value| interpolate_json(value, lookup))?,
+        _ => {}
+    }
+    Ok(())
+}
+
+fn interpolate_toml(
+    value: &mut toml::Value,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> Result<()> {
+    match value {
+        toml::Value::String(s) => *s = interpolate(s, lookup)?,
+        toml::Value::Array(values) => values
+            .iter_mut()
+            .try_for_each(|value| interpolate_toml(value, lookup))?,
+        toml::Value::Table(table) => table
+            .iter_mut()
+            .try_for_each(|(_, value)| interpolate_toml(value, lookup))?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Style options shared by multiple messages
+///
+/// Used for the `[defaults]` section and the named presets in `[styles]`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[must_use]
+pub struct StyleOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blink: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub border: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<Speed>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<ModeOption>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font: Option<Font>,
+    /// Number of blank columns between the frames of fast mode (default 4)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_gap: Option<usize>,
+}
+
+impl StyleOptions {
+    /// Fill all options that are not set with the ones from `other`
+    pub fn or(self, other: &Self) -> Self {
+        Self {
+            blink: self.blink.or(other.blink),
+            border: self.border.or(other.border),
+            speed: self.speed.or(other.speed),
+            mode: self.mode.or_else(|| other.mode.clone()),
+            font: self.font.or(other.font),
+            frame_gap: self.frame_gap.or(other.frame_gap),
+        }
+    }
+
+    /// Enable blink mode
+    pub fn blink(mut self) -> Self {
+        self.blink = Some(true);
+        self
+    }
+
+    /// Show a border
+    pub fn border(mut self) -> Self {
+        self.border = Some(true);
+        self
+    }
+
+    /// Set the animation speed
+    pub fn speed(mut self, speed: Speed) -> Self {
+        self.speed = Some(speed);
+        self
+    }
+
+    /// Set the display mode, either a fixed [`Mode`] or [`ModeOption::Random`]
+    pub fn mode(mut self, mode: impl Into<ModeOption>) -> Self {
+        self.mode = Some(mode.into());
+        self
+    }
+
+    /// Set the font for text
+    pub fn font(mut self, font: Font) -> Self {
+        self.font = Some(font);
+        self
+    }
+
+    /// Set the number of blank columns between the frames of fast mode
+    pub fn frame_gap(mut self, gap: usize) -> Self {
+        self.frame_gap = Some(gap);
+        self
+    }
+}
+
+/// A single message with its content and display options
+#[derive(Serialize, Deserialize)]
+#[must_use]
+pub struct Message {
+    /// Name of a style preset from the `[styles]` section
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blink: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub border: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<Speed>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<ModeOption>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_gap: Option<usize>,
+
+    /// Pin the message to a slot (1 to 8) of the badge
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slot: Option<usize>,
+
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub pad_start: usize,
+
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub pad_end: usize,
+
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub offset: Offset,
+
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub invert: bool,
+
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub rotate_180: bool,
+
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub mirror: bool,
+
+    /// Split content wider than this number of 44 column screens across several slots
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub split_screens: Option<usize>,
+
+    #[serde(flatten)]
+    pub content: Content,
+}
+
+impl Message {
+    /// Create a message with the default options
+    pub fn new(content: Content) -> Self {
+        Self {
+            style: None,
+            blink: None,
+            border: None,
+            speed: None,
+            mode: None,
+            frame_gap: None,
+            slot: None,
+            pad_start: 0,
+            pad_end: 0,
+            offset: Offset::default(),
+            invert: false,
+            rotate_180: false,
+            mirror: false,
+            split_screens: None,
+            content,
+        }
+    }
+
+    /// Create a text message
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::new(Content::text(text))
+    }
+
+    /// Create a message from a bitstring (`_` = Off, `X` = On)
+    pub fn bitstring(bitstring: impl Into<String>) -> Self {
+        Self::new(Content::Bitstring {
+            bitstring: bitstring.into(),
+            bitstring_align: VerticalAlign::default(),
+            scale: None,
+        })
+    }
+
+    /// Select a style preset by name
+    pub fn style(mut self, name: impl Into<String>) -> Self {
+        self.style = Some(name.into());
+        self
+    }
+
+    /// Enable blink mode
+    pub fn blink(mut self) -> Self {
+        self.blink = Some(true);
+        self
+    }
+
+    /// Show a border
+    pub fn border(mut self) -> Self {
+        self.border = Some(true);
+        self
+    }
+
+    /// Set the animation speed
+    pub fn speed(mut self, speed: Speed) -> Self {
+        self.speed = Some(speed);
+        self
+    }
+
+    /// Set the display mode, either a fixed [`Mode`] or [`ModeOption::Random`]
+    pub fn mode(mut self, mode: impl Into<ModeOption>) -> Self {
+        self.mode = Some(mode.into());
+        self
+    }
+
+    /// Pin the message to a slot (1 to 8)
+    pub fn slot(mut self, slot: usize) -> Self {
+        self.slot = Some(slot);
+        self
+    }
+}
+
+/// Display mode of a message
+///
+/// Besides a fixed mode (`mode = "drop"`), a mode can be chosen at random every time
+/// the payload is built (`mode = "random"`), optionally from a list of modes
+/// (`mode = { random = ["drop", "curtain", "laser"] }`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ModeOption {
+    Fixed(Mode),
+    Random(RandomMode),
+    RandomFrom { random: Vec<Mode> },
+}
+
+/// The keyword `random`, which selects any mode except [`Mode::Fast`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RandomMode {
+    Random,
+}
+
+impl ModeOption {
+    /// Modes chosen by `mode = "random"`, fast mode shows frames and is left out
+    const RANDOM: [Mode; 8] = [
+        Mode::Left,
+        Mode::Right,
+        Mode::Up,
+        Mode::Down,
+        Mode::Center,
+        Mode::Drop,
+        Mode::Curtain,
+        Mode::Laser,
+    ];
+
+    /// Return the fixed mode or choose one of the random modes
+    pub fn choose(&self) -> Result<Mode> {
+        match self {
+            Self::Fixed(mode) => Ok(*mode),
+            Self::Random(RandomMode::Random) => {
+                Ok(Self::RANDOM[fastrand::usize(..Self::RANDOM.len())])
+            }
+            Self::RandomFrom { random } => {
+                anyhow::ensure!(!random.is_empty(), "no modes to choose from in random");
+                Ok(random[fastrand::usize(..random.len())])
+            }
+        }
+    }
+}
+
+impl From<Mode> for ModeOption {
+    fn from(mode: Mode) -> Self {
+        Self::Fixed(mode)
+    }
+}
+
+/// Number of pixels to move the content to the right (`x`) and down (`y`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Offset {
+    #[serde(default)]
+    pub x: i32,
+    #[serde(default)]
+    pub y: i32,
+}
+
+/// Content of a message
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields, untagged)]
+pub enum Content {
+    Text {
+        text: String,
+        #[serde(default, skip_serializing_if = "is_default")]
+        align: Align,
+        #[serde(default, skip_serializing_if = "is_default")]
+        wrap: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        font: Option<Font>,
+        #[serde(default, skip_serializing_if = "is_default")]
+        fit: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        effect: Option<Effect>,
+    },
+    Bitstring {
+        bitstring: String,
+        #[serde(default, skip_serializing_if = "is_default")]
+        bitstring_align: VerticalAlign,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        scale: Option<usize>,
+    },
+    Banner {
+        banner: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        banner_font: Option<PathBuf>,
+    },
+    Animation {
+        animation: Animation,
+    },
+    BitmapBase64 {
+        width: u32,
+        bitmap_base64: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        scale: Option<usize>,
+    },
+    BitmapFile {
+        width: u32,
+        bitmap_file: PathBuf,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        scale: Option<usize>,
+    },
+    Icon {
+        icon: Icon,
+    },
+    /// Segments laid out side by side, with `gap` blank columns in between
+    Parts {
+        parts: Vec<Content>,
+        #[serde(default, skip_serializing_if = "is_default")]
+        gap: u32,
+    },
+    /// Current weather, fetched every time the config is rendered
+    Weather {
+        weather: Weather,
+    },
+    /// Latest headlines of a feed, fetched again after its refresh interval
+    Feed {
+        feed: Feed,
+    },
+    /// Track currently played by a media player (Linux only)
+    NowPlaying {
+        now_playing: NowPlaying,
+    },
+    /// System stats of the host, read every time the config is rendered
+    Stats {
+        stats: Stats,
+    },
+    /// Next event of an iCalendar file, e.g. the next session at a conference
+    Calendar {
+        calendar: Calendar,
+    },
+    // TODO: implement png
+    // PngFile { png_file: PathBuf },
+}
+
+impl Content {
+    fn text(text: impl Into<String>) -> Self {
+        Self::Text {
+            text: text.into(),
+            align: Align::default(),
+            wrap: false,
+            font: None,
+            fit: false,
+            effect: None,
+        }
+    }
+
+    /// Text with every `{icon}` replaced by `icon`, laid out as parts
+    fn text_with_icon(text: &str, icon: Icon) -> Self {
+        let mut parts = Vec::new();
+        for (index, text) in text.split("{icon}").enumerate() {
+            if index > 0 {
+                parts.push(Self::Icon { icon });
+            }
+            if !text.is_empty() {
+                parts.push(Self::text(text));
+            }
+        }
+        Self::Parts { parts, gap: 1 }
+    }
+
+    /// Local file the content is read from, if any
+    fn file(&self) -> Option<&Path> {
+        match self {
+            Self::Banner { banner_font, .. } => banner_font.as_deref(),
+            Self::BitmapFile { bitmap_file, .. } => Some(bitmap_file),
+            Self::Calendar { calendar } => Some(&calendar.file),
+            Self::Parts { parts, .. } => parts.iter().find_map(Self::file),
+            _ => None,
+        }
+    }
+
+    fn refresh_interval(&self) -> Option<Duration> {
+        match self {
+            Self::Weather { .. } => Some(Weather::REFRESH),
+            Self::Feed { feed } => Some(feed.refresh_interval()),
+            Self::NowPlaying { .. } => Some(NowPlaying::REFRESH),
+            Self::Stats { .. } => Some(Stats::REFRESH),
+            Self::Calendar { .. } => Some(Calendar::REFRESH),
+            Self::Parts { parts, .. } => parts.iter().filter_map(Self::refresh_interval).min(),
+            _ => None,
+        }
+    }
+}
+
+/// Built-in icon, e.g. to put in front of text in a message with `parts`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Icon {
+    Heart,
+    Star,
+    Smile,
+    Check,
+    Arrow,
+    Sun,
+    Cloud,
+    Rain,
+    Snow,
+    Storm,
+    Fog,
+    Note,
+}
+
+/// Weather at a location, shown as text with `{temp}`, `{condition}` and `{icon}` replaced
+///
+/// Fetching the weather requires the `weather` feature.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Weather {
+    /// Name of the place, e.g. `Singapore`
+    pub location: String,
+    #[serde(default = "Weather::default_format")]
+    pub format: String,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub provider: WeatherProvider,
+    /// API key of the provider, required for [`WeatherProvider::OpenWeather`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+}
+
+impl Weather {
+    /// Interval after which the weather is shown again by long running commands
+    pub const REFRESH: Duration = Duration::from_mins(30);
+
+    fn default_format() -> String {
+        "{temp}\u{b0} {icon}".into()
+    }
+}
+
+/// Latest headlines of an RSS or Atom feed, scrolling one after another
+///
+/// Fetching the feed requires the `feed` feature.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Feed {
+    pub url: String,
+    /// Number of headlines shown
+    #[serde(default = "Feed::default_count")]
+    pub count: usize,
+    /// Text between two headlines
+    #[serde(default = "Feed::default_separator")]
+    pub separator: String,
+    /// Minutes until the feed is fetched again (at most a week), the headlines are cached in between
+    #[serde(
+        default = "Feed::default_refresh",
+        deserialize_with = "Feed::deserialize_refresh"
+    )]
+    pub refresh: u64,
+}
+
+impl Feed {
+    /// Longest refresh interval in minutes, one week
+    pub const MAX_REFRESH: u64 = 7 * 24 * 60;
+
+    fn default_count() -> usize {
+        5
+    }
+
+    fn default_separator() -> String {
+        " +++ ".into()
+    }
+
+    fn default_refresh() -> u64 {
+        15
+    }
+
+    fn deserialize_refresh<'de, D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<u64, D::Error> {
+        let refresh = u64::deserialize(deserializer)?;
+        if refresh > Self::MAX_REFRESH {
+            return Err(serde::de::Error::custom(format_args!(
+                "refresh must be at most {} minutes",
+                Self::MAX_REFRESH
+            )));
+        }
+        Ok(refresh)
+    }
+
+    /// Refresh interval, longer ones set in code are cut to [`Feed::MAX_REFRESH`]
+    fn refresh_interval(&self) -> Duration {
+        Duration::from_mins(self.refresh.min(Self::MAX_REFRESH))
+    }
+}
+
+/// Track currently played by a media player, read over MPRIS
+///
+/// Reading the track requires the `mpris` feature and only works on Linux.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NowPlaying {
+    /// Name of the player, e.g. `spotify` (default: the first player that is playing)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub player: Option<String>,
+    /// Text shown, `{artist}`, `{title}`, `{album}` and `{icon}` (a note) are replaced
+    #[serde(default = "NowPlaying::default_format")]
+    pub format: String,
+    /// Text shown when no track is playing
+    #[serde(default = "NowPlaying::default_idle")]
+    pub idle: String,
+}
+
+impl NowPlaying {
+    /// Interval in which long running commands check for a track change
+    pub const REFRESH: Duration = Duration::from_secs(5);
+
+    fn default_format() -> String {
+        "{icon} {artist} - {title}".into()
+    }
+
+    fn default_idle() -> String {
+        "{icon} Nothing playing".into()
+    }
+}
+
+/// System stats shown as text with `{cpu}`, `{mem}`, `{swap}`, `{disk}`, `{load}` and `{uptime}` replaced
+///
+/// Reading the stats requires the `stats` feature.
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Stats {
+    pub format: String,
+}
+
+impl Stats {
+    /// Interval after which the stats are shown again by long running commands
+    pub const REFRESH: Duration = Duration::from_secs(30);
+}
+
+/// Next event of an iCalendar (`.ics`) file, with `{title}`, `{time}`, `{date}` and `{location}` replaced
+///
+/// Showing the event requires the `time` feature.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Calendar {
+    /// Path of the calendar, relative to the working directory
+    pub file: PathBuf,
+    #[serde(default = "Calendar::default_format")]
+    pub format: String,
+    /// Text shown when there are no more events
+    #[serde(default = "Calendar::default_idle")]
+    pub idle: String,
+}
+
+impl Calendar {
+    /// Interval after which the next event is shown again by long running commands
+    pub const REFRESH: Duration = Duration::from_mins(1);
+
+    fn default_format() -> String {
+        "{time} {title}".into()
+    }
+
+    fn default_idle() -> String {
+        "No more events".into()
+    }
+}
+
+/// Online service the weather is fetched from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WeatherProvider {
+    /// [wttr.in](https://wttr.in), works without an API key
+    #[default]
+    Wttr,
+    /// [OpenWeather](https://openweathermap.org), requires an API key
+    OpenWeather,
+}
+
+/// Horizontal alignment of text within the visible display area
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// Vertical alignment of content that is lower than the display
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VerticalAlign {
+    Top,
+    #[default]
+    Center,
+    Bottom,
+}
+
+/// Text animation pre-rendered into `Mode::Fast` frames
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Animation {
+    pub kind: AnimationKind,
+    pub text: String,
+    #[serde(default = "Animation::default_frames")]
+    pub frames: usize,
+    #[serde(default = "Animation::default_font")]
+    pub font: Font,
+}
+
+impl Animation {
+    fn default_frames() -> usize {
+        8
+    }
+
+    fn default_font() -> Font {
+        Font::Mono5x7
+    }
+}
+
+/// Kind of a text animation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AnimationKind {
+    /// Move the whole text up and down
+    Bounce,
+    /// Move each character up and down, shifted in phase
+    Wave,
+}
+
+/// Effect applied to rendered text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Effect {
+    Outline,
+    Bold,
+    Shadow,
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{interpolate, Config, Content, Feed, Message, ModeOption, RandomMode};
+    use crate::protocol::Mode;
+
+    #[test]
+    fn interpolate_environment_variables() {
+        let lookup = |name: &str| (name == "NAME").then(|| String::from("Alice"));
+        assert_eq!(interpolate("Hi ${NAME}!", &lookup).unwrap(), "Hi Alice!");
+        assert_eq!(interpolate("$$5 or $1", &lookup).unwrap(), "$5 or $1");
+        assert!(interpolate("${UNSET}", &lookup).is_err());
+        assert!(interpolate("${NAME", &lookup).is_err());
+
+        // only config files are interpolated, untrusted configs keep the text as is
+        let source = "[[message]]\ntext = \"${BADGEMAGIC_TEST_UNSET}\"";
+        assert!(Config::parse_with(source, "toml", true).is_err());
+        let config = Config::parse(source, "toml").unwrap();
+        let text = |config: &Config| match &config.messages[0].content {
+            Content::Text { text, .. } => text.clone(),
+            _ => unreachable!(),
+        };
+        assert_eq!(text(&config), "${BADGEMAGIC_TEST_UNSET}");
+        let source = r#"{"message": [{"text": "$${NAME}"}]}"#;
+        let config = Config::parse_with(source, "json", true).unwrap();
+        assert_eq!(text(&config), "${NAME}");
+    }
+
+    #[test]
+    fn untrusted_configs_read_no_files() {
+        assert!(Config::parse("[[message]]\ntext = \"Hi\"", "toml").is_ok());
+        for source in [
+            "include = [\"other.toml\"]",
+            "[[message]]\nwidth = 8\nbitmap_file = \"/etc/passwd\"",
+            "[[message]]\nbanner = \"Hi\"\nbanner_font = \"/etc/passwd\"",
+            "[[message]]\nparts = [{ calendar = { file = \"/etc/passwd\" } }]",
+            "[[device]]\n[[device.message]]\nwidth = 8\nbitmap_file = \"/etc/passwd\"",
+        ] {
+            assert!(Config::parse(source, "toml").is_err(), "{source}");
+            assert!(Config::parse_with(source, "toml", true).is_ok(), "{source}");
+        }
+    }
+
+    #[test]
+    fn feed_refresh_is_bounded() {
+        let feed = |refresh: u64| {
+            let source =
+                format!("[[message]]\nfeed = {{ url = \"http://x\", refresh = {refresh} }}");
+            Config::parse(&source, "toml")
+        };
+        let config = feed(Feed::MAX_REFRESH).unwrap();
+        assert_eq!(
+            config.refresh_interval(),
+            Some(Duration::from_hours(7 * 24))
+        );
+        assert!(feed(Feed::MAX_REFRESH + 1).is_err());
+        assert!(feed(u64::MAX).is_err());
+    }
+
+    #[test]
+    fn serialize_round_trip() {
+        let config = Config::default()
+            .message(Message::text("Hello").blink())
+            .message(Message::bitstring("X_X"));
+        let toml = toml::to_string(&config).unwrap();
+        let config: Config = toml::from_str(&toml).unwrap();
+        assert_eq!(config.messages.len(), 2);
+        assert_eq!(config.messages[0].blink, Some(true));
+        let json = serde_json::to_string(&config).unwrap();
+        let config: Config = serde_json::from_str(&json).unwrap();
+        assert_eq!(config.messages.len(), 2);
+    }
+
+    #[test]
+    fn random_mode() {
+        let config: Config = toml::from_str(
+            r#"
+            [[message]]
+            text = "fixed"
+            mode = "drop"
+
+            [[message]]
+            text = "any"
+            mode = "random"
+
+            [[message]]
+            text = "allowed"
+            mode = { random = ["curtain", "laser"] }
+            "#,
+        )
+        .unwrap();
+        let modes: Vec<_> = config.messages.iter().map(|m| m.mode.clone()).collect();
+        assert_eq!(
+            modes,
+            [
+                Some(ModeOption::Fixed(Mode::Drop)),
+                Some(ModeOption::Random(RandomMode::Random)),
+                Some(ModeOption::RandomFrom {
+                    random: vec![Mode::Curtain, Mode::Laser]
+                }),
+            ]
+        );
+        for _ in 0..20 {
+            assert_ne!(modes[1].as_ref().unwrap().choose().unwrap(), Mode::Fast);
+            let mode = modes[2].as_ref().unwrap().choose().unwrap();
+            assert!(matches!(mode, Mode::Curtain | Mode::Laser));
+        }
+        assert_eq!(
+            toml::to_string(&config.messages[1]).unwrap(),
+            "mode = \"random\"\ntext = \"any\"\n"
+        );
+        assert!(ModeOption::RandomFrom { random: vec![] }.choose().is_err());
+    }
+
+    #[test]
+    fn split_long_text() {
+        let text = "Lorem ipsum dolor sit amet";
+        let mut payload = Config::default()
+            .message(Message::text(text))
+            .build_payload()
+            .unwrap();
+        assert_eq!(payload.num_messages(), 1);
+
+        let mut message = Message::text(text);
+        message.split_screens = Some(1);
+        let mut payload = Config::default().message(message).build_payload().unwrap();
+        assert!(payload.num_messages() > 1);
+        for index in 0..payload.num_messages() {
+            let (_, content) = payload.message(index).unwrap();
+            assert!(content.len() * 8 < 44 + 8);
+        }
+    }
+
+    #[test]
+    fn parts_message() {
+        let config: Config = toml::from_str(
+            r#"
+            [[message]]
+            parts = [{ icon = "heart" }, { text = "FOSSASIA" }]
+            gap = 2
+            "#,
+        )
+        .unwrap();
+        let mut payload = config.build_payload().unwrap();
+        assert_eq!(payload.num_messages(), 1);
+        let (_, content) = payload.message(0).unwrap();
+        assert!(content.len() * 8 >= 7 + 2 + 8 * 6);
+    }
+
+    #[test]
+    #[cfg(feature = "time")]
+    fn report_substituted_characters() {
+        let config: Config = toml::from_str(
+            r#"
+            [[message]]
+            parts = [{ text = "5\u20ac" }, { banner = "\u263a\u20ac" }]
+            "#,
+        )
+        .unwrap();
+        let now = time::OffsetDateTime::UNIX_EPOCH;
+        let (_, substituted) = config.build_payload_with_substitutions(None, now).unwrap();
+        assert_eq!(substituted, ['\u{20ac}', '\u{263a}']);
+    }
+
+    #[test]
+    fn animation_frame_gap() {
+        let width = |frame_gap: &str| {
+            let config: Config = toml::from_str(&format!(
+                r#"
+                [[message]]
+                animation = {{ kind = "bounce", text = "Hi", frames = 2 }}
+                {frame_gap}
+                "#
+            ))
+            .unwrap();
+            let payload = config.build_payload().unwrap();
+            payload.message(0).unwrap().1.len() * 8
+        };
+        assert_eq!(width(""), 2 * 48);
+        assert_eq!(width("frame_gap = 0"), 2 * 44);
+        assert_eq!(width("frame_gap = 12"), 2 * 56);
+    }
+
+    #[test]
+    fn frames_stay_on_their_grid() {
+        let columns = |options: &str| -> anyhow::Result<Vec<usize>> {
+            let config: Config = toml::from_str(&format!(
+                r#"
+                [[message]]
+                animation = {{ kind = "bounce", text = "I", frames = 2 }}
+                {options}
+                "#
+            ))
+            .unwrap();
+            let payload = config.build_payload()?;
+            let (_, content) = payload.message(0).unwrap();
+            Ok((0..content.len() * 8)
+                .filter(|x| {
+                    content[x / 8]
+                        .iter()
+                        .any(|row| row & (0x80 >> (x % 8)) != 0)
+                })
+                .collect())
+        };
+        let lit = columns("offset = { x = 3, y = 0 }").unwrap();
+        let unmoved: Vec<_> = columns("").unwrap().iter().map(|x| x + 3).collect();
+        assert_eq!(lit, unmoved);
+        // both frames show the same columns of their 44 visible columns
+        let (first, second): (Vec<_>, Vec<_>) = lit.into_iter().partition(|&x| x < 48);
+        assert!(!first.is_empty() && first.iter().all(|&x| x < 44));
+        assert_eq!(first, second.iter().map(|x| x - 48).collect::<Vec<_>>());
+
+        assert!(columns("pad_start = 4").is_err());
+        assert!(columns("mode = \"fast\"\npad_end = 4").is_err());
+    }
+}
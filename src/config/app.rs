@@ -0,0 +1,284 @@
+//! Saved badges of the Badge Magic app
+//!
+//! The Android app exports its saved badges as JSON.
+//! Every message stores its pixels as a list of hex strings with 11 bytes each,
+//! one byte per row of an 8 column wide block (the same layout as the payload).
+//! ```
+//! use badgemagic::config::app::Data;
+//! # fn main() -> anyhow::Result<()> {
+//! let data = Data::from_json(
+//!     r#"{"messages": [{
+//!     "text": ["003c4281a5a581bd423c00"],
+//!     "flash": false,
+//!     "marquee": true,
+//!     "speed": "0x30",
+//!     "mode": "0x04"
+//! }]}"#,
+//! )?;
+//! let config = data.into_config()?;
+//! let json = Data::from_config(&config)?.to_json()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt::Write as _;
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::{Mode, Speed};
+
+/// Number of bytes per 8 column wide block of pixels
+const BLOCK_SIZE: usize = 11;
+
+/// A badge as exported by the app
+#[derive(Serialize, Deserialize)]
+pub struct Data {
+    pub messages: Vec<Message>,
+}
+
+/// A message as exported by the app
+#[derive(Serialize, Deserialize)]
+pub struct Message {
+    /// Pixels as hex strings, 11 bytes per 8 column wide block
+    pub text: Vec<String>,
+
+    /// Blink mode
+    pub flash: bool,
+
+    /// Animated border
+    pub marquee: bool,
+
+    /// Speed as hex string (`0x00` to `0x70`)
+    pub speed: String,
+
+    /// Mode as hex string (`0x00` to `0x08`)
+    pub mode: String,
+}
+
+impl Data {
+    /// Parse the JSON exported by the app
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).context("parse app export")
+    }
+
+    /// Parse a shared badge
+    ///
+    /// Accepts the JSON export itself, the JSON encoded as base64 (standard or URL safe alphabet)
+    /// or a link with the encoded JSON in its `data` query parameter or fragment,
+    /// e.g. as scanned from a QR code.
+    pub fn from_share(share: &str) -> Result<Self> {
+        let share = share.trim();
+        if share.starts_with('{') {
+            return Self::from_json(share);
+        }
+
+        let encoded = match share.split_once(['?', '#']) {
+            Some((_, params)) => params
+                .split(['&', '#'])
+                .find_map(|param| param.strip_prefix("data="))
+                .or_else(|| share.split_once('#').map(|(_, fragment)| fragment))
+                .context("missing data in share link")?,
+            None => share,
+        };
+        let encoded = percent_decode(encoded)?;
+        let encoded = encoded.trim_end_matches('=');
+        let json = if encoded.contains(['-', '_']) {
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(encoded)
+        } else {
+            base64::engine::general_purpose::STANDARD_NO_PAD.decode(encoded)
+        }
+        .context("decode shared badge")?;
+        Self::from_json(std::str::from_utf8(&json).context("decode shared badge")?)
+    }
+
+    /// Render the messages of `config` into the format of the app
+    ///
+    /// Empty message slots are skipped, as the app does not support them.
+    pub fn from_config(config: &super::Config) -> Result<Self> {
+        let payload = config.build_payload()?;
+        let messages = (0..)
+            .map_while(|index| payload.message(index))
+            .filter(|(_, content)| !content.is_empty())
+            .map(|(style, content)| Message {
+                text: content.iter().map(|block| to_hex(block)).collect(),
+                flash: style.is_blink(),
+                marquee: style.is_border(),
+                speed: format!("0x{:02x}", u8::from(style.get_speed()) << 4),
+                mode: format!("0x{:02x}", u8::from(style.get_mode())),
+            })
+            .collect();
+        Ok(Self { messages })
+    }
+
+    /// Serialize the badge to the JSON format of the app
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).context("serialize app export")
+    }
+
+    /// Convert the badge into a config with one bitstring message per app message
+    pub fn into_config(self) -> Result<super::Config> {
+        let mut config = super::Config::default();
+        for (index, message) in self.messages.iter().enumerate() {
+            let message = message
+                .to_config_message()
+                .with_context(|| format!("message {}", index + 1))?;
+            config = config.message(message);
+        }
+        Ok(config)
+    }
+}
+
+impl Message {
+    fn to_config_message(&self) -> Result<super::Message> {
+        let mut data = Vec::new();
+        for block in &self.text {
+            data.extend(parse_hex(block)?);
+        }
+        anyhow::ensure!(
+            !data.is_empty() && data.len().is_multiple_of(BLOCK_SIZE),
+            "expected a multiple of {BLOCK_SIZE} bytes of pixel data, found {} bytes",
+            data.len(),
+        );
+
+        let bitstring = (0..BLOCK_SIZE)
+            .map(|y| {
+                data.chunks(BLOCK_SIZE)
+                    .flat_map(|block| (0..8).map(move |x| block[y] & (0x80 >> x) != 0))
+                    .map(|on| if on { 'X' } else { '_' })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let speed = parse_hex_byte(&self.speed).context("speed")?;
+        let speed = Speed::try_from(speed >> 4).context("invalid speed")?;
+        let mode = parse_hex_byte(&self.mode).context("mode")?;
+        let mode = Mode::try_from(mode).context("invalid mode")?;
+
+        let mut message = super::Message::bitstring(bitstring).speed(speed).mode(mode);
+        if self.flash {
+            message = message.blink();
+        }
+        if self.marquee {
+            message = message.border();
+        }
+        Ok(message)
+    }
+}
+
+fn parse_hex_byte(value: &str) -> Result<u8> {
+    let digits = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+    u8::from_str_radix(digits, 16).with_context(|| format!("invalid hex value: {value:?}"))
+}
+
+/// Decode `%XX` escapes, as used in links
+fn percent_decode(value: &str) -> Result<String> {
+    let mut result = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let digits = [bytes.next(), bytes.next()];
+            let [Some(high), Some(low)] = digits else {
+                anyhow::bail!("invalid escape in share link: {value:?}");
+            };
+            let digits = std::str::from_utf8(&[high, low])?.to_owned();
+            result.push(parse_hex_byte(&digits)?);
+        } else {
+            result.push(byte);
+        }
+    }
+    String::from_utf8(result).context("invalid share link")
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().fold(String::new(), |mut hex, byte| {
+        write!(hex, "{byte:02x}").unwrap();
+        hex
+    })
+}
+
+fn parse_hex(value: &str) -> Result<Vec<u8>> {
+    anyhow::ensure!(
+        value.len().is_multiple_of(2),
+        "odd number of hex digits: {value:?}"
+    );
+    (0..value.len())
+        .step_by(2)
+        .map(|i| {
+            let digits = value
+                .get(i..i + 2)
+                .with_context(|| format!("invalid hex string: {value:?}"))?;
+            u8::from_str_radix(digits, 16).with_context(|| format!("invalid hex string: {value:?}"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::Data;
+    use crate::{
+        config::{Config, Message},
+        protocol::Mode,
+    };
+
+    #[test]
+    fn import_pixels() {
+        let json = r#"{"messages": [{
+            "text": ["003c4281a5a581bd423c00", "0000000000ff0000000000"],
+            "flash": true,
+            "marquee": false,
+            "speed": "0x30",
+            "mode": "0x04"
+        }]}"#;
+        let config = Data::from_json(json).unwrap().into_config().unwrap();
+        let payload = config.build_payload().unwrap();
+        assert_eq!(
+            payload.as_bytes()[64..],
+            [
+                0x00, 0x3c, 0x42, 0x81, 0xa5, 0xa5, 0x81, 0xbd, 0x42, 0x3c, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00,
+            ]
+        );
+    }
+
+    #[test]
+    fn export_and_import() {
+        let config = Config::default()
+            .message(Message::bitstring("X_\n_X").mode(Mode::Center).blink())
+            .message(Message::text("Hi"));
+        let data = Data::from_config(&config).unwrap();
+        assert_eq!(data.messages.len(), 2);
+        assert_eq!(data.messages[0].text, ["0000000080400000000000"]);
+        assert_eq!(data.messages[0].mode, "0x04");
+        assert_eq!(data.messages[0].speed, "0x40");
+        assert!(data.messages[0].flash);
+
+        let json = data.to_json().unwrap();
+        let config = Data::from_json(&json).unwrap().into_config().unwrap();
+        assert_eq!(config.messages.len(), 2);
+    }
+
+    #[test]
+    fn parse_share_links() {
+        let json = r#"{"messages":[{"text":["0000000080400000000000"],"flash":false,"marquee":false,"speed":"0x00","mode":"0x00"}]}"#;
+        let base64 = "eyJtZXNzYWdlcyI6W3sidGV4dCI6WyIwMDAwMDAwMDgwNDAwMDAwMDAwMDAwIl0sImZsYXNoIjpmYWxzZSwibWFycXVlZSI6ZmFsc2UsInNwZWVkIjoiMHgwMCIsIm1vZGUiOiIweDAwIn1dfQ==";
+        for share in [
+            json.to_owned(),
+            base64.to_owned(),
+            format!(
+                "https://example.com/badge?v=1&data={}",
+                base64.replace('=', "%3D")
+            ),
+            format!("https://example.com/badge#{}", base64.trim_end_matches('=')),
+        ] {
+            let data = Data::from_share(&share).unwrap();
+            assert_eq!(data.messages[0].text, ["0000000080400000000000"]);
+        }
+        assert!(Data::from_share("https://example.com/badge?v=1").is_err());
+    }
+}
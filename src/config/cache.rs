@@ -0,0 +1,104 @@
+//! Hashes of the payloads last written to each badge
+//!
+//! Writing takes several seconds, so the CLI skips the upload if a badge already shows the payload.
+//! The timestamp in the header is ignored when comparing payloads.
+//! ```
+//! use badgemagic::{
+//!     config::cache::PayloadCache,
+//!     protocol::{PayloadBuffer, Style, Timestamp},
+//! };
+//!
+//! let mut cache = PayloadCache::default();
+//! let mut payload = PayloadBuffer::new();
+//! assert!(!cache.is_current("usb:0001", &payload));
+//! cache.update("usb:0001", &payload);
+//!
+//! payload.set_timestamp(Timestamp {
+//!     year: 99,
+//!     ..Timestamp::default()
+//! });
+//! assert!(cache.is_current("usb:0001", &payload));
+//! payload.add_message(Style::default(), 1);
+//! assert!(!cache.is_current("usb:0001", &payload));
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::{PayloadBuffer, Timestamp};
+
+/// Hashes of the payloads last written, by badge
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PayloadCache {
+    hashes: BTreeMap<String, String>,
+}
+
+impl PayloadCache {
+    /// Return the default location of the cache
+    ///
+    /// This is `badgemagic/payloads.toml` in `$XDG_CACHE_HOME` (defaults to `~/.cache`),
+    /// or in `%LOCALAPPDATA%` on Windows.
+    pub fn default_path() -> Result<PathBuf> {
+        let dir = if cfg!(windows) {
+            env::var_os("LOCALAPPDATA").map(PathBuf::from)
+        } else {
+            env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        };
+        let dir = dir.context("unable to determine the cache directory")?;
+        Ok(dir.join("badgemagic").join("payloads.toml"))
+    }
+
+    /// Load the cache from `path`, a missing file is an empty cache
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(source) => {
+                toml::from_str(&source).with_context(|| format!("parse cache: {}", path.display()))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).with_context(|| format!("load cache: {}", path.display())),
+        }
+    }
+
+    /// Save the cache to `path`, creating the parent directory
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("create directory: {}", dir.display()))?;
+        }
+        fs::write(path, toml::to_string_pretty(self)?)
+            .with_context(|| format!("save cache: {}", path.display()))
+    }
+
+    /// Return whether `payload` was the last payload written to `device`
+    #[must_use]
+    pub fn is_current(&self, device: &str, payload: &PayloadBuffer) -> bool {
+        self.hashes.get(device) == Some(&hash(payload))
+    }
+
+    /// Remember that `payload` was written to `device`
+    pub fn update(&mut self, device: &str, payload: &PayloadBuffer) {
+        self.hashes.insert(device.into(), hash(payload));
+    }
+}
+
+/// Hash the payload without its timestamp (64 bit FNV-1a, stable across builds)
+fn hash(payload: &PayloadBuffer) -> String {
+    let mut payload = payload.clone();
+    payload.set_timestamp(Timestamp::default());
+    let hash = payload
+        .as_bytes()
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("{hash:016x}")
+}
@@ -0,0 +1,277 @@
+//! Read the next event of an iCalendar file for `calendar` messages
+
+use std::fs;
+
+use anyhow::{Context, Result};
+use time::{Date, Duration, Month, OffsetDateTime, PrimitiveDateTime, Time};
+
+use super::{Calendar, Content};
+
+/// Event of a calendar, with the times in local time
+#[derive(Debug, PartialEq)]
+struct Event {
+    title: String,
+    location: String,
+    start: PrimitiveDateTime,
+    end: PrimitiveDateTime,
+    all_day: bool,
+}
+
+/// Show the next event that has not ended yet at `now`, or the idle text
+///
+/// Events without a time zone are in the offset of `now` and the times are shown in it.
+pub(super) fn render(calendar: &Calendar, now: OffsetDateTime) -> Result<Content> {
+    let source = fs::read_to_string(&calendar.file)
+        .with_context(|| format!("load calendar: {}", calendar.file.display()))?;
+    let events = parse(&source, now)
+        .with_context(|| format!("parse calendar: {}", calendar.file.display()))?;
+    let now = PrimitiveDateTime::new(now.date(), now.time());
+    let text = match next_event(&events, now) {
+        Some(event) => format(&calendar.format, event),
+        None => calendar.idle.clone(),
+    };
+    Ok(Content::text(text))
+}
+
+/// Return the event that starts first of the events that have not ended at `now`
+fn next_event(events: &[Event], now: PrimitiveDateTime) -> Option<&Event> {
+    events
+        .iter()
+        .filter(|event| event.end > now)
+        .min_by_key(|event| event.start)
+}
+
+fn format(format: &str, event: &Event) -> String {
+    let time = if event.all_day {
+        "all day".into()
+    } else {
+        let time = event.start.time();
+        format!("{:02}:{:02}", time.hour(), time.minute())
+    };
+    let date = event.start.date();
+    let date = format!(
+        "{}-{:02}-{:02}",
+        date.year(),
+        u8::from(date.month()),
+        date.day()
+    );
+    format
+        .replace("{title}", &event.title)
+        .replace("{location}", &event.location)
+        .replace("{time}", &time)
+        .replace("{date}", &date)
+}
+
+/// Parse the events of an iCalendar file, times in UTC are converted to the offset of `now`
+///
+/// Recurring events only appear at their first date,
+/// times with a `TZID` are assumed to be in local time.
+fn parse(source: &str, now: OffsetDateTime) -> Result<Vec<Event>> {
+    let mut events = Vec::new();
+    let mut event: Option<Properties> = None;
+    // components nested in the current event, e.g. alarms, have their own properties
+    let mut nested = 0_usize;
+    for line in unfold(source) {
+        let (name, value) = split_line(&line).with_context(|| format!("invalid line: {line:?}"))?;
+        let name = name.split_once(';').map_or(name, |(name, _params)| name);
+        match (name.to_ascii_uppercase().as_str(), &mut event) {
+            ("BEGIN", None) if value.eq_ignore_ascii_case("VEVENT") => {
+                event = Some(Properties::default());
+            }
+            ("BEGIN", Some(_)) => nested += 1,
+            ("END", Some(_)) if nested > 0 => nested -= 1,
+            ("END", Some(_)) if value.eq_ignore_ascii_case("VEVENT") => {
+                let properties = event.take().unwrap();
+                events.push(properties.into_event()?);
+            }
+            _ if nested > 0 => {}
+            ("SUMMARY", Some(event)) => event.title = unescape(value),
+            ("LOCATION", Some(event)) => event.location = unescape(value),
+            ("DTSTART", Some(event)) => event.start = Some(parse_time(value, now)?),
+            ("DTEND", Some(event)) => event.end = Some(parse_time(value, now)?),
+            _ => {}
+        }
+    }
+    Ok(events)
+}
+
+/// Properties of an event read so far
+#[derive(Default)]
+struct Properties {
+    title: String,
+    location: String,
+    start: Option<(PrimitiveDateTime, bool)>,
+    end: Option<(PrimitiveDateTime, bool)>,
+}
+
+impl Properties {
+    fn into_event(self) -> Result<Event> {
+        let (start, all_day) = self
+            .start
+            .with_context(|| format!("event without start: {:?}", self.title))?;
+        // without an end, events last until the next day or end at their start
+        let end = match self.end {
+            Some((end, _)) => end,
+            None if all_day => start + Duration::days(1),
+            None => start,
+        };
+        Ok(Event {
+            title: self.title,
+            location: self.location,
+            start,
+            end,
+            all_day,
+        })
+    }
+}
+
+/// Join lines continued on the next line (starting with a space or tab)
+fn unfold(source: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in source.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continued), Some(last)) => last.push_str(continued),
+            _ if line.trim().is_empty() => {}
+            _ => lines.push(line.into()),
+        }
+    }
+    lines
+}
+
+/// Split a content line into name (with parameters) and value, colons in quoted parameters are skipped
+fn split_line(line: &str) -> Option<(&str, &str)> {
+    let mut quoted = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ':' if !quoted => return Some((&line[..index], &line[index + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parse a date (`20250315`, all day) or a date and time (`20250315T100000`, with `Z` in UTC)
+///
+/// The date is returned with whether it is all day.
+fn parse_time(value: &str, now: OffsetDateTime) -> Result<(PrimitiveDateTime, bool)> {
+    let invalid = || format!("invalid date: {value:?}");
+    let Some((date, time)) = value.split_once('T') else {
+        let date = parse_date(value).with_context(invalid)?;
+        return Ok((PrimitiveDateTime::new(date, Time::MIDNIGHT), true));
+    };
+    let date = parse_date(date).with_context(invalid)?;
+    let (time, utc) = match time.strip_suffix('Z') {
+        Some(time) => (time, true),
+        None => (time, false),
+    };
+    let time = parse_clock(time).with_context(invalid)?;
+    let mut datetime = PrimitiveDateTime::new(date, time);
+    if utc {
+        let local = datetime.assume_utc().to_offset(now.offset());
+        datetime = PrimitiveDateTime::new(local.date(), local.time());
+    }
+    Ok((datetime, false))
+}
+
+/// Parse a date like `20250315`
+fn parse_date(value: &str) -> Option<Date> {
+    if value.len() != 8 || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let month = Month::try_from(value[4..6].parse::<u8>().ok()?).ok()?;
+    Date::from_calendar_date(value[..4].parse().ok()?, month, value[6..].parse().ok()?).ok()
+}
+
+/// Parse a time like `103000`
+fn parse_clock(value: &str) -> Option<Time> {
+    if value.len() != 6 || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (hour, minute, second) = (&value[..2], &value[2..4], &value[4..]);
+    Time::from_hms(
+        hour.parse().ok()?,
+        minute.parse().ok()?,
+        second.parse().ok()?,
+    )
+    .ok()
+}
+
+/// Replace the escaped characters of a text value, line breaks become spaces
+fn unescape(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => text.push(' '),
+            Some(c) => text.push(c),
+            None => {}
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use time::{Date, Month, PrimitiveDateTime, Time, UtcOffset};
+
+    use super::{format, next_event, parse};
+
+    fn local(day: u8, hour: u8, minute: u8) -> PrimitiveDateTime {
+        PrimitiveDateTime::new(
+            Date::from_calendar_date(2025, Month::March, day).unwrap(),
+            Time::from_hms(hour, minute, 0).unwrap(),
+        )
+    }
+
+    #[test]
+    fn next_session() {
+        let source = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+SUMMARY:Opening\r
+DTSTART;TZID=Asia/Singapore:20250315T090000\r
+DTEND;TZID=Asia/Singapore:20250315T093000\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+SUMMARY:Rust on LED badges\\, live\r
+LOCATION:Hall\r
+  A\r
+DTSTART:20250315T020000Z\r
+DTEND:20250315T024500Z\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+SUMMARY:Reminder\r
+DESCRIPTION:Talk starts soon\r
+DTSTART:20250315T013000Z\r
+TRIGGER:-PT15M\r
+END:VALARM\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+SUMMARY:Hackathon\r
+DTSTART;VALUE=DATE:20250316\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        let offset = UtcOffset::from_hms(8, 0, 0).unwrap();
+        let events = parse(source, local(15, 9, 45).assume_offset(offset)).unwrap();
+        assert_eq!(events.len(), 3);
+
+        let event = next_event(&events, local(15, 9, 45)).unwrap();
+        assert_eq!(
+            format("{time} {title} ({location}, {date})", event),
+            "10:00 Rust on LED badges, live (Hall A, 2025-03-15)"
+        );
+        let event = next_event(&events, local(15, 11, 0)).unwrap();
+        assert_eq!(format("{time} {title}", event), "all day Hackathon");
+        assert!(next_event(&events, local(17, 0, 0)).is_none());
+        assert!(parse(
+            "BEGIN:VEVENT\nDTSTART:2025\nEND:VEVENT",
+            local(15, 0, 0).assume_utc()
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,132 @@
+//! Fetch the headlines of RSS and Atom feeds for `feed` messages
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+
+#[cfg(feature = "feed")]
+use super::http::get;
+use super::{Content, Feed};
+
+/// Headlines by URL, with the time they were fetched
+type Cache = HashMap<String, (Instant, Vec<String>)>;
+
+/// Headlines fetched by this process
+///
+/// Long running commands render the config again and again, the feed is only fetched after its refresh interval.
+static CACHE: Mutex<Option<Cache>> = Mutex::new(None);
+
+/// Fetch the headlines (or take them from the cache) and join them into one text
+pub(super) fn render(feed: &Feed) -> Result<Content> {
+    anyhow::ensure!(feed.count > 0, "count must be at least 1");
+    anyhow::ensure!(feed.refresh > 0, "refresh must be at least 1 minute");
+    let headlines = headlines(&feed.url, feed.refresh_interval())?;
+    anyhow::ensure!(!headlines.is_empty(), "feed has no headlines: {}", feed.url);
+    let text = headlines
+        .iter()
+        .take(feed.count)
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(&feed.separator);
+    Ok(Content::text(text))
+}
+
+/// Return the cached headlines of `url`, fetching them again if they are older than `refresh`
+///
+/// If fetching fails, outdated headlines are shown until the next attempt.
+fn headlines(url: &str, refresh: Duration) -> Result<Vec<String>> {
+    let cached = CACHE
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .get(url)
+        .cloned();
+    if let Some((fetched, headlines)) = &cached {
+        if fetched.elapsed() < refresh {
+            return Ok(headlines.clone());
+        }
+    }
+    // fetched without holding the lock, other feeds must not wait for it
+    let result = fetch(url).with_context(|| format!("fetch feed: {url}"));
+    let headlines = match result {
+        Ok(headlines) => headlines,
+        Err(err) => {
+            let (_, headlines) = cached.ok_or(err)?;
+            event!(tracing::Level::WARN, url, "showing outdated headlines");
+            headlines
+        }
+    };
+    CACHE
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(url.into(), (Instant::now(), headlines.clone()));
+    Ok(headlines)
+}
+
+#[cfg(feature = "feed")]
+fn fetch(url: &str) -> Result<Vec<String>> {
+    parse(&get(url, &[])?)
+}
+
+#[cfg(not(feature = "feed"))]
+fn fetch(_url: &str) -> Result<Vec<String>> {
+    anyhow::bail!("badgemagic was built without the `feed` feature")
+}
+
+/// Parse the titles of the items of an RSS feed or the entries of an Atom feed
+#[cfg(feature = "feed")]
+fn parse(xml: &str) -> Result<Vec<String>> {
+    let options = roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..roxmltree::ParsingOptions::default()
+    };
+    let document = roxmltree::Document::parse_with_options(xml, options).context("parse feed")?;
+    let headlines = document
+        .descendants()
+        .filter(|node| matches!(node.tag_name().name(), "item" | "entry"))
+        .filter_map(|item| {
+            let title = item
+                .children()
+                .find(|node| node.tag_name().name() == "title")?;
+            // the title may be split into text and CDATA nodes
+            let text: String = title
+                .descendants()
+                .filter(roxmltree::Node::is_text)
+                .filter_map(|node| node.text())
+                .collect();
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            (!text.is_empty()).then_some(text)
+        })
+        .collect();
+    Ok(headlines)
+}
+
+#[cfg(all(test, feature = "feed"))]
+mod test {
+    use super::parse;
+
+    #[test]
+    fn parse_rss_and_atom() {
+        let rss = r#"<?xml version="1.0"?>
+            <rss version="2.0"><channel>
+              <title>News</title>
+              <item><title>First &amp; foremost</title></item>
+              <item><title><![CDATA[Second
+                headline]]></title></item>
+              <item><description>No title</description></item>
+            </channel></rss>"#;
+        assert_eq!(parse(rss).unwrap(), ["First & foremost", "Second headline"]);
+
+        let atom = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+              <title>Blog</title>
+              <entry><title type="text">Release 1.0</title></entry>
+            </feed>"#;
+        assert_eq!(parse(atom).unwrap(), ["Release 1.0"]);
+        assert!(parse("<rss>").is_err());
+    }
+}
@@ -0,0 +1,17 @@
+//! HTTP requests for messages showing online content
+
+use std::time::Duration;
+
+use anyhow::Result;
+
+/// Time after which a request is aborted
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Fetch `url` with the `query` parameters and return the body
+pub(super) fn get(url: &str, query: &[(&str, &str)]) -> Result<String> {
+    let mut request = ureq::get(url).timeout(TIMEOUT);
+    for (name, value) in query {
+        request = request.query(name, value);
+    }
+    Ok(request.call()?.into_string()?)
+}
@@ -0,0 +1,107 @@
+//! Read the track of a media player for `now_playing` messages
+
+use anyhow::Result;
+
+use super::{Content, Icon, NowPlaying};
+
+/// Track played by a media player
+#[cfg_attr(not(all(feature = "mpris", target_os = "linux")), allow(dead_code))]
+struct Track {
+    artist: String,
+    title: String,
+    album: String,
+}
+
+/// Read the current track and lay out the format (or the idle text) with a note as icon
+pub(super) fn render(now_playing: &NowPlaying) -> Result<Content> {
+    let text = match current_track(now_playing.player.as_deref())? {
+        Some(track) => format(&now_playing.format, &track),
+        None => now_playing.idle.clone(),
+    };
+    Ok(Content::text_with_icon(&text, Icon::Note))
+}
+
+fn format(format: &str, track: &Track) -> String {
+    format
+        .replace("{artist}", &track.artist)
+        .replace("{title}", &track.title)
+        .replace("{album}", &track.album)
+}
+
+/// Return the track of `player` (or of the first player) that is playing
+#[cfg(all(feature = "mpris", target_os = "linux"))]
+fn current_track(player: Option<&str>) -> Result<Option<Track>> {
+    use std::time::Duration;
+
+    use anyhow::Context;
+    use dbus::{
+        arg::{prop_cast, PropMap},
+        blocking::{stdintf::org_freedesktop_dbus::Properties as _, Connection},
+    };
+
+    const TIMEOUT: Duration = Duration::from_secs(2);
+    const PREFIX: &str = "org.mpris.MediaPlayer2.";
+    const PLAYER: &str = "org.mpris.MediaPlayer2.Player";
+
+    let connection = Connection::new_session().context("connect to the D-Bus session bus")?;
+    let (names,): (Vec<String>,) = connection
+        .with_proxy("org.freedesktop.DBus", "/org/freedesktop/DBus", TIMEOUT)
+        .method_call("org.freedesktop.DBus", "ListNames", ())
+        .context("list media players")?;
+    for name in &names {
+        let Some(instance) = name.strip_prefix(PREFIX) else {
+            continue;
+        };
+        // additional instances are called e.g. `vlc.instance1234`
+        if player.is_some_and(|player| instance.split('.').next() != Some(player)) {
+            continue;
+        }
+        let proxy = connection.with_proxy(name.as_str(), "/org/mpris/MediaPlayer2", TIMEOUT);
+        let status: String = proxy
+            .get(PLAYER, "PlaybackStatus")
+            .with_context(|| format!("read playback status of {instance}"))?;
+        if status != "Playing" {
+            continue;
+        }
+        let metadata: PropMap = proxy
+            .get(PLAYER, "Metadata")
+            .with_context(|| format!("read track of {instance}"))?;
+        let text = |key: &str| {
+            prop_cast::<String>(&metadata, key)
+                .cloned()
+                .unwrap_or_default()
+        };
+        let artist = prop_cast::<Vec<String>>(&metadata, "xesam:artist")
+            .map(|artists| artists.join(", "))
+            .unwrap_or_default();
+        return Ok(Some(Track {
+            artist,
+            title: text("xesam:title"),
+            album: text("xesam:album"),
+        }));
+    }
+    Ok(None)
+}
+
+#[cfg(not(all(feature = "mpris", target_os = "linux")))]
+fn current_track(_player: Option<&str>) -> Result<Option<Track>> {
+    anyhow::bail!("showing the current track requires the `mpris` feature on Linux")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format, Track};
+
+    #[test]
+    fn format_track() {
+        let track = Track {
+            artist: "Daft Punk".into(),
+            title: "Digital Love".into(),
+            album: "Discovery".into(),
+        };
+        assert_eq!(
+            format("{icon} {artist} - {title} ({album})", &track),
+            "{icon} Daft Punk - Digital Love (Discovery)"
+        );
+    }
+}
@@ -0,0 +1,204 @@
+//! Badges saved with a nickname
+//!
+//! The registry is stored in `~/.config/badgemagic/devices.toml`,
+//! with one table per badge and named groups of badges:
+//! ```toml
+//! [device.alice]
+//! transport = "ble"
+//! address = "AA:BB:CC:DD:EE:FF"
+//!
+//! [device.desk]
+//! transport = "usb"
+//! serial = "0001"
+//!
+//! [group]
+//! frontdesk = ["alice", "desk"]
+//! ```
+//! ```
+//! use badgemagic::config::registry::{Device, Registry};
+//! # fn main() -> anyhow::Result<()> {
+//! let mut registry =
+//!     Registry::parse("[device.alice]\ntransport = \"ble\"\naddress = \"AA:BB:CC:DD:EE:FF\"")?;
+//! registry.add(
+//!     "desk",
+//!     Device::Usb {
+//!         serial: "0001".into(),
+//!     },
+//! )?;
+//! registry.set_group("frontdesk", vec!["alice".into(), "desk".into()])?;
+//! registry.rename("alice", "bob")?;
+//! assert!(matches!(registry.get("bob")?, Device::Ble { .. }));
+//! assert_eq!(registry.group("frontdesk")?, ["bob", "desk"]);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// A badge and how to reach it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "transport", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Device {
+    /// A badge connected over USB
+    Usb {
+        /// Serial number of the badge
+        serial: String,
+    },
+
+    /// A badge connected over Bluetooth Low Energy
+    Ble {
+        /// Bluetooth address of the badge
+        address: String,
+    },
+}
+
+/// Nicknames of saved badges and groups of them
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Registry {
+    #[serde(default, rename = "device", skip_serializing_if = "BTreeMap::is_empty")]
+    devices: BTreeMap<String, Device>,
+
+    #[serde(default, rename = "group", skip_serializing_if = "BTreeMap::is_empty")]
+    groups: BTreeMap<String, Vec<String>>,
+}
+
+impl Registry {
+    /// Return the default location of the registry
+    ///
+    /// This is `badgemagic/devices.toml` in `$XDG_CONFIG_HOME` (defaults to `~/.config`),
+    /// or in `%APPDATA%` on Windows.
+    pub fn default_path() -> Result<PathBuf> {
+        let dir = if cfg!(windows) {
+            env::var_os("APPDATA").map(PathBuf::from)
+        } else {
+            env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        };
+        let dir = dir.context("unable to determine the config directory")?;
+        Ok(dir.join("badgemagic").join("devices.toml"))
+    }
+
+    /// Load the registry from `path`, a missing file is an empty registry
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(source) => {
+                Self::parse(&source).with_context(|| format!("parse registry: {}", path.display()))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).with_context(|| format!("load registry: {}", path.display())),
+        }
+    }
+
+    /// Save the registry to `path`, creating the parent directory
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("create directory: {}", dir.display()))?;
+        }
+        fs::write(path, toml::to_string_pretty(self)?)
+            .with_context(|| format!("save registry: {}", path.display()))
+    }
+
+    /// Parse a registry in TOML format
+    pub fn parse(source: &str) -> Result<Self> {
+        Ok(toml::from_str(source)?)
+    }
+
+    /// Return the badge called `name`
+    pub fn get(&self, name: &str) -> Result<&Device> {
+        self.devices
+            .get(name)
+            .with_context(|| format!("unknown device: {name}"))
+    }
+
+    /// Save a badge as `name`
+    pub fn add(&mut self, name: impl Into<String>, device: Device) -> Result<()> {
+        let name = name.into();
+        anyhow::ensure!(
+            !self.devices.contains_key(&name),
+            "device already exists: {name}"
+        );
+        self.devices.insert(name, device);
+        Ok(())
+    }
+
+    /// Change the nickname of a badge, its groups are updated
+    pub fn rename(&mut self, name: &str, new_name: impl Into<String>) -> Result<()> {
+        let new_name = new_name.into();
+        anyhow::ensure!(
+            !self.devices.contains_key(&new_name),
+            "device already exists: {new_name}"
+        );
+        let device = self
+            .devices
+            .remove(name)
+            .with_context(|| format!("unknown device: {name}"))?;
+        for members in self.groups.values_mut() {
+            for member in members.iter_mut().filter(|member| *member == name) {
+                member.clone_from(&new_name);
+            }
+        }
+        self.devices.insert(new_name, device);
+        Ok(())
+    }
+
+    /// Remove the badge called `name`, it is removed from its groups as well
+    pub fn remove(&mut self, name: &str) -> Result<Device> {
+        let device = self
+            .devices
+            .remove(name)
+            .with_context(|| format!("unknown device: {name}"))?;
+        for members in self.groups.values_mut() {
+            members.retain(|member| member != name);
+        }
+        Ok(device)
+    }
+
+    /// Return the names of the badges in the group `name`
+    pub fn group(&self, name: &str) -> Result<&[String]> {
+        self.groups
+            .get(name)
+            .map(Vec::as_slice)
+            .with_context(|| format!("unknown group: {name}"))
+    }
+
+    /// Create or replace the group `name`, all members have to be saved badges
+    pub fn set_group(&mut self, name: impl Into<String>, members: Vec<String>) -> Result<()> {
+        for member in &members {
+            self.get(member)?;
+        }
+        self.groups.insert(name.into(), members);
+        Ok(())
+    }
+
+    /// Remove the group `name`, its badges are kept
+    pub fn remove_group(&mut self, name: &str) -> Result<()> {
+        self.groups
+            .remove(name)
+            .with_context(|| format!("unknown group: {name}"))?;
+        Ok(())
+    }
+
+    /// Iterate over the badges ordered by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Device)> {
+        self.devices
+            .iter()
+            .map(|(name, device)| (name.as_str(), device))
+    }
+
+    /// Iterate over the groups ordered by name
+    pub fn groups(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.groups
+            .iter()
+            .map(|(name, members)| (name.as_str(), members.as_slice()))
+    }
+}
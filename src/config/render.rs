@@ -0,0 +1,1217 @@
+//! Render the messages of a config into a payload
+
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use embedded_graphics::{
+    draw_target::DrawTargetExt,
+    geometry::{Dimensions, Point},
+    image::{Image, ImageRawLE},
+    mono_font::{iso_8859_1::FONT_5X7, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    text::{Baseline, Text},
+    Drawable,
+};
+
+#[cfg(feature = "time")]
+use super::calendar;
+use super::{
+    feed, now_playing, stats, weather, Align, Animation, AnimationKind, Config, Content, Effect,
+    Font, Icon, Message, ModeOption, StyleOptions, VerticalAlign,
+};
+use crate::{
+    protocol::{self, Mode, PayloadBuffer, Style},
+    util::{DrawableEffectExt, DrawableLayoutExt, Framebuffer},
+};
+
+/// Number of columns visible on the display at once, in the coordinates of embedded-graphics
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+const DISPLAY_WIDTH: i32 = protocol::DISPLAY_WIDTH as i32;
+
+/// Default number of blank columns between two frames in `Mode::Fast`
+const DEFAULT_FRAME_GAP: i32 = 4;
+
+/// Time the messages are rendered at, used by calendar messages (with the `time` feature)
+#[derive(Clone, Copy)]
+pub(super) struct Now {
+    #[cfg(feature = "time")]
+    time: time::OffsetDateTime,
+}
+
+impl Now {
+    /// Render at the current time in UTC
+    pub(super) fn utc() -> Self {
+        Self {
+            #[cfg(feature = "time")]
+            time: time::OffsetDateTime::now_utc(),
+        }
+    }
+
+    #[cfg(feature = "time")]
+    pub(super) fn at(time: time::OffsetDateTime) -> Self {
+        Self { time }
+    }
+}
+
+/// Return the options of `message`, filled with its style preset and the defaults of `config`
+fn style_options(config: &Config, message: &Message) -> Result<StyleOptions> {
+    let preset = match &message.style {
+        Some(name) => config
+            .styles
+            .get(name)
+            .with_context(|| format!("unknown style preset: {name:?}"))?,
+        None => &StyleOptions::default(),
+    };
+    Ok(StyleOptions {
+        blink: message.blink,
+        border: message.border,
+        speed: message.speed,
+        mode: message.mode.clone(),
+        font: None,
+        frame_gap: message.frame_gap,
+    }
+    .or(preset)
+    .or(&config.defaults))
+}
+
+/// Render `messages` at `now` with the defaults and style presets of `config`
+///
+/// Characters missing from the fonts are replaced, see [`transliterate`], and added to `substituted`.
+pub(super) fn build_payload(
+    config: &Config,
+    messages: &[Message],
+    now: Now,
+    substituted: &mut Vec<char>,
+) -> Result<PayloadBuffer> {
+    let mut rendered = Vec::new();
+    for message in messages {
+        let options = style_options(config, message)?;
+        let mut style = Style::default();
+        if options.blink.unwrap_or_default() {
+            style = style.blink();
+        }
+        if options.border.unwrap_or_default() {
+            style = style.border();
+        }
+        let rotate_180 = message.rotate_180;
+        let mirror = message.mirror;
+        let chosen_mode = options
+            .mode
+            .as_ref()
+            .map_or(Ok(Mode::default()), ModeOption::choose)?;
+        // scroll in the direction the viewer expects
+        let mode = match chosen_mode {
+            Mode::Left if rotate_180 != mirror => Mode::Right,
+            Mode::Right if rotate_180 != mirror => Mode::Left,
+            Mode::Up if rotate_180 => Mode::Down,
+            Mode::Down if rotate_180 => Mode::Up,
+            mode => mode,
+        };
+        style = style.speed(options.speed.unwrap_or_default()).mode(mode);
+        if let Content::Animation { .. } = message.content {
+            style = style.mode(Mode::Fast);
+        }
+
+        let split_width = split_width(message, mode)?;
+        let frame_width = DISPLAY_WIDTH + frame_gap(&options)?;
+        let frames = shows_frames(message, mode);
+        // padding would move the frames off their grid
+        anyhow::ensure!(
+            !frames || (message.pad_start == 0 && message.pad_end == 0),
+            "pad_start and pad_end are not supported in fast mode and for animations",
+        );
+
+        let mut parts = Vec::new();
+        for mut bitmap in render_content(&message.content, &options, mode, now, substituted)? {
+            bitmap.offset_vertical(message.offset.y);
+            if frames {
+                bitmap.offset_frames(frame_width, DISPLAY_WIDTH, message.offset.x);
+            } else {
+                bitmap.offset_horizontal(message.offset.x);
+                bitmap.pad(message.pad_start, message.pad_end);
+            }
+            if mode == Mode::Fast {
+                // transform every frame in place, to keep the frames in order
+                if rotate_180 {
+                    bitmap.flip_vertical();
+                }
+                if rotate_180 != mirror {
+                    bitmap.mirror_frames(frame_width, DISPLAY_WIDTH);
+                }
+            }
+            let mut bitmaps = match split_width {
+                Some(width) => bitmap.split_columns(width),
+                None => vec![bitmap],
+            };
+            if chosen_mode == Mode::Right {
+                // content scrolling to the right shows its end first
+                bitmaps.reverse();
+            }
+            parts.extend(bitmaps.into_iter().map(|bitmap| Slot {
+                style,
+                bitmap,
+                invert: message.invert,
+                rotate_180: rotate_180 && mode != Mode::Fast,
+                mirror: mirror && mode != Mode::Fast,
+            }));
+        }
+        rendered.push((message.slot, parts));
+    }
+
+    let slots = assign_slots(rendered)?;
+
+    let mut payload = PayloadBuffer::new();
+    let used = slots.iter().rposition(Option::is_some).map_or(0, |i| i + 1);
+    for slot in slots.into_iter().take(used) {
+        let Some(slot) = slot else {
+            // leave the slot empty
+            payload.add_message(Style::default(), 0);
+            continue;
+        };
+        let mut bitmap = slot.bitmap;
+        // only the content is transformed, the padding to a multiple of 8 columns stays dark
+        // at the end, as the badge shows it after the content
+        if slot.invert {
+            bitmap.invert();
+        }
+        if slot.rotate_180 {
+            bitmap.flip_vertical();
+        }
+        if slot.rotate_180 != slot.mirror {
+            bitmap.mirror();
+        }
+        let mut buffer = payload.add_message(slot.style, bitmap.width.div_ceil(8));
+        bitmap.blit_to(&mut buffer, Point::zero());
+    }
+
+    Ok(payload)
+}
+
+/// Return the number of blank columns between two frames in `Mode::Fast`
+fn frame_gap(options: &StyleOptions) -> Result<i32> {
+    options.frame_gap.map_or(Ok(DEFAULT_FRAME_GAP), |gap| {
+        i32::try_from(gap).context("frame_gap is too large")
+    })
+}
+
+/// Return whether `message` shows frames of the display width, i.e. in fast mode or as animation
+fn shows_frames(message: &Message, mode: Mode) -> bool {
+    mode == Mode::Fast || matches!(message.content, Content::Animation { .. })
+}
+
+/// Return the maximum width of a slot if `message` is split across several slots
+///
+/// Animations and fast mode show frames, which are never split.
+fn split_width(message: &Message, mode: Mode) -> Result<Option<usize>> {
+    Ok(match message.split_screens {
+        Some(0) => anyhow::bail!("split_screens must be at least 1"),
+        Some(screens) if !shows_frames(message, mode) => {
+            Some(screens * usize::try_from(DISPLAY_WIDTH).unwrap())
+        }
+        _ => None,
+    })
+}
+
+/// Assign the rendered messages to the 8 message slots of the badge
+///
+/// Pinned messages are placed first, the remaining messages fill the free slots in order.
+fn assign_slots(rendered: Vec<(Option<usize>, Vec<Slot>)>) -> Result<Vec<Option<Slot>>> {
+    let mut slots: Vec<Option<Slot>> = (0..8).map(|_| None).collect();
+    let (pinned, unpinned): (Vec<_>, Vec<_>) =
+        rendered.into_iter().partition(|(slot, _)| slot.is_some());
+    for (slot, parts) in pinned {
+        let slot = slot.unwrap();
+        anyhow::ensure!(
+            (1..=8).contains(&slot),
+            "slot must be between 1 and 8, found {slot}",
+        );
+        anyhow::ensure!(
+            slot - 1 + parts.len() <= 8,
+            "message pinned to slot {slot} does not fit into the 8 available message slots",
+        );
+        for (index, part) in (slot - 1..).zip(parts) {
+            anyhow::ensure!(
+                slots[index].is_none(),
+                "slot {} is used by multiple messages",
+                index + 1,
+            );
+            slots[index] = Some(part);
+        }
+    }
+    for part in unpinned.into_iter().flat_map(|(_, parts)| parts) {
+        let free = slots
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .context("messages do not fit into the 8 available message slots")?;
+        *free = Some(part);
+    }
+    Ok(slots)
+}
+
+/// A rendered message, ready to be written to a message slot of the badge
+struct Slot {
+    style: Style,
+    bitmap: Framebuffer,
+    invert: bool,
+    rotate_180: bool,
+    mirror: bool,
+}
+
+/// Render the content into one bitmap per message, aligned for the display `mode`
+fn render_content(
+    content: &Content,
+    options: &StyleOptions,
+    mode: Mode,
+    now: Now,
+    substituted: &mut Vec<char>,
+) -> Result<Vec<Framebuffer>> {
+    Ok(match content {
+        Content::Text {
+            text,
+            align,
+            wrap,
+            font,
+            fit,
+            effect,
+        } => {
+            let text = transliterate(text, substituted);
+            let font = font.or(options.font).unwrap_or_default();
+            let font = if *fit {
+                *Font::ALL
+                    .iter()
+                    .find(|font| text_width(&text, **font) <= DISPLAY_WIDTH)
+                    .with_context(|| format!("text does not fit on the display: {text:?}"))?
+            } else {
+                font
+            };
+            let lines = if *wrap {
+                wrap_text(&text, |line| text_width(line, font) <= DISPLAY_WIDTH)
+            } else {
+                vec![text]
+            };
+            lines
+                .iter()
+                .map(|line| render_text(line, font, *effect, *align, mode))
+                .collect()
+        }
+        Content::Bitstring {
+            bitstring,
+            bitstring_align,
+            scale,
+        } => {
+            let scale = scale.unwrap_or(1);
+            anyhow::ensure!(scale > 0, "scale must be at least 1");
+            let mut bitmap = parse_bitstring(bitstring, scale)?.scale(scale);
+            bitmap.align_vertically(11, *bitstring_align);
+            vec![bitmap]
+        }
+        Content::Banner {
+            banner,
+            banner_font,
+        } => {
+            let font = load_banner_font(banner_font.as_deref())?;
+            let bitmap = render_banner(&font, &transliterate(banner, substituted));
+            vec![render_aligned(&bitmap, Align::Left, mode)]
+        }
+        Content::Animation { animation } => {
+            vec![render_animation(
+                animation,
+                frame_gap(options)?,
+                substituted,
+            )?]
+        }
+        Content::BitmapBase64 {
+            width,
+            bitmap_base64: bitmap,
+            scale,
+        } => {
+            vec![render_image(&decode_base64(bitmap)?, *width, *scale)?]
+        }
+        Content::BitmapFile {
+            width,
+            bitmap_file,
+            scale,
+        } => {
+            let data = fs::read(bitmap_file).context("load bitmap")?;
+            vec![render_image(&data, *width, *scale)?]
+        }
+        Content::Icon { icon } => vec![render_icon(*icon)],
+        Content::Parts { parts, gap } => {
+            vec![render_parts(parts, *gap, options, mode, now, substituted)?]
+        }
+        Content::Weather { weather } => {
+            render_content(&weather::render(weather)?, options, mode, now, substituted)?
+        }
+        Content::Feed { feed } => {
+            render_content(&feed::render(feed)?, options, mode, now, substituted)?
+        }
+        Content::NowPlaying { now_playing } => {
+            let content = now_playing::render(now_playing)?;
+            render_content(&content, options, mode, now, substituted)?
+        }
+        Content::Stats { stats } => {
+            render_content(&stats::render(stats)?, options, mode, now, substituted)?
+        }
+        #[cfg(feature = "time")]
+        Content::Calendar { calendar } => {
+            let content = calendar::render(calendar, now.time)?;
+            render_content(&content, options, mode, now, substituted)?
+        }
+        #[cfg(not(feature = "time"))]
+        Content::Calendar { .. } => {
+            anyhow::bail!("badgemagic was built without the `time` feature")
+        }
+    })
+}
+
+/// Load the `FIGlet` font at `path`, or the built-in banner font
+fn load_banner_font(path: Option<&Path>) -> Result<HashMap<char, Framebuffer>> {
+    let Some(path) = path else {
+        return Ok(builtin_banner_font());
+    };
+    let font = fs::read_to_string(path)
+        .with_context(|| format!("load banner font: {}", path.display()))?;
+    parse_figlet_font(&font).context("parse banner font")
+}
+
+/// Decode a base64 bitmap, with or without padding
+fn decode_base64(bitmap: &str) -> Result<Vec<u8>> {
+    if bitmap.ends_with('=') {
+        base64::engine::general_purpose::STANDARD
+    } else {
+        base64::engine::general_purpose::STANDARD_NO_PAD
+    }
+    .decode(bitmap)
+    .context("decode bitmap")
+}
+
+/// Render `parts` side by side, with `gap` blank columns in between
+fn render_parts(
+    parts: &[Content],
+    gap: u32,
+    options: &StyleOptions,
+    mode: Mode,
+    now: Now,
+    substituted: &mut Vec<char>,
+) -> Result<Framebuffer> {
+    let mut rendered: Option<Framebuffer> = None;
+    for (index, part) in parts.iter().enumerate() {
+        let context = || format!("part {}", index + 1);
+        anyhow::ensure!(
+            !matches!(part, Content::Animation { .. }),
+            "{}: animations can not be combined with other parts",
+            context(),
+        );
+        let bitmaps =
+            render_content(part, options, mode, now, substituted).with_context(context)?;
+        let [bitmap] = <[Framebuffer; 1]>::try_from(bitmaps).map_err(|_| {
+            anyhow::anyhow!(
+                "{}: wrapped text can not be combined with other parts",
+                context()
+            )
+        })?;
+        rendered = Some(match rendered {
+            Some(left) => render_aligned(&left.hstack(bitmap, gap), Align::Left, mode),
+            None => bitmap,
+        });
+    }
+    rendered.context("parts must contain at least one part")
+}
+
+/// Render a built-in icon, centered vertically
+fn render_icon(icon: Icon) -> Framebuffer {
+    let bitstring = match icon {
+        Icon::Heart => ".XX.XX.\nXXXXXXX\nXXXXXXX\nXXXXXXX\n.XXXXX.\n..XXX..\n...X...",
+        Icon::Star => "...X...\n...X...\nXXXXXXX\n.XXXXX.\n..XXX..\n.XX.XX.\nXX...XX",
+        Icon::Smile => ".XXXXX.\nX.....X\nX.X.X.X\nX.....X\nX.XXX.X\nX.....X\n.XXXXX.",
+        Icon::Check => "......X\n.....XX\nX...XX.\nXX.XX..\n.XXX...\n..X....",
+        Icon::Arrow => "...X...\n...XX..\nXXXXXX.\nXXXXXXX\nXXXXXX.\n...XX..\n...X...",
+        Icon::Sun => "...X...\n.X...X.\n..XXX..\nX.XXX.X\n..XXX..\n.X...X.\n...X...",
+        Icon::Cloud => "..XXX...\n.X...XX.\nX......X\nX......X\n.XXXXXX.",
+        Icon::Rain => "..XXX...\n.X...XX.\nX......X\n.XXXXXX.\n........\n.X..X..X\nX..X..X.",
+        Icon::Snow => "X..X..X\n.X.X.X.\n..XXX..\nXXXXXXX\n..XXX..\n.X.X.X.\nX..X..X",
+        Icon::Storm => "...XX\n..XX.\n.XX..\nXXXXX\n..XX.\n.XX..\nXX...",
+        Icon::Fog => "XXXXXX.\n.......\n.XXXXXX\n.......\nXXXXXX.",
+        Icon::Note => "..XXXXX\n..X...X\n..X...X\n..X...X\nXXX.XXX\nXXX.XXX",
+    };
+    let mut bitmap = parse_bitstring(bitstring, 1).unwrap();
+    bitmap.align_vertically(11, VerticalAlign::Center);
+    bitmap
+}
+
+/// Render a raw image with 1 bit per pixel, scaled up by `scale`
+fn render_image(data: &[u8], width: u32, scale: Option<usize>) -> Result<Framebuffer> {
+    let scale = scale.unwrap_or(1);
+    anyhow::ensure!(scale > 0, "scale must be at least 1");
+    let image_raw = ImageRawLE::<BinaryColor>::new(data, width);
+    let image = Image::new(&image_raw, Point::zero());
+    let size = image.bounding_box().size;
+    let mut bitmap = Framebuffer::blank(
+        usize::try_from(size.width).unwrap(),
+        usize::try_from(size.height).unwrap(),
+    );
+    image.draw(&mut bitmap).unwrap();
+    Ok(render_aligned(
+        &bitmap.scale(scale),
+        Align::Left,
+        Mode::default(),
+    ))
+}
+
+/// Parse a bitstring for content that is scaled up by `scale`
+///
+/// The bitstring may have fewer lines than the display has rows.
+/// Off pixels are `_`, `.` or space, on pixels are `X`, `#` or `*`.
+fn parse_bitstring(bitstring: &str, scale: usize) -> Result<Framebuffer> {
+    // spaces are off pixels, so only remove blank lines around the image
+    let mut lines: Vec<_> = bitstring.lines().collect();
+    while lines.last().is_some_and(|l| l.trim().is_empty()) {
+        lines.pop();
+    }
+    let first = lines.iter().take_while(|l| l.trim().is_empty()).count();
+    let lines = &lines[first..];
+
+    anyhow::ensure!(
+        !lines.is_empty() && lines.len() * scale <= 11,
+        "expected 1 to {} lines in bitstring, found {} lines",
+        11 / scale,
+        lines.len()
+    );
+    // shorter lines are padded with off pixels
+    let width = lines.iter().map(|l| l.chars().count()).max().unwrap();
+    let mut bitmap = Framebuffer::blank(width, lines.len());
+
+    for (y, line) in lines.iter().enumerate() {
+        for (x, c) in line.chars().enumerate() {
+            bitmap.rows[y][x] = match c {
+                '_' | '.' | ' ' => false,
+                'X' | '#' | '*' => true,
+                _ => anyhow::bail!("invalid bit value for bit ({x}, {y}): {c:?}"),
+            };
+        }
+    }
+
+    Ok(bitmap)
+}
+
+fn text_width(text: &str, font: Font) -> i32 {
+    let width = font.text(text).bounding_box().size.width;
+    i32::try_from(width).unwrap_or(i32::MAX)
+}
+
+fn render_text(
+    text: &str,
+    font: Font,
+    effect: Option<Effect>,
+    align: Align,
+    mode: Mode,
+) -> Framebuffer {
+    let text = font.text(text);
+    match effect {
+        None => render_aligned(&text, align, mode),
+        Some(Effect::Outline) => render_aligned(&text.outline(), align, mode),
+        Some(Effect::Bold) => render_aligned(&text.embolden(), align, mode),
+        Some(Effect::Shadow) => render_aligned(&text.shadow(), align, mode),
+    }
+}
+
+/// Render `content` moved onto the display and aligned horizontally
+///
+/// Centered and right aligned content starts at its column of the display.
+/// In `Mode::Center` the badge centers the message itself,
+/// so the content is placed in a message that the badge moves to that column.
+fn render_aligned<T>(content: &T, align: Align, mode: Mode) -> Framebuffer
+where
+    T: Drawable<Color = BinaryColor> + Dimensions,
+{
+    let bounds = content.bounding_box();
+    let width = i32::try_from(bounds.size.width).unwrap_or(i32::MAX);
+    let height = i32::try_from(bounds.size.height).unwrap_or(i32::MAX);
+    // content wider than the display is always left aligned
+    let left = match align {
+        Align::Left => 0,
+        Align::Center => (DISPLAY_WIDTH - width) / 2,
+        Align::Right => DISPLAY_WIDTH - width,
+    }
+    .max(0);
+    let (left, columns) = if mode == Mode::Center && align != Align::Left {
+        centered_message(width, left)
+    } else {
+        (left, left + width)
+    };
+    let dx = left - bounds.top_left.x;
+    // keep effects that grow the content from leaving the display vertically
+    let top = if height > 11 {
+        (11 - height) / 2
+    } else {
+        bounds.top_left.y.clamp(0, 11 - height)
+    };
+    let dy = top - bounds.top_left.y;
+
+    // content taller than the display is clipped at the top and bottom
+    let mut bitmap = Framebuffer::blank(usize::try_from(columns).unwrap(), 11);
+    content
+        .draw(&mut bitmap.translated(Point::new(dx, dy)))
+        .unwrap();
+    bitmap
+}
+
+/// Return the left column of `width` columns of content and the width of the message,
+/// so the content starts at column `left` of the display when the badge centers the message
+///
+/// The badge centers messages of whole bytes, content it can not move to `left`
+/// (e.g. right aligned) is placed as close as possible.
+fn centered_message(width: i32, left: i32) -> (i32, i32) {
+    let narrowest = (width + 7) / 8 * 8;
+    (narrowest..=DISPLAY_WIDTH)
+        .step_by(8)
+        .map(|columns| {
+            let shift = (DISPLAY_WIDTH - columns) / 2;
+            ((left - shift).clamp(0, columns - width), columns)
+        })
+        .min_by_key(|&(x, columns)| (x + (DISPLAY_WIDTH - columns) / 2 - left).abs())
+        .unwrap_or((left, left + width))
+}
+
+fn render_animation(
+    animation: &Animation,
+    frame_gap: i32,
+    substituted: &mut Vec<char>,
+) -> Result<Framebuffer> {
+    let text = transliterate(&animation.text, substituted);
+    let font = animation.font.mono_font();
+    let char_width = i32::try_from(font.character_size.width + font.character_spacing).unwrap();
+    let char_height = i32::try_from(font.character_size.height).unwrap();
+    let width = text_width(&text, animation.font);
+    anyhow::ensure!(
+        width <= DISPLAY_WIDTH,
+        "animated text does not fit on the display: {text:?}"
+    );
+    anyhow::ensure!(animation.frames > 0, "animation needs at least one frame");
+
+    let frame_width = DISPLAY_WIDTH + frame_gap;
+    let frames = i32::try_from(animation.frames).context("too many frames")?;
+    let columns = usize::try_from(frames * frame_width).context("too many frames")?;
+    let mut bitmap = Framebuffer::blank(columns, 11);
+
+    let amplitude = f64::from((11 - char_height) / 2);
+    let left = (DISPLAY_WIDTH - width) / 2;
+    let character_style = MonoTextStyle::new(font, BinaryColor::On);
+    for frame in 0..frames {
+        for (i, c) in (0..).zip(text.chars()) {
+            let phase = match animation.kind {
+                AnimationKind::Bounce => 0.0,
+                AnimationKind::Wave => f64::from(i) / 4.0,
+            };
+            let angle = std::f64::consts::TAU * (f64::from(frame) / f64::from(frames) + phase);
+            #[allow(clippy::cast_possible_truncation)] // the amplitude is at most 5
+            let dy = (amplitude * angle.sin()).round() as i32;
+            let mut buf = [0; 4];
+            Text::with_baseline(
+                c.encode_utf8(&mut buf),
+                Point::new(
+                    frame * frame_width + left + i * char_width,
+                    (11 - char_height) / 2 + dy,
+                ),
+                character_style,
+                Baseline::Top,
+            )
+            .draw(&mut bitmap)
+            .unwrap();
+        }
+    }
+    Ok(bitmap)
+}
+
+/// Replace characters missing from the ISO 8859-1 fonts with similar looking ones
+///
+/// The replaced characters are added to `substituted`, unless already listed.
+fn transliterate(text: &str, substituted: &mut Vec<char>) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        if u32::from(c) <= 0xff {
+            result.push(c);
+            continue;
+        }
+        let replacement = match c {
+            '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}' | '\u{2032}' => "'",
+            '\u{201c}' | '\u{201d}' | '\u{201e}' | '\u{201f}' | '\u{2033}' => "\"",
+            '\u{2039}' => "<",
+            '\u{203a}' => ">",
+            '\u{2010}'..='\u{2015}' | '\u{2212}' => "-",
+            '\u{2026}' => "...",
+            '\u{2022}' | '\u{2219}' => "\u{b7}",
+            '\u{20ac}' => "EUR",
+            '\u{2122}' => "TM",
+            '\u{0152}' => "OE",
+            '\u{0153}' => "oe",
+            '\u{0141}' => "L",
+            '\u{0142}' => "l",
+            '\u{0160}' => "S",
+            '\u{0161}' => "s",
+            '\u{017d}' => "Z",
+            '\u{017e}' => "z",
+            '\u{0178}' => "Y",
+            '\u{0131}' => "i",
+            '\u{2000}'..='\u{200a}' | '\u{202f}' | '\u{205f}' => " ",
+            _ => "?",
+        };
+        result.push_str(replacement);
+        if !substituted.contains(&c) {
+            substituted.push(c);
+        }
+    }
+    result
+}
+
+/// Split `text` at word boundaries into lines for which `fits` returns true
+///
+/// Words that do not fit on a line by themselves are split at the last
+/// character that still fits.
+fn wrap_text(text: &str, fits: impl Fn(&str) -> bool) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() {
+            word.to_owned()
+        } else {
+            format!("{line} {word}")
+        };
+        if fits(&candidate) {
+            line = candidate;
+            continue;
+        }
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        for c in word.chars() {
+            line.push(c);
+            if !fits(&line) && line.chars().count() > 1 {
+                line.pop();
+                lines.push(std::mem::replace(&mut line, c.into()));
+            }
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Operations of the renderer, on images of arbitrary size
+impl Framebuffer {
+    fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Scale the image to `height` rows, keeping the aspect ratio (nearest neighbor)
+    fn scale_to_height(&self, height: usize) -> Self {
+        if self.height() == 0 {
+            return Self::blank(0, height);
+        }
+        let width = (self.width * height + self.height() / 2) / self.height();
+        let mut scaled = Self::blank(width, height);
+        for (y, row) in scaled.rows.iter_mut().enumerate() {
+            let src = &self.rows[y * self.height() / height];
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = src[x * self.width / width];
+            }
+        }
+        scaled
+    }
+
+    /// Scale the image up by an integer `factor` (nearest neighbor)
+    fn scale(&self, factor: usize) -> Self {
+        let rows = self
+            .rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .flat_map(|pixel| std::iter::repeat_n(*pixel, factor))
+                    .collect::<Vec<_>>()
+            })
+            .flat_map(|row| std::iter::repeat_n(row, factor))
+            .collect();
+        Self {
+            width: self.width * factor,
+            rows,
+        }
+    }
+
+    /// Add blank rows to the top and bottom to align the image in `height` rows
+    fn align_vertically(&mut self, height: usize, align: VerticalAlign) {
+        let missing = height.saturating_sub(self.height());
+        let top = match align {
+            VerticalAlign::Top => 0,
+            VerticalAlign::Center => missing / 2,
+            VerticalAlign::Bottom => missing,
+        };
+        let blank = vec![false; self.width];
+        self.rows
+            .splice(0..0, std::iter::repeat_n(blank.clone(), top));
+        self.rows.resize(self.height() + missing - top, blank);
+    }
+
+    /// Add blank columns to the left and right side of the image
+    fn pad(&mut self, start: usize, end: usize) {
+        for row in &mut self.rows {
+            row.splice(0..0, std::iter::repeat_n(false, start));
+            row.resize(row.len() + end, false);
+        }
+        self.width += start + end;
+    }
+
+    /// Mirror the image vertically
+    fn flip_vertical(&mut self) {
+        self.rows.reverse();
+    }
+
+    /// Mirror the image horizontally
+    fn mirror(&mut self) {
+        for row in &mut self.rows {
+            row.reverse();
+        }
+    }
+
+    /// Turn lit pixels off and dark pixels on
+    fn invert(&mut self) {
+        for pixel in self.rows.iter_mut().flatten() {
+            *pixel = !*pixel;
+        }
+    }
+
+    /// Mirror each frame of `frame_width` columns horizontally
+    ///
+    /// Only the first `width` columns of each frame are mirrored, the rest is left blank.
+    fn mirror_frames(&mut self, frame_width: i32, width: i32) {
+        let frame_width = usize::try_from(frame_width).unwrap();
+        let width = usize::try_from(width).unwrap();
+        for row in &mut self.rows {
+            row.resize(self.width.next_multiple_of(frame_width), false);
+            for frame in row.chunks_mut(frame_width) {
+                frame[..width].reverse();
+            }
+        }
+        self.width = self.width.next_multiple_of(frame_width);
+    }
+
+    /// Move the image `y` rows down (or up if negative), clipping at the edges
+    fn offset_vertical(&mut self, y: i32) {
+        let height = self.height();
+        let shift = usize::try_from(y.unsigned_abs())
+            .unwrap_or(usize::MAX)
+            .min(height);
+        let blank = vec![false; self.width];
+        if y > 0 {
+            self.rows.truncate(height - shift);
+            self.rows.splice(0..0, std::iter::repeat_n(blank, shift));
+        } else {
+            self.rows.drain(..shift);
+            self.rows.resize(height, blank);
+        }
+    }
+
+    /// Move the image `x` columns to the right, by adding blank columns,
+    /// or to the left if negative, by removing columns
+    fn offset_horizontal(&mut self, x: i32) {
+        let shift = usize::try_from(x.unsigned_abs()).unwrap_or(usize::MAX);
+        if x > 0 {
+            self.pad(shift, 0);
+        } else {
+            let shift = shift.min(self.width);
+            for row in &mut self.rows {
+                row.drain(..shift);
+            }
+            self.width -= shift;
+        }
+    }
+
+    /// Move every frame `x` columns to the right (or left if negative),
+    /// clipping at the edges of the frame
+    fn offset_frames(&mut self, frame_width: i32, width: i32, x: i32) {
+        if x == 0 {
+            return;
+        }
+        let frame_width = usize::try_from(frame_width).unwrap();
+        let width = usize::try_from(width).unwrap();
+        let shift = usize::try_from(x.unsigned_abs())
+            .unwrap_or(usize::MAX)
+            .min(width);
+        for row in &mut self.rows {
+            row.resize(self.width.next_multiple_of(frame_width), false);
+            for frame in row.chunks_mut(frame_width) {
+                let frame = &mut frame[..width];
+                if x > 0 {
+                    frame.rotate_right(shift);
+                    frame[..shift].fill(false);
+                } else {
+                    frame.rotate_left(shift);
+                    frame[width - shift..].fill(false);
+                }
+            }
+        }
+        self.width = self.width.next_multiple_of(frame_width);
+    }
+
+    /// Split the image into parts of at most `width` columns
+    ///
+    /// The image is cut after a blank column if there is one in the second half of a part,
+    /// to avoid cutting through a character.
+    fn split_columns(&self, width: usize) -> Vec<Self> {
+        let is_blank = |x: usize| self.rows.iter().all(|row| !row[x]);
+        let mut parts = Vec::new();
+        let mut start = 0;
+        while self.width - start > width {
+            let end = (start + width / 2..start + width)
+                .rev()
+                .find(|&x| is_blank(x))
+                .map_or(start + width, |x| x + 1);
+            parts.push(self.columns(start..end));
+            start = end;
+        }
+        parts.push(self.columns(start..self.width));
+        parts
+    }
+
+    /// Return a copy of the `columns` of the image
+    fn columns(&self, columns: std::ops::Range<usize>) -> Self {
+        Self {
+            width: columns.len(),
+            rows: self
+                .rows
+                .iter()
+                .map(|row| row[columns.clone()].to_vec())
+                .collect(),
+        }
+    }
+
+    /// Append `other` to the right side of the image
+    fn append(&mut self, other: &Self) {
+        let height = self.height().max(other.height());
+        self.rows.resize(height, vec![false; self.width]);
+        for (y, row) in self.rows.iter_mut().enumerate() {
+            match other.rows.get(y) {
+                Some(other) => row.extend_from_slice(other),
+                None => row.resize(row.len() + other.width, false),
+            }
+        }
+        self.width += other.width;
+    }
+}
+
+/// Parse a `FIGlet` font file (`.flf`)
+///
+/// Every character other than a space or the hardblank is a lit pixel.
+/// Smushing and kerning are not supported, glyphs are placed side by side.
+fn parse_figlet_font(font: &str) -> Result<HashMap<char, Framebuffer>> {
+    let mut lines = font.lines();
+    let header = lines.next().context("missing header")?;
+    let signature = header.strip_prefix("flf2a").context("invalid signature")?;
+    let mut chars = signature.chars();
+    let hardblank = chars.next().context("missing hardblank")?;
+    let params: Vec<usize> = chars
+        .as_str()
+        .split_whitespace()
+        .take(5)
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .context("invalid header")?;
+    let [height, _baseline, _max_length, _old_layout, comment_lines] = params[..] else {
+        anyhow::bail!("incomplete header");
+    };
+
+    let mut lines = lines.skip(comment_lines);
+    // required characters: printable ASCII followed by 7 german characters
+    let codes = (32..127).chain([196, 214, 220, 228, 246, 252, 223]);
+    let mut glyphs = HashMap::new();
+    for code in codes {
+        let c = char::from_u32(code).unwrap();
+        let mut rows = Vec::with_capacity(height);
+        for _ in 0..height {
+            let Some(line) = lines.next() else {
+                // the german characters are optional in practice
+                anyhow::ensure!(code > 126, "missing glyph for {c:?}");
+                return Ok(glyphs);
+            };
+            let endmark = line.chars().last().unwrap_or_default();
+            let row: Vec<bool> = line
+                .trim_end_matches(endmark)
+                .chars()
+                .map(|c| c != ' ' && c != hardblank)
+                .collect();
+            rows.push(row);
+        }
+        let width = rows.iter().map(Vec::len).max().unwrap_or_default();
+        for row in &mut rows {
+            row.resize(width, false);
+        }
+        glyphs.insert(c, Framebuffer { width, rows });
+    }
+    Ok(glyphs)
+}
+
+/// Build a blocky banner font from the 5x7 font
+fn builtin_banner_font() -> HashMap<char, Framebuffer> {
+    (' '..='\u{ff}')
+        .map(|c| {
+            let mut glyph = Framebuffer::blank(6, 7);
+            let mut buf = [0; 4];
+            let text = Text::with_baseline(
+                c.encode_utf8(&mut buf),
+                Point::zero(),
+                MonoTextStyle::new(&FONT_5X7, BinaryColor::On),
+                Baseline::Top,
+            );
+            text.draw(&mut glyph).unwrap();
+            (c, glyph)
+        })
+        .collect()
+}
+
+/// Render `text` with a banner font and scale it to the display height
+fn render_banner(font: &HashMap<char, Framebuffer>, text: &str) -> Framebuffer {
+    let mut bitmap = Framebuffer::blank(0, 0);
+    for c in text.chars() {
+        if let Some(glyph) = font.get(&c).or_else(|| font.get(&'?')) {
+            bitmap.append(glyph);
+        }
+    }
+    bitmap.scale_to_height(11)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        assign_slots, parse_bitstring, parse_figlet_font, render_parts, render_text, text_width,
+        transliterate, wrap_text, Align, Config, Content, Font, Framebuffer, Icon, Now, Slot,
+        StyleOptions, VerticalAlign,
+    };
+    use crate::{
+        protocol::{Mode, Style},
+        simulator,
+    };
+
+    #[test]
+    fn assign_pinned_slots() {
+        let part = |width| Slot {
+            style: Style::default(),
+            bitmap: Framebuffer::blank(width, 11),
+            invert: false,
+            rotate_180: false,
+            mirror: false,
+        };
+        let slots = assign_slots(vec![
+            (None, vec![part(1)]),
+            (Some(3), vec![part(3), part(4)]),
+            (None, vec![part(2), part(5)]),
+        ])
+        .unwrap();
+        let widths: Vec<_> = slots
+            .iter()
+            .map(|s| s.as_ref().map(|s| s.bitmap.width))
+            .collect();
+        assert_eq!(
+            widths,
+            [
+                Some(1),
+                Some(2),
+                Some(3),
+                Some(4),
+                Some(5),
+                None,
+                None,
+                None
+            ]
+        );
+
+        assert!(assign_slots(vec![(Some(2), vec![part(1)]), (Some(2), vec![part(1)])]).is_err());
+        assert!(assign_slots(vec![(Some(9), vec![part(1)])]).is_err());
+    }
+
+    #[test]
+    fn align_text_on_display() {
+        let font = Font::default();
+        let width = usize::try_from(text_width("Hi", font)).unwrap();
+        let glyphs = render_text("Hi", font, None, Align::Left, Mode::Left);
+        let glyphs: Vec<_> = (0..glyphs.width)
+            .filter(|&x| glyphs.rows.iter().any(|row| row[x]))
+            .collect();
+        // the columns lit on the display in every frame, the badge centers short messages itself
+        let lit = |align: &str, mode: &str| -> Vec<Vec<usize>> {
+            let config: Config = toml::from_str(&format!(
+                "[[message]]\ntext = \"Hi\"\nalign = \"{align}\"\nmode = \"{mode}\""
+            ))
+            .unwrap();
+            let payload = config.build_payload().unwrap();
+            let (style, content) = payload.message(0).unwrap();
+            simulator::message_frames(style, content)
+                .iter()
+                .map(|frame| {
+                    (0..simulator::WIDTH)
+                        .filter(|&x| (0..11).any(|y| frame.get(x, y)))
+                        .collect()
+                })
+                .collect()
+        };
+        let moved = |dx: usize| glyphs.iter().map(|x| x + dx).collect::<Vec<_>>();
+        let centered = lit("center", "center");
+        assert!(centered
+            .iter()
+            .all(|frame| *frame == moved((44 - width) / 2)));
+        // the badge can not move a message of whole bytes to the right edge
+        let right = lit("right", "center");
+        assert!(right.iter().all(|frame| *frame == right[0]));
+        assert!(right[0][0] > centered[0][0] && *right[0].last().unwrap() < 44);
+        // the content stays on one screen, the last frame shows it
+        let dropped = lit("center", "drop");
+        assert_eq!(dropped.last().unwrap(), &moved((44 - width) / 2));
+        assert!(dropped
+            .iter()
+            .all(|frame| frame.iter().all(|x| centered[0].contains(x))));
+    }
+
+    #[test]
+    fn invert_leaves_padding_dark() {
+        let width = usize::try_from(text_width("Hi", Font::default())).unwrap();
+        assert_ne!(width % 8, 0);
+        let config: Config = toml::from_str("[[message]]\ntext = \"Hi\"\ninvert = true").unwrap();
+        let payload = config.build_payload().unwrap();
+        let (_, content) = payload.message(0).unwrap();
+        let lit = |x: usize| {
+            content[x / 8]
+                .iter()
+                .any(|row| row & (0x80 >> (x % 8)) != 0)
+        };
+        assert!((0..width).all(lit));
+        assert!((width..content.len() * 8).all(|x| !lit(x)));
+    }
+
+    #[test]
+    fn mirror_leaves_padding_dark() {
+        let glyphs = render_text("Hi", Font::default(), None, Align::Left, Mode::Left);
+        let width = glyphs.width;
+        assert_ne!(width % 8, 0);
+        let glyphs: Vec<_> = (0..width)
+            .filter(|&x| glyphs.rows.iter().any(|row| row[x]))
+            .collect();
+        let lit = |options: &str| -> Vec<usize> {
+            let config: Config =
+                toml::from_str(&format!("[[message]]\ntext = \"Hi\"\n{options}")).unwrap();
+            let payload = config.build_payload().unwrap();
+            let (_, content) = payload.message(0).unwrap();
+            (0..content.len() * 8)
+                .filter(|x| {
+                    content[x / 8]
+                        .iter()
+                        .any(|row| row & (0x80 >> (x % 8)) != 0)
+                })
+                .collect()
+        };
+        let mut mirrored: Vec<_> = glyphs.iter().map(|x| width - 1 - x).collect();
+        mirrored.sort_unstable();
+        assert_eq!(lit("mirror = true"), mirrored);
+        assert_eq!(lit("rotate_180 = true"), mirrored);
+        assert_eq!(lit("rotate_180 = true\nmirror = true"), glyphs);
+    }
+
+    #[test]
+    fn split_columns_after_blank_column() {
+        let bitmap = parse_bitstring("XXX_XXX_XXXXXX", 1).unwrap();
+        let widths = |width| {
+            bitmap
+                .split_columns(width)
+                .iter()
+                .map(|part| part.width)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(widths(20), [14]);
+        assert_eq!(widths(8), [8, 6]);
+        assert_eq!(widths(4), [4, 4, 4, 2]);
+
+        let parts = bitmap.split_columns(8);
+        assert_eq!(parts[0].rows[0][4..], [true, true, true, false]);
+        assert_eq!(parts[1].rows[0], [true; 6]);
+    }
+
+    #[test]
+    fn render_parts_side_by_side() {
+        let options = StyleOptions::default();
+        let parts = [
+            Content::Icon { icon: Icon::Heart },
+            Content::Bitstring {
+                bitstring: "XX".into(),
+                bitstring_align: VerticalAlign::Top,
+                scale: None,
+            },
+        ];
+        let bitmap =
+            render_parts(&parts, 2, &options, Mode::Left, Now::utc(), &mut Vec::new()).unwrap();
+        assert_eq!(bitmap.width, 7 + 2 + 2);
+        assert_eq!(bitmap.height(), 11);
+        assert_eq!(bitmap.rows[0][7..], [false, false, true, true]);
+        assert!(bitmap.rows[4][..7].iter().all(|&on| on));
+
+        assert!(render_parts(&[], 0, &options, Mode::Left, Now::utc(), &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn scale_bitstring() {
+        let mut bitmap = parse_bitstring("X_\n_X", 2).unwrap().scale(2);
+        bitmap.align_vertically(11, VerticalAlign::Center);
+        assert_eq!(bitmap.height(), 11);
+        assert_eq!(bitmap.rows[3], [true, true, false, false]);
+        assert_eq!(bitmap.rows[6], [false, false, true, true]);
+        assert!(parse_bitstring("X\nX\nX\nX\nX\nX", 2).is_err());
+    }
+
+    #[test]
+    fn parse_lenient_bitstring() {
+        let bitmap = parse_bitstring("\n #.\n*\n", 1).unwrap();
+        assert_eq!(bitmap.rows, [[false, true, false], [true, false, false]]);
+    }
+
+    #[test]
+    fn parse_figlet_font_glyphs() {
+        let mut font = String::from("flf2a$ 2 2 4 0 1\ncomment\n");
+        for c in ' '..='~' {
+            let (top, bottom) = if c == 'A' { ("/\\", "$|") } else { ("", "") };
+            font.extend([top, "@\n", bottom, "@@\n"]);
+        }
+        let glyphs = parse_figlet_font(&font).unwrap();
+        assert_eq!(glyphs.len(), 95);
+        assert_eq!(glyphs[&'A'].rows, [[true, true], [false, true]]);
+        assert_eq!(glyphs[&' '].width, 0);
+    }
+
+    #[test]
+    fn transliterate_unsupported_characters() {
+        let mut substituted = Vec::new();
+        assert_eq!(transliterate("Grüße", &mut substituted), "Grüße");
+        assert!(substituted.is_empty());
+        assert_eq!(
+            transliterate(
+                "\u{201c}Hi\u{201d} \u{2013} it\u{2019}s 5\u{20ac}\u{2026}",
+                &mut substituted
+            ),
+            "\"Hi\" - it's 5EUR..."
+        );
+        assert_eq!(transliterate("\u{263a}\u{201c}", &mut substituted), "?\"");
+        assert_eq!(
+            substituted,
+            ['\u{201c}', '\u{201d}', '\u{2013}', '\u{2019}', '\u{20ac}', '\u{2026}', '\u{263a}']
+        );
+    }
+
+    #[test]
+    fn wrap_text_at_word_boundaries() {
+        let fits = |line: &str| line.len() <= 7;
+        assert_eq!(
+            wrap_text("Hello  brave new\nworld", fits),
+            ["Hello", "brave", "new", "world"],
+        );
+        assert_eq!(wrap_text("a b c d e", fits), ["a b c d", "e"]);
+        assert_eq!(wrap_text("Badgemagic!", fits), ["Badgema", "gic!"]);
+        assert!(wrap_text("   ", fits).is_empty());
+    }
+}
@@ -0,0 +1,119 @@
+//! Read the system stats for `stats` messages
+
+use anyhow::Result;
+
+use super::{Content, Stats};
+
+/// Values of the placeholders, usages in percent
+#[cfg_attr(not(feature = "stats"), allow(dead_code))]
+struct Values {
+    cpu: f32,
+    mem: u64,
+    swap: u64,
+    disk: u64,
+    /// Load average of the last minute
+    load: f64,
+    /// Uptime in seconds
+    uptime: u64,
+}
+
+/// Read the stats and replace the placeholders in the format of `stats`
+pub(super) fn render(stats: &Stats) -> Result<Content> {
+    Ok(Content::text(format(&stats.format, &read()?)))
+}
+
+fn format(format: &str, stats: &Values) -> String {
+    format
+        .replace("{cpu}", &format!("{:.0}", stats.cpu))
+        .replace("{mem}", &stats.mem.to_string())
+        .replace("{swap}", &stats.swap.to_string())
+        .replace("{disk}", &stats.disk.to_string())
+        .replace("{load}", &format!("{:.2}", stats.load))
+        .replace("{uptime}", &format_uptime(stats.uptime))
+}
+
+/// Format the uptime with its two largest units, e.g. `3d 4h`
+fn format_uptime(seconds: u64) -> String {
+    let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+    match (days, hours) {
+        (0, 0) => format!("{minutes}m"),
+        (0, _) => format!("{hours}h {minutes}m"),
+        _ => format!("{days}d {hours}h"),
+    }
+}
+
+#[cfg(feature = "stats")]
+#[allow(clippy::unnecessary_wraps)] // fails without the `stats` feature
+fn read() -> Result<Values> {
+    use std::{sync::Mutex, thread};
+
+    use sysinfo::{Disks, System, MINIMUM_CPU_UPDATE_INTERVAL};
+
+    /// Kept between renders, the CPU usage is measured since the last refresh
+    static SYSTEM: Mutex<Option<System>> = Mutex::new(None);
+
+    let percent = |used: u64, total: u64| used.saturating_mul(100) / total.max(1);
+
+    let mut system = SYSTEM.lock().unwrap();
+    let system = system.get_or_insert_with(|| {
+        let mut system = System::new();
+        system.refresh_cpu_usage();
+        thread::sleep(MINIMUM_CPU_UPDATE_INTERVAL);
+        system
+    });
+    system.refresh_cpu_usage();
+    system.refresh_memory();
+
+    let disks = Disks::new_with_refreshed_list();
+    let disk = disks
+        .list()
+        .iter()
+        .find(|disk| disk.mount_point() == std::path::Path::new("/"))
+        .or_else(|| disks.list().first())
+        .map_or(0, |disk| {
+            percent(
+                disk.total_space().saturating_sub(disk.available_space()),
+                disk.total_space(),
+            )
+        });
+
+    Ok(Values {
+        cpu: system.global_cpu_usage(),
+        mem: percent(system.used_memory(), system.total_memory()),
+        swap: percent(system.used_swap(), system.total_swap()),
+        disk,
+        load: System::load_average().one,
+        uptime: System::uptime(),
+    })
+}
+
+#[cfg(not(feature = "stats"))]
+fn read() -> Result<Values> {
+    anyhow::bail!("badgemagic was built without the `stats` feature")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format, Values};
+
+    #[test]
+    fn format_stats() {
+        let stats = Values {
+            cpu: 12.6,
+            mem: 40,
+            swap: 0,
+            disk: 71,
+            load: 0.5,
+            uptime: 3 * 86400 + 4 * 3600 + 5 * 60,
+        };
+        assert_eq!(
+            format("{cpu}% {mem}% {swap}% {disk}% {load} {uptime}", &stats),
+            "13% 40% 0% 71% 0.50 3d 4h"
+        );
+        let stats = Values {
+            uptime: 65 * 60,
+            ..stats
+        };
+        assert_eq!(format("up {uptime}", &stats), "up 1h 5m");
+    }
+}
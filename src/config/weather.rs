@@ -0,0 +1,178 @@
+//! Fetch the current weather for `weather` messages
+
+use std::fmt::Write as _;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+#[cfg(feature = "weather")]
+use super::http::get;
+use super::{Content, Icon, Weather, WeatherProvider};
+
+/// Current weather at a location
+#[derive(Debug, PartialEq)]
+struct Conditions {
+    /// Temperature in °C
+    temp: i32,
+    /// Short description, e.g. `Light rain`
+    condition: String,
+    icon: Icon,
+}
+
+/// Fetch the weather and lay out its format as parts, with `{icon}` as built-in icon
+pub(super) fn render(weather: &Weather) -> Result<Content> {
+    let conditions =
+        fetch(weather).with_context(|| format!("fetch weather for {:?}", weather.location))?;
+    Ok(format(&weather.format, &conditions))
+}
+
+fn fetch(weather: &Weather) -> Result<Conditions> {
+    match weather.provider {
+        WeatherProvider::Wttr => {
+            let url = format!("https://wttr.in/{}", encode(&weather.location));
+            parse_wttr(&get(&url, &[("format", "j1")])?)
+        }
+        WeatherProvider::OpenWeather => {
+            let api_key = weather
+                .api_key
+                .as_deref()
+                .context("OpenWeather requires an api_key")?;
+            let query = [
+                ("q", weather.location.as_str()),
+                ("units", "metric"),
+                ("appid", api_key),
+            ];
+            let url = "https://api.openweathermap.org/data/2.5/weather";
+            parse_open_weather(&get(url, &query)?)
+        }
+    }
+}
+
+#[cfg(not(feature = "weather"))]
+fn get(_url: &str, _query: &[(&str, &str)]) -> Result<String> {
+    anyhow::bail!("badgemagic was built without the `weather` feature")
+}
+
+/// Percent-encode a path segment
+fn encode(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~,".contains(&byte) {
+            encoded.push(byte.into());
+        } else {
+            write!(encoded, "%{byte:02X}").unwrap();
+        }
+    }
+    encoded
+}
+
+fn field(value: &Value) -> Result<&str> {
+    value.as_str().context("unexpected weather format")
+}
+
+/// Parse the `j1` JSON format of wttr.in
+fn parse_wttr(json: &str) -> Result<Conditions> {
+    let value: Value = serde_json::from_str(json).context("parse weather")?;
+    let current = &value["current_condition"][0];
+    let code: u32 = field(&current["weatherCode"])?.parse()?;
+    Ok(Conditions {
+        temp: field(&current["temp_C"])?.parse()?,
+        condition: field(&current["weatherDesc"][0]["value"])?.trim().into(),
+        // https://www.worldweatheronline.com/weather-api/api/docs/weather-icons.aspx
+        icon: match code {
+            113 => Icon::Sun,
+            116 | 119 | 122 => Icon::Cloud,
+            143 | 248 | 260 => Icon::Fog,
+            200 | 386 | 389 | 392 | 395 => Icon::Storm,
+            179 | 182 | 185 | 227 | 230 | 317 | 320 | 323..=350 | 362..=377 => Icon::Snow,
+            _ => Icon::Rain,
+        },
+    })
+}
+
+/// Parse the current weather of [`WeatherProvider::OpenWeather`]
+fn parse_open_weather(json: &str) -> Result<Conditions> {
+    let value: Value = serde_json::from_str(json).context("parse weather")?;
+    let temp = value["main"]["temp"]
+        .as_f64()
+        .context("unexpected weather format")?;
+    let weather = &value["weather"][0];
+    let id = weather["id"]
+        .as_u64()
+        .context("unexpected weather format")?;
+    #[allow(clippy::cast_possible_truncation)] // temperatures on earth fit into i32
+    let temp = temp.round() as i32;
+    Ok(Conditions {
+        temp,
+        condition: weather["main"].as_str().unwrap_or_default().into(),
+        // https://openweathermap.org/weather-conditions
+        icon: match id {
+            200..=299 => Icon::Storm,
+            600..=699 => Icon::Snow,
+            700..=799 => Icon::Fog,
+            800 => Icon::Sun,
+            801.. => Icon::Cloud,
+            _ => Icon::Rain,
+        },
+    })
+}
+
+/// Replace the placeholders of `format`, every `{icon}` splits the text into parts
+fn format(format: &str, conditions: &Conditions) -> Content {
+    let text = format
+        .replace("{temp}", &conditions.temp.to_string())
+        .replace("{condition}", &conditions.condition);
+    Content::text_with_icon(&text, conditions.icon)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{encode, format, parse_open_weather, parse_wttr, Conditions};
+    use crate::config::{Content, Icon};
+
+    #[test]
+    fn parse_providers() {
+        let wttr = r#"{"current_condition": [{
+            "temp_C": "-3",
+            "weatherCode": "338",
+            "weatherDesc": [{"value": "Heavy snow"}]
+        }]}"#;
+        let expected = Conditions {
+            temp: -3,
+            condition: "Heavy snow".into(),
+            icon: Icon::Snow,
+        };
+        assert_eq!(parse_wttr(wttr).unwrap(), expected);
+
+        let open_weather = r#"{
+            "weather": [{"id": 501, "main": "Rain", "description": "moderate rain"}],
+            "main": {"temp": 30.6}
+        }"#;
+        let expected = Conditions {
+            temp: 31,
+            condition: "Rain".into(),
+            icon: Icon::Rain,
+        };
+        assert_eq!(parse_open_weather(open_weather).unwrap(), expected);
+        assert!(parse_wttr("{}").is_err());
+        assert_eq!(encode("New York"), "New%20York");
+    }
+
+    #[test]
+    fn format_parts() {
+        let conditions = Conditions {
+            temp: 31,
+            condition: "Sunny".into(),
+            icon: Icon::Sun,
+        };
+        let Content::Parts { parts, gap: 1 } =
+            format("{icon} {temp}\u{b0} {condition}", &conditions)
+        else {
+            panic!("expected parts");
+        };
+        let [Content::Icon { icon: Icon::Sun }, Content::Text { text, .. }] = &parts[..] else {
+            panic!("unexpected parts");
+        };
+        assert_eq!(text, " 31\u{b0} Sunny");
+    }
+}
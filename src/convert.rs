@@ -0,0 +1,104 @@
+//! Convert PNG images into message content for a config
+
+use std::{fs::File, io::BufReader, path::Path};
+
+use anyhow::{Context, Result};
+use badgemagic::config::{Config, Content, Message};
+use base64::Engine;
+
+/// Rows of the display, taller images are rejected
+const HEIGHT: u32 = 11;
+
+/// A monochrome image
+struct Monochrome {
+    width: u32,
+    pixels: Vec<bool>,
+}
+
+impl Monochrome {
+    /// Load a PNG image, pixels brighter than `threshold` (0 to 1) are lit
+    ///
+    /// Transparent pixels are treated as dark.
+    fn load(path: &Path, threshold: f32, invert: bool) -> Result<Self> {
+        anyhow::ensure!(
+            (0.0..=1.0).contains(&threshold),
+            "threshold must be between 0 and 1"
+        );
+        let file = File::open(path).with_context(|| format!("open image: {}", path.display()))?;
+        let mut decoder = png::Decoder::new(BufReader::new(file));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().context("decode image")?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).context("decode image")?;
+        anyhow::ensure!(
+            info.height <= HEIGHT,
+            "image is {} pixels high, the badge has {HEIGHT} rows",
+            info.height
+        );
+
+        let channels = info.color_type.samples();
+        let pixels = data[..info.buffer_size()]
+            .chunks_exact(channels)
+            .map(|pixel| {
+                let (luma, alpha) = match *pixel {
+                    [luma] => (f32::from(luma), 255.0),
+                    [luma, alpha] => (f32::from(luma), f32::from(alpha)),
+                    [r, g, b] => (luma(r, g, b), 255.0),
+                    [r, g, b, alpha] => (luma(r, g, b), f32::from(alpha)),
+                    _ => unreachable!("expanded to at most 4 channels"),
+                };
+                (luma * alpha / (255.0 * 255.0) > threshold) != invert
+            })
+            .collect();
+        Ok(Self {
+            width: info.width,
+            pixels,
+        })
+    }
+
+    fn rows(&self) -> impl Iterator<Item = &[bool]> {
+        self.pixels.chunks(self.width as usize)
+    }
+
+    /// Return the image as bitstring (`X` = On, `_` = Off)
+    fn bitstring(&self) -> String {
+        self.rows()
+            .map(|row| row.iter().map(|&on| if on { 'X' } else { '_' }).collect())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// Return the image with 1 bit per pixel (rows padded to whole bytes), encoded as base64
+    fn base64(&self) -> String {
+        let data: Vec<u8> = self
+            .rows()
+            .flat_map(|row| {
+                row.chunks(8).map(|bits| {
+                    bits.iter()
+                        .enumerate()
+                        .fold(0, |byte, (i, &on)| byte | (u8::from(on) << (7 - i)))
+                })
+            })
+            .collect();
+        base64::engine::general_purpose::STANDARD.encode(data)
+    }
+}
+
+fn luma(r: u8, g: u8, b: u8) -> f32 {
+    0.299 * f32::from(r) + 0.587 * f32::from(g) + 0.114 * f32::from(b)
+}
+
+/// Convert the image at `path` into a message of a config in TOML format
+pub fn convert(path: &Path, threshold: f32, invert: bool, base64: bool) -> Result<String> {
+    let image = Monochrome::load(path, threshold, invert)?;
+    let message = if base64 {
+        Message::new(Content::BitmapBase64 {
+            width: image.width,
+            bitmap_base64: image.base64(),
+            scale: None,
+        })
+    } else {
+        Message::bitstring(image.bitstring())
+    };
+    Ok(toml::to_string_pretty(&Config::default().message(message))?)
+}
@@ -0,0 +1,154 @@
+//! Compare two payloads, e.g. our output with a capture of the official apps
+//!
+//! ```
+//! use badgemagic::{
+//!     diff::diff,
+//!     protocol::{PayloadBuffer, State, Style},
+//! };
+//!
+//! let mut a = PayloadBuffer::new();
+//! a.add_message(Style::default(), 1);
+//! let mut b = PayloadBuffer::new();
+//! b.add_message(Style::default(), 1).set((2, 5), State::On);
+//!
+//! let diff = diff(&a, &b);
+//! assert_eq!(diff.pixels.len(), 1);
+//! assert_eq!(diff.pixels[0].1.lines().nth(5), Some("__+_____"));
+//! ```
+
+use std::fmt;
+
+use crate::protocol::{PayloadBuffer, Timestamp};
+
+/// Differences between two payloads `a` and `b`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PayloadDiff {
+    /// Differences of the header, e.g. `message 1: mode Left != Center`
+    pub header: Vec<String>,
+
+    /// Index and pixel differences of the messages with different content
+    ///
+    /// The pixels are rendered with one line per row:
+    /// `X` is lit in both, `_` is dark in both, `-` is only lit in `a` and `+` only in `b`.
+    pub pixels: Vec<(usize, String)>,
+}
+
+impl PayloadDiff {
+    /// Return whether the payloads are equal
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.header.is_empty() && self.pixels.is_empty()
+    }
+}
+
+impl fmt::Display for PayloadDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.header {
+            writeln!(f, "{line}")?;
+        }
+        for (index, pixels) in &self.pixels {
+            writeln!(
+                f,
+                "message {index}: pixels differ (- only in a, + only in b)"
+            )?;
+            f.write_str(pixels)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare the header and the messages of two payloads
+#[must_use]
+pub fn diff(a: &PayloadBuffer, b: &PayloadBuffer) -> PayloadDiff {
+    let mut diff = PayloadDiff::default();
+    diff.header.extend(field(
+        "timestamp",
+        &Time(a.timestamp()),
+        &Time(b.timestamp()),
+    ));
+    for index in 0..8 {
+        match (a.message(index), b.message(index)) {
+            (None, None) => {}
+            (Some(_), None) => diff.header.push(format!("message {index}: only in a")),
+            (None, Some(_)) => diff.header.push(format!("message {index}: only in b")),
+            (Some((style_a, content_a)), Some((style_b, content_b))) => {
+                let name = |name: &str| format!("message {index}: {name}");
+                diff.header.extend(
+                    [
+                        field(&name("blink"), &style_a.is_blink(), &style_b.is_blink()),
+                        field(&name("border"), &style_a.is_border(), &style_b.is_border()),
+                        field(&name("speed"), &style_a.get_speed(), &style_b.get_speed()),
+                        field(&name("mode"), &style_a.get_mode(), &style_b.get_mode()),
+                        field(
+                            &name("columns"),
+                            &(content_a.len() * 8),
+                            &(content_b.len() * 8),
+                        ),
+                    ]
+                    .into_iter()
+                    .flatten(),
+                );
+            }
+        }
+    }
+
+    for index in 0..8 {
+        if let (Some((_, content_a)), Some((_, content_b))) = (a.message(index), b.message(index)) {
+            if let Some(pixels) = pixel_diff(content_a, content_b) {
+                diff.pixels.push((index, pixels));
+            }
+        }
+    }
+    diff
+}
+
+/// Describe the difference of a header field, if the values differ
+fn field<T: fmt::Debug + PartialEq>(name: &str, a: &T, b: &T) -> Option<String> {
+    (a != b).then(|| format!("{name}: {a:?} != {b:?}"))
+}
+
+/// Render the pixels of both messages, if they differ
+fn pixel_diff(a: &[[u8; 11]], b: &[[u8; 11]]) -> Option<String> {
+    let pixel = |content: &[[u8; 11]], x: usize, y: usize| {
+        content
+            .get(x / 8)
+            .is_some_and(|block| block[y] & (0x80 >> (x % 8)) != 0)
+    };
+    let mut differ = false;
+    let mut out = String::new();
+    for y in 0..11 {
+        for x in 0..a.len().max(b.len()) * 8 {
+            let (on_a, on_b) = (pixel(a, x, y), pixel(b, x, y));
+            differ |= on_a != on_b;
+            out.push(match (on_a, on_b) {
+                (true, true) => 'X',
+                (false, false) => '_',
+                (true, false) => '-',
+                (false, true) => '+',
+            });
+        }
+        out.push('\n');
+    }
+    differ.then_some(out)
+}
+
+/// Format a timestamp as date and time
+#[derive(PartialEq)]
+struct Time(Timestamp);
+
+impl fmt::Debug for Time {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Timestamp {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        } = self.0;
+        write!(
+            f,
+            "{year:02}-{month:02}-{day:02} {hour:02}:{minute:02}:{second:02}"
+        )
+    }
+}
@@ -0,0 +1,310 @@
+//! Terminal pixel editor for bitstring messages
+//!
+//! Keys: arrows (or `hjkl`) move the cursor, space toggles a pixel,
+//! `n` adds a copy of the current frame, `x` deletes it, `Tab` / `Shift+Tab` switch frames,
+//! `c` clears the frame, `s` saves and `q` quits without saving.
+//! Multiple frames are saved side by side as a single message in `fast` mode,
+//! so only drawings at most as wide as the display can have more than one frame.
+
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, Read, Write},
+    mem::MaybeUninit,
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use badgemagic::{
+    config::{Config, Message},
+    protocol::{Mode, DISPLAY_WIDTH},
+};
+
+const HEIGHT: usize = 11;
+
+/// Columns of a frame in fast mode, the display width and the default frame gap
+const FRAME_WIDTH: usize = DISPLAY_WIDTH + 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Toggle,
+    NewFrame,
+    DeleteFrame,
+    NextFrame,
+    PreviousFrame,
+    Clear,
+    Save,
+    Quit,
+}
+
+/// Parse the keys of a chunk read from the terminal, unknown keys are ignored
+fn parse_keys(mut input: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    while let Some((&byte, rest)) = input.split_first() {
+        input = rest;
+        let key = match byte {
+            0x1b => {
+                let Some((sequence, rest)) = input.split_at_checked(2) else {
+                    continue;
+                };
+                input = rest;
+                match sequence {
+                    b"[A" | b"OA" => Key::Up,
+                    b"[B" | b"OB" => Key::Down,
+                    b"[C" | b"OC" => Key::Right,
+                    b"[D" | b"OD" => Key::Left,
+                    b"[Z" => Key::PreviousFrame,
+                    _ => continue,
+                }
+            }
+            b'k' => Key::Up,
+            b'j' => Key::Down,
+            b'l' => Key::Right,
+            b'h' => Key::Left,
+            b' ' => Key::Toggle,
+            b'n' => Key::NewFrame,
+            b'x' => Key::DeleteFrame,
+            b'\t' => Key::NextFrame,
+            b'c' => Key::Clear,
+            b's' => Key::Save,
+            // Ctrl+C, raw mode does not send a signal
+            b'q' | 0x03 => Key::Quit,
+            _ => continue,
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+struct Canvas {
+    width: usize,
+    frames: Vec<Vec<Vec<bool>>>,
+    frame: usize,
+    x: usize,
+    y: usize,
+}
+
+impl Canvas {
+    fn new(width: usize) -> Self {
+        Self {
+            width,
+            frames: vec![vec![vec![false; width]; HEIGHT]],
+            frame: 0,
+            x: 0,
+            y: 0,
+        }
+    }
+
+    fn apply(&mut self, key: Key) {
+        let frames = self.frames.len();
+        match key {
+            Key::Up => self.y = self.y.saturating_sub(1),
+            Key::Down => self.y = (self.y + 1).min(HEIGHT - 1),
+            Key::Left => self.x = self.x.saturating_sub(1),
+            Key::Right => self.x = (self.x + 1).min(self.width - 1),
+            Key::Toggle => {
+                let pixel = &mut self.frames[self.frame][self.y][self.x];
+                *pixel = !*pixel;
+            }
+            // frames wider than the display would be cut in fast mode
+            Key::NewFrame if self.width > DISPLAY_WIDTH => {}
+            Key::NewFrame => {
+                self.frames
+                    .insert(self.frame + 1, self.frames[self.frame].clone());
+                self.frame += 1;
+            }
+            Key::DeleteFrame if frames > 1 => {
+                self.frames.remove(self.frame);
+                self.frame = self.frame.min(frames - 2);
+            }
+            Key::DeleteFrame => self.frames[0] = vec![vec![false; self.width]; HEIGHT],
+            Key::NextFrame => self.frame = (self.frame + 1) % frames,
+            Key::PreviousFrame => self.frame = (self.frame + frames - 1) % frames,
+            Key::Clear => self.frames[self.frame] = vec![vec![false; self.width]; HEIGHT],
+            Key::Save | Key::Quit => {}
+        }
+    }
+
+    /// Return the frames side by side as bitstring (`X` = On, `_` = Off)
+    ///
+    /// Multiple frames start every [`FRAME_WIDTH`] columns, where fast mode shows them.
+    fn bitstring(&self) -> String {
+        let width = if self.frames.len() > 1 {
+            FRAME_WIDTH
+        } else {
+            self.width
+        };
+        (0..HEIGHT)
+            .map(|y| {
+                self.frames
+                    .iter()
+                    .flat_map(|frame| {
+                        let row = frame[y].iter().map(|&on| if on { 'X' } else { '_' });
+                        row.chain(std::iter::repeat('_')).take(width)
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Return the drawing as config with a single message
+    fn config(&self) -> Config {
+        let mut message = Message::bitstring(self.bitstring());
+        if self.frames.len() > 1 {
+            message = message.mode(Mode::Fast);
+        }
+        Config::default().message(message)
+    }
+
+    /// Render the current frame with the cursor highlighted and a help line
+    fn render(&self) -> String {
+        let mut out = String::from("\x1b[H\x1b[2J");
+        let _ = write!(out, "frame {} of {}\r\n", self.frame + 1, self.frames.len());
+        for (y, row) in self.frames[self.frame].iter().enumerate() {
+            for (x, &on) in row.iter().enumerate() {
+                let pixel = if on { "██" } else { "··" };
+                if (x, y) == (self.x, self.y) {
+                    let _ = write!(out, "\x1b[7m{pixel}\x1b[0m");
+                } else {
+                    out += pixel;
+                }
+            }
+            out += "\r\n";
+        }
+        out += "\r\narrows move, space toggles, n new frame, x delete frame, tab switch frame\r\n";
+        out += "c clear, s save, q quit\r\n";
+        out
+    }
+}
+
+/// Run the editor and save the drawing as config to `path`
+pub fn draw(width: usize, path: &Path) -> Result<()> {
+    anyhow::ensure!(width > 0, "width must be at least 1");
+    anyhow::ensure!(!path.exists(), "config already exists: {}", path.display());
+
+    let mut canvas = Canvas::new(width);
+    let saved = {
+        let _raw = RawMode::enable()?;
+        edit(&mut canvas)?
+    };
+    print!("\x1b[H\x1b[2J");
+    if !saved {
+        println!("Not saved");
+        return Ok(());
+    }
+
+    fs::write(path, toml::to_string_pretty(&canvas.config())?)
+        .with_context(|| format!("save config: {}", path.display()))?;
+    println!("Saved {}", path.display());
+    Ok(())
+}
+
+/// Handle keys until the drawing is saved (`true`) or the editor is quit (`false`)
+fn edit(canvas: &mut Canvas) -> Result<bool> {
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+    let mut input = [0; 64];
+    loop {
+        stdout.write_all(canvas.render().as_bytes())?;
+        stdout.flush()?;
+        let n = stdin.read(&mut input)?;
+        if n == 0 {
+            return Ok(false);
+        }
+        for key in parse_keys(&input[..n]) {
+            match key {
+                Key::Save => return Ok(true),
+                Key::Quit => return Ok(false),
+                key => canvas.apply(key),
+            }
+        }
+    }
+}
+
+/// Puts the terminal into raw mode until dropped
+struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    fn enable() -> Result<Self> {
+        let mut original = MaybeUninit::uninit();
+        // SAFETY: tcgetattr initializes the termios struct on success
+        let original = unsafe {
+            if libc::tcgetattr(libc::STDIN_FILENO, original.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error()).context("stdin is not a terminal");
+            }
+            original.assume_init()
+        };
+        let mut raw = original;
+        // SAFETY: raw is a valid termios struct
+        unsafe {
+            libc::cfmakeraw(&raw mut raw);
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw const raw) != 0 {
+                return Err(io::Error::last_os_error()).context("enable raw mode");
+            }
+        }
+        Ok(Self { original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: original was returned by tcgetattr
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw const self.original);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use badgemagic::simulator;
+
+    use super::{Canvas, Key};
+
+    /// Return the lit pixels of every frame shown by the badge
+    fn frames(canvas: &Canvas) -> Vec<Vec<(usize, usize)>> {
+        let payload = canvas.config().build_payload().unwrap();
+        let (style, content) = payload.message(0).unwrap();
+        simulator::message_frames(style, content)
+            .iter()
+            .map(|frame| {
+                (0..11)
+                    .flat_map(|y| (0..simulator::WIDTH).map(move |x| (x, y)))
+                    .filter(|&(x, y)| frame.get(x, y))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let mut canvas = Canvas::new(44);
+        canvas.apply(Key::Toggle);
+        canvas.apply(Key::NewFrame);
+        canvas.apply(Key::Toggle);
+        for _ in 0..43 {
+            canvas.apply(Key::Right);
+        }
+        canvas.apply(Key::Down);
+        canvas.apply(Key::Toggle);
+        assert_eq!(frames(&canvas), [vec![(0, 0)], vec![(43, 1)]]);
+
+        let mut canvas = Canvas::new(5);
+        canvas.apply(Key::NewFrame);
+        canvas.apply(Key::Right);
+        canvas.apply(Key::Toggle);
+        assert_eq!(frames(&canvas), [vec![], vec![(1, 0)]]);
+
+        // frames wider than the display can not be shown in fast mode
+        let mut canvas = Canvas::new(50);
+        canvas.apply(Key::NewFrame);
+        assert_eq!(canvas.frames.len(), 1);
+    }
+}
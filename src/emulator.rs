@@ -0,0 +1,440 @@
+//! Virtual badge to test the Bluetooth Low Energy transport without hardware
+//!
+//! The [`Emulator`] registers the badge service (`0xFEE0`) at `BlueZ`,
+//! advertises it with the `LSLED` name like a real badge
+//! and reassembles the chunks written to the badge characteristic (`0xFEE1`).
+//! It needs an adapter that supports the peripheral role,
+//! the badge has to be written from another adapter or machine.
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use badgemagic::{
+//!     emulator::Emulator,
+//!     protocol::{PayloadBuffer, Style},
+//! };
+//! # fn main() -> anyhow::Result<()> {
+//! let emulator = Emulator::start()?;
+//! let mut payload = PayloadBuffer::new();
+//! payload.add_message(Style::default(), 1);
+//! // write the payload over BLE, e.g. with `badgemagic --transport ble`
+//! let received = emulator.wait_payload(Duration::from_secs(30))?;
+//! assert_eq!(received.as_bytes(), payload.as_bytes());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! For the USB HID transport, see the [`gadget`] module.
+//! The [`Reassembler`] is available on all platforms, e.g. to check recorded chunks.
+
+use std::mem;
+
+use anyhow::{Context, Result};
+
+use crate::protocol::PayloadBuffer;
+
+#[cfg(target_os = "linux")]
+pub mod gadget;
+
+#[cfg(target_os = "linux")]
+pub use self::bluez::Emulator;
+
+/// Reassembles the chunks written to the badge characteristic into payloads
+#[derive(Debug, Default)]
+pub struct Reassembler {
+    data: Vec<u8>,
+}
+
+impl Reassembler {
+    /// Append a chunk and return the payload, if it is complete
+    ///
+    /// A chunk starting with the payload magic starts a new payload,
+    /// the data of an incomplete payload is dropped.
+    pub fn push(&mut self, chunk: &[u8]) -> Result<Option<PayloadBuffer>> {
+        if chunk.starts_with(b"wang") {
+            self.data.clear();
+        } else if self.data.is_empty() {
+            anyhow::bail!("chunk without payload header");
+        }
+        self.data.extend_from_slice(chunk);
+
+        match PayloadBuffer::padded_len(&self.data) {
+            Some(len) if self.data.len() >= len => {
+                let data = mem::take(&mut self.data);
+                PayloadBuffer::from_bytes(&data)
+                    .map(Some)
+                    .context("invalid payload received")
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod bluez {
+    use std::{
+        collections::{HashMap, VecDeque},
+        ffi::CString,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            mpsc, Arc, Condvar, Mutex, MutexGuard, PoisonError,
+        },
+        thread::{self, JoinHandle},
+        time::{Duration, Instant},
+    };
+
+    use anyhow::{Context, Result};
+    use dbus::{
+        arg::{PropMap, RefArg, Variant},
+        blocking::{stdintf::org_freedesktop_dbus::ObjectManager as _, Connection},
+        channel::{MatchingReceiver as _, Sender as _},
+        message::{MatchRule, MessageType},
+        strings::ErrorName,
+        Message, Path,
+    };
+
+    use super::Reassembler;
+    use crate::protocol::PayloadBuffer;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    const SERVICE_UUID: &str = "0000fee0-0000-1000-8000-00805f9b34fb";
+    const CHAR_UUID: &str = "0000fee1-0000-1000-8000-00805f9b34fb";
+    const DEVICE_NAME: &str = "LSLED";
+
+    const APP_PATH: &str = "/org/badgemagic/emulator";
+    const SERVICE_PATH: &str = "/org/badgemagic/emulator/service0";
+    const CHAR_PATH: &str = "/org/badgemagic/emulator/service0/char0";
+    const ADVERTISEMENT_PATH: &str = "/org/badgemagic/emulator/advertisement0";
+
+    const GATT_SERVICE: &str = "org.bluez.GattService1";
+    const GATT_CHAR: &str = "org.bluez.GattCharacteristic1";
+    const ADVERTISEMENT: &str = "org.bluez.LEAdvertisement1";
+    const GATT_MANAGER: &str = "org.bluez.GattManager1";
+    const ADVERTISING_MANAGER: &str = "org.bluez.LEAdvertisingManager1";
+
+    /// A virtual badge registered at `BlueZ` (Linux only)
+    ///
+    /// The badge is advertised until the emulator is dropped.
+    pub struct Emulator {
+        shared: Arc<Shared>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    #[derive(Default)]
+    struct Shared {
+        stop: AtomicBool,
+        state: Mutex<State>,
+        changed: Condvar,
+    }
+
+    #[derive(Default)]
+    struct State {
+        reassembler: Reassembler,
+        payloads: VecDeque<PayloadBuffer>,
+        replies: HashMap<u32, Result<(), String>>,
+        error: Option<String>,
+    }
+
+    impl Shared {
+        fn state(&self) -> MutexGuard<'_, State> {
+            self.state.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+    }
+
+    impl Emulator {
+        /// Start the emulator on the first adapter that supports the peripheral role
+        pub fn start() -> Result<Self> {
+            Self::spawn(None)
+        }
+
+        /// Start the emulator on the adapter called `adapter`, e.g. `hci1`
+        pub fn start_on(adapter: &str) -> Result<Self> {
+            Self::spawn(Some(format!("/org/bluez/{adapter}")))
+        }
+
+        fn spawn(adapter: Option<String>) -> Result<Self> {
+            let shared = Arc::<Shared>::default();
+            let (started, registered) = mpsc::channel();
+            let thread = thread::spawn({
+                let shared = Arc::clone(&shared);
+                move || {
+                    if let Err(err) = serve(&shared, adapter, &started) {
+                        // the error is reported by `start` if registration failed
+                        let message = format!("{err:#}");
+                        if started.send(Err(err)).is_err() {
+                            shared.state().error = Some(message);
+                            shared.changed.notify_all();
+                        }
+                    }
+                }
+            });
+            let emulator = Self {
+                shared,
+                thread: Some(thread),
+            };
+            registered
+                .recv()
+                .context("emulator stopped unexpectedly")??;
+            Ok(emulator)
+        }
+
+        /// Return the oldest payload received, if there is any
+        #[must_use]
+        pub fn try_payload(&self) -> Option<PayloadBuffer> {
+            self.shared.state().payloads.pop_front()
+        }
+
+        /// Wait for the oldest payload received
+        pub fn wait_payload(&self, timeout: Duration) -> Result<PayloadBuffer> {
+            let state = self.shared.state();
+            let (mut state, _) = self
+                .shared
+                .changed
+                .wait_timeout_while(state, timeout, |state| {
+                    state.payloads.is_empty() && state.error.is_none()
+                })
+                .unwrap_or_else(PoisonError::into_inner);
+            if let Some(payload) = state.payloads.pop_front() {
+                return Ok(payload);
+            }
+            match &state.error {
+                Some(err) => anyhow::bail!("emulator failed: {err}"),
+                None => anyhow::bail!("no payload received within {timeout:?}"),
+            }
+        }
+    }
+
+    impl Drop for Emulator {
+        fn drop(&mut self) {
+            self.shared.stop.store(true, Ordering::Relaxed);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    /// Register the badge at `BlueZ` and handle its method calls until stopped
+    ///
+    /// `BlueZ` removes the service and the advertisement when the connection is closed.
+    fn serve(
+        shared: &Arc<Shared>,
+        adapter: Option<String>,
+        started: &mpsc::Sender<Result<()>>,
+    ) -> Result<()> {
+        let conn = Connection::new_system().context("connect to the system bus")?;
+        let adapter = match adapter {
+            Some(adapter) => adapter,
+            None => find_adapter(&conn)?,
+        };
+
+        conn.start_receive(MatchRule::new(), {
+            let shared = Arc::clone(shared);
+            Box::new(move |msg, conn| {
+                dispatch(&shared, msg, conn);
+                true
+            })
+        });
+
+        // BlueZ queries the registered objects before replying, so the replies are awaited here
+        let register = |interface: &str, method: &str, path: &'static str| {
+            let msg = Message::new_method_call("org.bluez", adapter.as_str(), interface, method)
+                .map_err(anyhow::Error::msg)?
+                .append2(Path::from(path), PropMap::new());
+            conn.send(msg)
+                .map_err(|()| anyhow::anyhow!("send {method} request"))
+        };
+        let requests = [
+            (
+                "register service",
+                register(GATT_MANAGER, "RegisterApplication", APP_PATH)?,
+            ),
+            (
+                "register advertisement",
+                register(
+                    ADVERTISING_MANAGER,
+                    "RegisterAdvertisement",
+                    ADVERTISEMENT_PATH,
+                )?,
+            ),
+        ];
+        let deadline = Instant::now() + TIMEOUT;
+        for (action, serial) in requests {
+            loop {
+                if let Some(reply) = shared.state().replies.remove(&serial) {
+                    reply.map_err(anyhow::Error::msg).context(action)?;
+                    break;
+                }
+                anyhow::ensure!(Instant::now() < deadline, "{action}: timeout");
+                conn.process(Duration::from_millis(100))?;
+            }
+        }
+        let _ = started.send(Ok(()));
+
+        while !shared.stop.load(Ordering::Relaxed) {
+            conn.process(Duration::from_millis(100))?;
+        }
+        Ok(())
+    }
+
+    fn find_adapter(conn: &Connection) -> Result<String> {
+        let objects = conn
+            .with_proxy("org.bluez", "/", TIMEOUT)
+            .get_managed_objects()
+            .context("list Bluetooth adapters")?;
+        objects
+            .into_iter()
+            .filter(|(_, interfaces)| {
+                interfaces.contains_key(GATT_MANAGER)
+                    && interfaces.contains_key(ADVERTISING_MANAGER)
+            })
+            .map(|(path, _)| path.to_string())
+            .min()
+            .context("no Bluetooth adapter with peripheral support found")
+    }
+
+    fn dispatch(shared: &Shared, mut msg: Message, conn: &Connection) {
+        match msg.msg_type() {
+            MessageType::MethodCall => {
+                let reply = handle(shared, &msg).unwrap_or_else(|(name, message)| {
+                    let name = ErrorName::from(name);
+                    let message = CString::new(message).unwrap_or_default();
+                    msg.error(&name, &message)
+                });
+                if !msg.get_no_reply() {
+                    let _ = conn.send(reply);
+                }
+            }
+            MessageType::MethodReturn | MessageType::Error => {
+                if let Some(serial) = msg.get_reply_serial() {
+                    let result = msg.as_result().map(drop).map_err(|err| {
+                        format!(
+                            "{}: {}",
+                            err.name().unwrap_or("unknown error"),
+                            err.message().unwrap_or_default()
+                        )
+                    });
+                    shared.state().replies.insert(serial, result);
+                }
+            }
+            MessageType::Signal => {}
+        }
+    }
+
+    type CallResult = Result<Message, (&'static str, String)>;
+
+    fn handle(shared: &Shared, msg: &Message) -> CallResult {
+        let path = msg.path().map(|path| path.to_string()).unwrap_or_default();
+        let interface = msg.interface().map(|i| i.to_string()).unwrap_or_default();
+        let member = msg.member().map(|m| m.to_string()).unwrap_or_default();
+        let invalid_args = |err| ("org.freedesktop.DBus.Error.InvalidArgs", format!("{err}"));
+
+        match (interface.as_str(), member.as_str()) {
+            ("org.freedesktop.DBus.ObjectManager", "GetManagedObjects") if path == APP_PATH => {
+                let objects: HashMap<Path<'static>, HashMap<String, PropMap>> =
+                    [(SERVICE_PATH, GATT_SERVICE), (CHAR_PATH, GATT_CHAR)]
+                        .into_iter()
+                        .filter_map(|(path, interface)| {
+                            let properties = properties(path, interface)?;
+                            Some((
+                                Path::from(path),
+                                HashMap::from([(interface.into(), properties)]),
+                            ))
+                        })
+                        .collect();
+                Ok(msg.method_return().append1(objects))
+            }
+            ("org.freedesktop.DBus.Properties", "GetAll") => {
+                let interface: &str = msg.read1().map_err(invalid_args)?;
+                let properties = properties(&path, interface).unwrap_or_default();
+                Ok(msg.method_return().append1(properties))
+            }
+            ("org.freedesktop.DBus.Properties", "Get") => {
+                let (interface, name): (&str, &str) = msg.read2().map_err(invalid_args)?;
+                let value = properties(&path, interface)
+                    .and_then(|mut properties| properties.remove(name))
+                    .ok_or_else(|| {
+                        (
+                            "org.freedesktop.DBus.Error.UnknownProperty",
+                            format!("unknown property: {name}"),
+                        )
+                    })?;
+                Ok(msg.method_return().append1(value))
+            }
+            (GATT_CHAR, "WriteValue") if path == CHAR_PATH => {
+                let value: Vec<u8> = msg.read1().map_err(invalid_args)?;
+                let mut state = shared.state();
+                match state.reassembler.push(&value) {
+                    Ok(Some(payload)) => {
+                        state.payloads.push_back(payload);
+                        shared.changed.notify_all();
+                    }
+                    Ok(None) => {}
+                    Err(err) => return Err(("org.bluez.Error.Failed", format!("{err:#}"))),
+                }
+                Ok(msg.method_return())
+            }
+            (ADVERTISEMENT, "Release") if path == ADVERTISEMENT_PATH => Ok(msg.method_return()),
+            _ => Err((
+                "org.freedesktop.DBus.Error.UnknownMethod",
+                format!("unknown method: {interface}.{member} on {path}"),
+            )),
+        }
+    }
+
+    /// Return the properties of the `interface` of the object at `path`
+    fn properties(path: &str, interface: &str) -> Option<PropMap> {
+        let mut properties = PropMap::new();
+        let mut set = |name: &str, value: Box<dyn RefArg>| {
+            properties.insert(name.into(), Variant(value));
+        };
+        match (path, interface) {
+            (SERVICE_PATH, GATT_SERVICE) => {
+                set("UUID", Box::new(SERVICE_UUID.to_string()));
+                set("Primary", Box::new(true));
+            }
+            (CHAR_PATH, GATT_CHAR) => {
+                set("UUID", Box::new(CHAR_UUID.to_string()));
+                set("Service", Box::new(Path::from(SERVICE_PATH)));
+                set(
+                    "Flags",
+                    Box::new(vec![
+                        "write".to_string(),
+                        "write-without-response".to_string(),
+                    ]),
+                );
+            }
+            (ADVERTISEMENT_PATH, ADVERTISEMENT) => {
+                set("Type", Box::new("peripheral".to_string()));
+                set("ServiceUUIDs", Box::new(vec![SERVICE_UUID.to_string()]));
+                set("LocalName", Box::new(DEVICE_NAME.to_string()));
+            }
+            _ => return None,
+        }
+        Some(properties)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Reassembler;
+    use crate::protocol::{PayloadBuffer, State, Style};
+
+    #[test]
+    fn reassemble_chunks() {
+        let mut payload = PayloadBuffer::new();
+        payload
+            .add_message(Style::default(), 2)
+            .set((3, 4), State::On);
+        let data = payload.clone().into_padded_bytes();
+
+        let mut reassembler = Reassembler::default();
+        let mut received = Vec::new();
+        for chunk in data.as_ref().chunks(16) {
+            received.extend(reassembler.push(chunk).unwrap());
+        }
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].as_bytes(), payload.as_bytes());
+
+        assert!(reassembler.push(&[0; 16]).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+//! Virtual USB badge created with the Linux USB gadget API
+//!
+//! The [`Gadget`] registers a HID function with the vendor and product id of the badge
+//! in configfs and binds it to a USB device controller.
+//! On machines without gadget hardware the `dummy_hcd` module provides a controller
+//! that connects the gadget to the local host, so [`usb_hid`](crate::usb_hid) finds it like a real badge.
+//! Creating the gadget needs root permissions and a mounted configfs.
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use badgemagic::emulator::gadget::Gadget;
+//! # fn main() -> anyhow::Result<()> {
+//! let mut gadget = Gadget::create()?;
+//! // write a payload over USB, e.g. with `badgemagic --transport usb`
+//! let received = gadget.wait_payload(Duration::from_secs(10))?;
+//! println!("{} bytes received", received.as_bytes().len());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read as _},
+    os::unix::fs::{FileTypeExt as _, MetadataExt as _, OpenOptionsExt as _},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+
+use super::Reassembler;
+use crate::protocol::{PayloadBuffer, USB_REPORT_SIZE};
+
+const CONFIGFS: &str = "/sys/kernel/config/usb_gadget";
+const NAME: &str = "badgemagic";
+const SERIAL_NUMBER: &str = "badgemagic-emulator";
+
+/// Vendor defined HID report descriptor with 64 byte input and output reports
+const REPORT_DESCRIPTOR: [u8; 25] = [
+    0x06, 0x00, 0xff, // usage page (vendor defined)
+    0x09, 0x01, // usage (1)
+    0xa1, 0x01, // collection (application)
+    0x09, 0x02, //   usage (2)
+    0x15, 0x00, //   logical minimum (0)
+    0x26, 0xff, 0x00, //   logical maximum (255)
+    0x75, 0x08, //   report size (8)
+    0x95, 0x40, //   report count (64)
+    0x81, 0x02, //   input (data, variable, absolute)
+    0x09, 0x03, //   usage (3)
+    0x91, 0x02, //   output (data, variable, absolute)
+    0xc0, // end collection
+];
+
+/// A virtual badge bound to a USB device controller (Linux only)
+///
+/// The gadget is removed when it is dropped.
+pub struct Gadget {
+    dir: PathBuf,
+    device: Option<File>,
+    reassembler: Reassembler,
+}
+
+impl Gadget {
+    /// Create the gadget on the first USB device controller
+    pub fn create() -> Result<Self> {
+        let udc = fs::read_dir("/sys/class/udc")
+            .context("list USB device controllers")?
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .min()
+            .context("no USB device controller found (load e.g. the dummy_hcd module)")?;
+        Self::create_on(&udc)
+    }
+
+    /// Create the gadget on the USB device controller `udc`, e.g. `dummy_udc.0`
+    pub fn create_on(udc: &str) -> Result<Self> {
+        let dir = Path::new(CONFIGFS).join(NAME);
+        create_dir(&dir)?;
+        let mut gadget = Self {
+            dir,
+            device: None,
+            reassembler: Reassembler::default(),
+        };
+        // a partially created gadget is removed on drop
+        gadget.setup(udc)?;
+        gadget.device = Some(gadget.open_device()?);
+        Ok(gadget)
+    }
+
+    /// Return the serial number the gadget reports to the host
+    #[must_use]
+    pub fn serial_number(&self) -> &'static str {
+        SERIAL_NUMBER
+    }
+
+    /// Wait for the next payload written by the host
+    #[allow(clippy::missing_panics_doc)] // the device is opened on creation
+    pub fn wait_payload(&mut self, timeout: Duration) -> Result<PayloadBuffer> {
+        let device = self.device.as_mut().unwrap();
+        let deadline = Instant::now() + timeout;
+        let mut report = [0; USB_REPORT_SIZE];
+        loop {
+            match device.read(&mut report) {
+                Ok(n) => {
+                    if let Some(payload) = self.reassembler.push(&report[..n])? {
+                        return Ok(payload);
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    anyhow::ensure!(
+                        Instant::now() < deadline,
+                        "no payload received within {timeout:?}"
+                    );
+                    thread::sleep(Duration::from_millis(10));
+                }
+                Err(err) => return Err(err).context("read from gadget"),
+            }
+        }
+    }
+
+    fn setup(&self, udc: &str) -> Result<()> {
+        let dir = &self.dir;
+        let function = dir.join("functions/hid.usb0");
+        let config = dir.join("configs/c.1");
+
+        write(&dir.join("idVendor"), "0x0416")?;
+        write(&dir.join("idProduct"), "0x5020")?;
+        write(&dir.join("bcdUSB"), "0x0200")?;
+        create_dir(&dir.join("strings/0x409"))?;
+        write(&dir.join("strings/0x409/serialnumber"), SERIAL_NUMBER)?;
+        write(&dir.join("strings/0x409/manufacturer"), "badgemagic")?;
+        write(&dir.join("strings/0x409/product"), "LED Badge Emulator")?;
+
+        create_dir(&function)?;
+        write(&function.join("protocol"), "0")?;
+        write(&function.join("subclass"), "0")?;
+        write(&function.join("report_length"), USB_REPORT_SIZE.to_string())?;
+        write(&function.join("report_desc"), REPORT_DESCRIPTOR)?;
+
+        create_dir(&config)?;
+        create_dir(&config.join("strings/0x409"))?;
+        write(&config.join("strings/0x409/configuration"), "badge")?;
+        std::os::unix::fs::symlink(&function, config.join("hid.usb0"))
+            .context("add HID function to configuration")?;
+
+        write(&dir.join("UDC"), udc)
+    }
+
+    /// Open the device node of the HID function, e.g. `/dev/hidg0`
+    fn open_device(&self) -> Result<File> {
+        let number = fs::read_to_string(self.dir.join("functions/hid.usb0/dev"))
+            .context("read HID function device number")?;
+        let (major, minor) = number
+            .trim()
+            .split_once(':')
+            .and_then(|(major, minor)| Some((major.parse().ok()?, minor.parse().ok()?)))
+            .context("invalid HID function device number")?;
+        let rdev = libc::makedev(major, minor);
+
+        let path = fs::read_dir("/dev")
+            .context("list devices")?
+            .filter_map(Result::ok)
+            .find(|entry| {
+                entry.file_name().to_string_lossy().starts_with("hidg")
+                    && entry.metadata().is_ok_and(|metadata| {
+                        metadata.file_type().is_char_device() && metadata.rdev() == rdev
+                    })
+            })
+            .context("HID gadget device not found")?
+            .path();
+        OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)
+            .with_context(|| format!("open gadget: {}", path.display()))
+    }
+}
+
+impl Drop for Gadget {
+    fn drop(&mut self) {
+        self.device = None;
+        let dir = &self.dir;
+        // removal is best effort, parts of the gadget may not exist
+        let _ = fs::write(dir.join("UDC"), "\n");
+        let _ = fs::remove_file(dir.join("configs/c.1/hid.usb0"));
+        for path in [
+            "configs/c.1/strings/0x409",
+            "configs/c.1",
+            "functions/hid.usb0",
+            "strings/0x409",
+            "",
+        ] {
+            let _ = fs::remove_dir(dir.join(path));
+        }
+    }
+}
+
+fn create_dir(path: &Path) -> Result<()> {
+    fs::create_dir(path).with_context(|| format!("create gadget: {}", path.display()))
+}
+
+fn write(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    fs::write(path, contents).with_context(|| format!("configure gadget: {}", path.display()))
+}
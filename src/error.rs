@@ -0,0 +1,256 @@
+//! Errors returned by the protocol and the transports
+//!
+//! Higher level helpers (the config, the image output of the simulator and the emulator)
+//! return [`anyhow`](https://docs.rs/anyhow) errors, `anyhow` is only a dependency with their features.
+
+use std::io;
+
+/// Result type of the protocol and the transports
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Error returned by the protocol and the transports
+///
+/// ```
+/// # #[cfg(feature = "usb-hid")]
+/// # fn main() {
+/// use badgemagic::{protocol::PayloadBuffer, usb_hid::Device, Error};
+///
+/// match Device::single().and_then(|device| device.write(PayloadBuffer::new())) {
+///     Ok(()) => println!("done"),
+///     Err(Error::NoDevice) => println!("please connect the badge"),
+///     Err(err) => println!("{err}"),
+/// }
+/// # }
+/// # #[cfg(not(feature = "usb-hid"))]
+/// # fn main() {}
+/// ```
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// No supported badge was found
+    #[error("no device found")]
+    NoDevice,
+
+    /// More than one supported badge was found, but a single one was requested
+    #[error("multiple devices found")]
+    MultipleDevices,
+
+    /// There is no Bluetooth adapter
+    #[error("no bluetooth adapter found")]
+    NoAdapter,
+
+    /// The badge does not have the expected characteristic to write the payload to
+    #[error("badge characteristic not found")]
+    MissingCharacteristic,
+
+    /// The payload is larger than the badge supports ([`MAX_PAYLOAD_SIZE`](crate::protocol::MAX_PAYLOAD_SIZE))
+    #[error("payload too long: {0} bytes (max {max} bytes)", max = crate::protocol::MAX_PAYLOAD_SIZE)]
+    PayloadTooLarge(usize),
+
+    /// More messages were supplied than the badge supports (8 messages)
+    #[error("too many messages: {0} (max 8)")]
+    TooManyMessages(usize),
+
+    /// A pixel was drawn outside of a message in [`DrawMode::Strict`](crate::protocol::DrawMode::Strict)
+    #[error("tried to draw pixel outside the display area (x: {x}, y: {y})")]
+    OutOfBounds {
+        /// Column of the pixel
+        x: i32,
+
+        /// Row of the pixel
+        y: i32,
+    },
+
+    /// A payload could not be parsed
+    #[error("invalid payload: {0}")]
+    InvalidPayload(String),
+
+    /// The operating system denied access to the USB device
+    #[error("{context}")]
+    UsbPermissionDenied {
+        /// What was being done, e.g. `open device`
+        context: &'static str,
+
+        /// The underlying error
+        #[source]
+        source: io::Error,
+    },
+
+    /// The operating system denied access to Bluetooth
+    #[error("{context}")]
+    BluetoothUnauthorized {
+        /// What was being done, e.g. `create BLE manager`
+        context: &'static str,
+
+        /// The underlying error
+        #[source]
+        source: io::Error,
+    },
+
+    /// The Bluetooth connection was aborted, e.g. `le-connection-abort-by-local` on Linux
+    #[error("{context}")]
+    ConnectionAborted {
+        /// What was being done, e.g. `bluetooth device connect`
+        context: &'static str,
+
+        /// The underlying error
+        #[source]
+        source: io::Error,
+    },
+
+    /// Communicating with the badge or the operating system failed
+    #[error("{context}")]
+    Transfer {
+        /// What was being done, e.g. `open device`
+        context: &'static str,
+
+        /// The underlying error
+        #[source]
+        source: io::Error,
+    },
+}
+
+impl Error {
+    /// Return a hint how to resolve the error, if there is a common cause
+    #[must_use]
+    pub fn hint(&self) -> Option<&'static str> {
+        Some(match self {
+            Self::NoDevice => {
+                "check that the badge is switched on, for Bluetooth it has to be in transfer mode"
+            }
+            Self::MultipleDevices => {
+                "connect a single badge or select the badges in [[device]] sections of the config"
+            }
+            Self::NoAdapter => "check that Bluetooth is enabled",
+            Self::UsbPermissionDenied { .. } if cfg!(target_os = "linux") => {
+                "allow access to the badge with a udev rule, e.g. in /etc/udev/rules.d/99-badgemagic.rules: \
+                 KERNEL==\"hidraw*\", ATTRS{idVendor}==\"0416\", ATTRS{idProduct}==\"5020\", TAG+=\"uaccess\""
+            }
+            Self::UsbPermissionDenied { .. } => {
+                "close other programs using the badge or run with administrator rights"
+            }
+            Self::BluetoothUnauthorized { .. } if cfg!(target_os = "macos") => {
+                "allow Bluetooth access for your terminal in System Settings > Privacy & Security > Bluetooth"
+            }
+            Self::BluetoothUnauthorized { .. } => {
+                "check that your user is allowed to use Bluetooth, e.g. is in the bluetooth group"
+            }
+            Self::ConnectionAborted { .. } => {
+                "move the badge closer and retry, restart Bluetooth if the error persists"
+            }
+            _ => return None,
+        })
+    }
+
+    /// Wrap the error of a transport, with `context` describing what was being done
+    ///
+    /// Common causes are recognized and returned as their own variants.
+    #[cfg_attr(not(any(feature = "usb-hid", feature = "ble")), allow(dead_code))]
+    pub(crate) fn transfer<E>(context: &'static str) -> impl FnOnce(E) -> Self
+    where
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        move |source| {
+            let source = source.into();
+            let diagnosis = Diagnosis::of(&*source);
+            let source = match source.downcast::<io::Error>() {
+                Ok(source) => *source,
+                Err(source) => io::Error::other(source),
+            };
+            match diagnosis {
+                #[cfg(feature = "usb-hid")]
+                Some(Diagnosis::UsbPermissionDenied) => {
+                    Self::UsbPermissionDenied { context, source }
+                }
+                Some(Diagnosis::BluetoothUnauthorized) => {
+                    Self::BluetoothUnauthorized { context, source }
+                }
+                Some(Diagnosis::ConnectionAborted) => Self::ConnectionAborted { context, source },
+                None => Self::Transfer { context, source },
+            }
+        }
+    }
+}
+
+/// Common causes of transport errors
+#[derive(Debug, PartialEq, Eq)]
+enum Diagnosis {
+    #[cfg(feature = "usb-hid")]
+    UsbPermissionDenied,
+    BluetoothUnauthorized,
+    ConnectionAborted,
+}
+
+impl Diagnosis {
+    /// Recognize the cause of `error` or any of its sources
+    fn of(error: &(dyn std::error::Error + 'static)) -> Option<Self> {
+        let mut error = Some(error);
+        while let Some(current) = error {
+            #[cfg(feature = "usb-hid")]
+            if let Some(err) = current.downcast_ref::<hidapi::HidError>() {
+                let denied = match err {
+                    hidapi::HidError::IoError { error } => {
+                        error.kind() == io::ErrorKind::PermissionDenied
+                    }
+                    err => {
+                        let message = err.to_string().to_lowercase();
+                        ["permission denied", "access denied", "access is denied"]
+                            .iter()
+                            .any(|pattern| message.contains(pattern))
+                    }
+                };
+                if denied {
+                    return Some(Self::UsbPermissionDenied);
+                }
+            }
+
+            #[cfg(feature = "ble")]
+            if let Some(btleplug::Error::PermissionDenied) = current.downcast_ref() {
+                return Some(Self::BluetoothUnauthorized);
+            }
+
+            let message = current.to_string();
+            if message.contains("le-connection-abort") {
+                return Some(Self::ConnectionAborted);
+            }
+            if message.contains("org.bluez.Error.NotPermitted")
+                || message.contains("org.freedesktop.DBus.Error.AccessDenied")
+                || message.contains("CBManagerStateUnauthorized")
+            {
+                return Some(Self::BluetoothUnauthorized);
+            }
+
+            error = current.source();
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Error;
+
+    #[test]
+    fn diagnose_bluez_errors() {
+        let err = Error::transfer::<&str>("bluetooth device connect")(
+            "org.bluez.Error.Failed: le-connection-abort-by-local",
+        );
+        assert!(matches!(err, Error::ConnectionAborted { .. }));
+        assert!(err.hint().is_some());
+
+        let err = Error::transfer::<&str>("writing payload chunk")("timeout");
+        assert!(matches!(err, Error::Transfer { .. }));
+        assert_eq!(err.hint(), None);
+    }
+
+    #[cfg(feature = "usb-hid")]
+    #[test]
+    fn diagnose_hidapi_permission_denied() {
+        use super::Diagnosis;
+
+        let err = hidapi::HidError::HidApiError {
+            message: "Permission denied".into(),
+        };
+        assert_eq!(Diagnosis::of(&err), Some(Diagnosis::UsbPermissionDenied));
+    }
+}
@@ -0,0 +1,269 @@
+//! C compatible interface, see `include/badgemagic.h`
+//!
+//! Build the shared library with
+//! `cargo rustc --release --lib --features ffi --crate-type cdylib`.
+//!
+//! All functions return [`BADGEMAGIC_OK`] on success and a negative error code on failure,
+//! a description of the last error on the current thread is returned by [`badgemagic_last_error`].
+//! Panics do not unwind into the caller, they are reported as [`BADGEMAGIC_ERROR_PANIC`].
+
+use std::{
+    any::Any,
+    cell::RefCell,
+    ffi::{c_char, c_int, CStr, CString},
+    fmt::Display,
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    ble,
+    config::{Config, Message},
+    protocol::{Mode, PayloadBuffer, Speed},
+    usb_hid,
+};
+
+/// The function succeeded
+pub const BADGEMAGIC_OK: c_int = 0;
+
+/// A required pointer argument was null
+pub const BADGEMAGIC_ERROR_NULL_POINTER: c_int = -1;
+
+/// An argument was invalid, e.g. a string that is not UTF-8 or an unknown mode
+pub const BADGEMAGIC_ERROR_INVALID_ARGUMENT: c_int = -2;
+
+/// The messages could not be rendered, e.g. because there are more than 8
+pub const BADGEMAGIC_ERROR_RENDER: c_int = -3;
+
+/// The payload could not be written to the badge
+pub const BADGEMAGIC_ERROR_TRANSPORT: c_int = -4;
+
+/// The function panicked, which is a bug in badgemagic
+pub const BADGEMAGIC_ERROR_PANIC: c_int = -5;
+
+/// Flag for [`badgemagic_payload_add_text`]: blink the message
+pub const BADGEMAGIC_BLINK: u32 = 1;
+
+/// Flag for [`badgemagic_payload_add_text`]: show an animated border around the message
+pub const BADGEMAGIC_BORDER: u32 = 2;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Messages to be written to a badge
+pub struct BadgemagicPayload {
+    config: Config,
+}
+
+/// Create an empty payload, to be released with [`badgemagic_payload_free`]
+///
+/// Returns null if creating the payload panicked.
+#[no_mangle]
+pub extern "C" fn badgemagic_payload_new() -> *mut BadgemagicPayload {
+    panic::catch_unwind(|| {
+        Box::into_raw(Box::new(BadgemagicPayload {
+            config: Config::default(),
+        }))
+    })
+    .unwrap_or_else(|panic| {
+        fail(BADGEMAGIC_ERROR_PANIC, panic_message(&*panic));
+        ptr::null_mut()
+    })
+}
+
+/// Release a payload created by [`badgemagic_payload_new`]
+///
+/// # Safety
+///
+/// `payload` must be null or a pointer returned by [`badgemagic_payload_new`]
+/// that was not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn badgemagic_payload_free(payload: *mut BadgemagicPayload) {
+    if !payload.is_null() {
+        // SAFETY: guaranteed by the caller
+        let payload = unsafe { Box::from_raw(payload) };
+        // there is nothing to report a panic to, but it must not unwind into the caller
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(payload)));
+    }
+}
+
+/// Append a text message
+///
+/// `mode` and `speed` are the numeric values of the protocol (`0` to `8` and `0` to `7`),
+/// `flags` is a combination of [`BADGEMAGIC_BLINK`] and [`BADGEMAGIC_BORDER`].
+///
+/// # Safety
+///
+/// `payload` must be null or a valid payload, `text` must be null or a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn badgemagic_payload_add_text(
+    payload: *mut BadgemagicPayload,
+    text: *const c_char,
+    mode: u8,
+    speed: u8,
+    flags: u32,
+) -> c_int {
+    // SAFETY: guaranteed by the caller
+    let (Some(payload), false) = (unsafe { payload.as_mut() }, text.is_null()) else {
+        return fail(BADGEMAGIC_ERROR_NULL_POINTER, "null pointer");
+    };
+    // SAFETY: guaranteed by the caller
+    let text = unsafe { CStr::from_ptr(text) };
+    catch_panic(|| {
+        let message = (|| -> Result<Message> {
+            let text = text.to_str().context("text is not valid UTF-8")?;
+            let mode = Mode::try_from(mode).with_context(|| format!("invalid mode: {mode}"))?;
+            let speed =
+                Speed::try_from(speed).with_context(|| format!("invalid speed: {speed}"))?;
+            let mut message = Message::text(text).mode(mode).speed(speed);
+            if flags & BADGEMAGIC_BLINK != 0 {
+                message = message.blink();
+            }
+            if flags & BADGEMAGIC_BORDER != 0 {
+                message = message.border();
+            }
+            Ok(message)
+        })();
+        match message {
+            Ok(message) => {
+                payload.config = std::mem::take(&mut payload.config).message(message);
+                BADGEMAGIC_OK
+            }
+            Err(err) => fail(BADGEMAGIC_ERROR_INVALID_ARGUMENT, err),
+        }
+    })
+}
+
+/// Write the payload to the single badge connected over USB
+///
+/// # Safety
+///
+/// `payload` must be null or a valid payload.
+#[no_mangle]
+pub unsafe extern "C" fn badgemagic_write_usb(payload: *const BadgemagicPayload) -> c_int {
+    // SAFETY: guaranteed by the caller
+    unsafe { write(payload, |payload| usb_hid::Device::single()?.write(payload)) }
+}
+
+/// Write the payload to the single badge in range over Bluetooth Low Energy
+///
+/// Blocks the calling thread while scanning for the badge and writing the payload.
+///
+/// # Safety
+///
+/// `payload` must be null or a valid payload.
+#[no_mangle]
+pub unsafe extern "C" fn badgemagic_write_ble_blocking(payload: *const BadgemagicPayload) -> c_int {
+    // SAFETY: guaranteed by the caller
+    unsafe {
+        write(payload, |payload| {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .map_err(crate::Error::transfer("create runtime"))?
+                .block_on(async { ble::Device::single().await?.write(payload).await })
+        })
+    }
+}
+
+/// Description of the last error on the current thread, or null if there was none
+///
+/// The string is valid until the next call of a `badgemagic_` function on the same thread.
+#[no_mangle]
+pub extern "C" fn badgemagic_last_error() -> *const c_char {
+    LAST_ERROR.with_borrow(|error| error.as_ref().map_or(ptr::null(), |error| error.as_ptr()))
+}
+
+/// Build the payload and pass it to `writer`
+///
+/// # Safety
+///
+/// `payload` must be null or a valid payload.
+unsafe fn write(
+    payload: *const BadgemagicPayload,
+    writer: impl FnOnce(PayloadBuffer) -> crate::Result<()>,
+) -> c_int {
+    // SAFETY: guaranteed by the caller
+    let Some(payload) = (unsafe { payload.as_ref() }) else {
+        return fail(BADGEMAGIC_ERROR_NULL_POINTER, "null pointer");
+    };
+    catch_panic(|| {
+        let payload = match payload.config.build_payload() {
+            Ok(payload) => payload,
+            Err(err) => return fail(BADGEMAGIC_ERROR_RENDER, err),
+        };
+        match writer(payload) {
+            Ok(()) => BADGEMAGIC_OK,
+            Err(err) => fail(BADGEMAGIC_ERROR_TRANSPORT, err),
+        }
+    })
+}
+
+/// Call `f`, a panic is returned as [`BADGEMAGIC_ERROR_PANIC`] instead of unwinding into C
+fn catch_panic(f: impl FnOnce() -> c_int) -> c_int {
+    // the state touched by `f` is not used after a panic, except to be released
+    panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|panic| fail(BADGEMAGIC_ERROR_PANIC, panic_message(&*panic)))
+}
+
+/// Describe the payload of a caught panic
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    let message = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause");
+    format!("panic: {message}")
+}
+
+/// Remember `error` for [`badgemagic_last_error`] and return `code`
+fn fail(code: c_int, error: impl Display) -> c_int {
+    let message = format!("{error:#}").replace('\0', " ");
+    LAST_ERROR.set(CString::new(message).ok());
+    code
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CStr;
+
+    use super::*;
+
+    #[test]
+    fn add_text() {
+        let payload = badgemagic_payload_new();
+        unsafe {
+            assert_eq!(
+                badgemagic_payload_add_text(payload, c"Hello".as_ptr(), 4, 3, BADGEMAGIC_BLINK),
+                BADGEMAGIC_OK
+            );
+            assert_eq!(
+                badgemagic_payload_add_text(payload, c"World".as_ptr(), 42, 3, 0),
+                BADGEMAGIC_ERROR_INVALID_ARGUMENT
+            );
+            assert_eq!(
+                CStr::from_ptr(badgemagic_last_error()).to_str().unwrap(),
+                "invalid mode: 42: out of range integral type conversion attempted"
+            );
+            assert_eq!((*payload).config.messages.len(), 1);
+            badgemagic_payload_free(payload);
+        }
+        assert_eq!(
+            unsafe { badgemagic_write_usb(ptr::null()) },
+            BADGEMAGIC_ERROR_NULL_POINTER
+        );
+    }
+
+    #[test]
+    fn catch_panics() {
+        assert_eq!(catch_panic(|| panic!("oops")), BADGEMAGIC_ERROR_PANIC);
+        assert_eq!(
+            unsafe { CStr::from_ptr(badgemagic_last_error()) }.to_str(),
+            Ok("panic: oops")
+        );
+        assert_eq!(catch_panic(|| BADGEMAGIC_OK), BADGEMAGIC_OK);
+    }
+}
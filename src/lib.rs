@@ -1,17 +1,53 @@
 #![warn(clippy::all, clippy::pedantic)]
 #![allow(clippy::missing_errors_doc)]
 
+/// Emit a [`tracing`](https://docs.rs/tracing) event, if the `tracing` feature is enabled
+macro_rules! event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::event!($($arg)*);
+    };
+}
+
+mod error;
+
 pub mod protocol;
 
+pub mod simulator;
+
+pub mod diff;
+
 #[cfg(feature = "usb-hid")]
 pub mod usb_hid;
 
 #[cfg(feature = "ble")]
 pub mod ble;
 
+#[cfg(any(feature = "usb-hid", feature = "ble"))]
+pub mod record;
+
+#[cfg(feature = "emulator")]
+pub mod emulator;
+
 #[cfg(feature = "embedded-graphics")]
 pub mod util;
 
+#[cfg(feature = "config")]
+pub mod config;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+pub mod prelude;
+
+#[cfg(all(
+    feature = "embedded-graphics",
+    any(feature = "usb-hid", feature = "ble")
+))]
+pub mod quick;
+
+pub use error::{Error, Result};
+
 #[cfg(feature = "embedded-graphics")]
 pub use embedded_graphics;
 
@@ -1,24 +1,50 @@
 #![warn(clippy::all, clippy::pedantic)]
 
-use std::{fs, path::PathBuf};
+use std::{
+    fs, io,
+    net::TcpListener,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{atomic::AtomicBool, OnceLock},
+    thread,
+    time::Duration,
+};
 
 use anyhow::{Context, Result};
 use badgemagic::{
-    ble::Device as BleDevice,
-    protocol::{Mode, PayloadBuffer, Speed, Style},
+    ble::{Device as BleDevice, Session as BleSession},
+    config::{
+        app,
+        cache::PayloadCache,
+        registry::{self, Registry},
+        Config, DeviceConfig,
+    },
+    protocol::{self, Mode, PayloadBuffer},
+    record, simulator,
     usb_hid::Device as UsbDevice,
 };
-use base64::Engine;
-use clap::{Parser, ValueEnum};
-use embedded_graphics::{
-    geometry::Point,
-    image::{Image, ImageRawLE},
-    mono_font::{iso_8859_1::FONT_6X9, MonoTextStyle},
-    pixelcolor::BinaryColor,
-    text::Text,
-    Drawable, Pixel,
-};
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
+use schedule::Schedule;
 use serde::Deserialize;
+use terminal::Graphics;
+
+mod agent;
+mod convert;
+#[cfg(unix)]
+mod draw;
+#[cfg(unix)]
+mod pipe;
+mod schedule;
+#[cfg(feature = "serve")]
+mod serve;
+#[cfg(all(feature = "systemd", unix))]
+mod systemd;
+mod terminal;
+mod timezone;
+
+/// How often long running commands check whether they should shut down
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(200);
 
 #[derive(Parser)]
 /// Upload a configuration with up to 8 messages to an LED badge
@@ -33,15 +59,87 @@ use serde::Deserialize;
 
 {all-args}{after-help}
     ",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
 )]
+#[allow(clippy::struct_excessive_bools)] // independent command line flags
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// File format of the config file (toml, json)
     #[clap(long)]
     format: Option<String>,
 
     /// Transport protocol to use
+    #[clap(long, required_unless_present_any = ["device", "group", "all", "dry_run"])]
+    transport: Option<TransportProtocol>,
+
+    /// Nickname of the badge to write to, saved with `device add`
+    #[clap(long, conflicts_with_all = ["transport", "list_devices"])]
+    device: Option<String>,
+
+    /// Group of saved badges to write to, created with `device group`
+    #[clap(long, conflicts_with_all = ["transport", "list_devices", "device"])]
+    group: Option<String>,
+
+    /// Write to every badge found, over USB and BLE unless --transport is given
+    #[clap(long, conflicts_with_all = ["list_devices", "device", "group"])]
+    all: bool,
+
+    /// Write to all badges at the same time instead of one after another (with --all)
+    #[clap(long, requires = "all")]
+    concurrent: bool,
+
+    /// Rotate all messages by 180 degrees (for badges worn upside down)
     #[clap(long)]
-    transport: TransportProtocol,
+    rotate_180: bool,
+
+    /// Write even if the badge already shows the payload
+    #[clap(long, global = true)]
+    force: bool,
+
+    /// Append every chunk written to a badge to this file (see `replay`)
+    #[clap(long, global = true)]
+    record: Option<PathBuf>,
+
+    /// Warn about messages wider than this number of 44 column screens (except in fast mode)
+    #[clap(long, default_value_t = 10)]
+    max_screens: usize,
+
+    /// Fail instead of warning about messages wider than --max-screens
+    #[clap(long)]
+    strict: bool,
+
+    /// Print the width and scroll duration of every message instead of writing
+    #[clap(long, conflicts_with = "list_devices")]
+    dry_run: bool,
+
+    /// Keep running and write the config again at this interval, e.g. `30m` or `1h`
+    /// (the config is reloaded every time)
+    #[clap(long, value_parser = parse_duration, conflicts_with_all = ["list_devices", "dry_run", "all"])]
+    repeat_every: Option<Duration>,
+
+    /// Only write again with --repeat-every when the payload changed
+    #[clap(long, requires = "repeat_every")]
+    only_changed: bool,
+
+    /// Write the timestamp of the payload in UTC instead of local time
+    #[clap(long, global = true, conflicts_with = "timezone")]
+    utc: bool,
+
+    /// Time zone of the timestamp, e.g. `Europe/Berlin` or `+02:00` (defaults to local time)
+    #[clap(long, global = true)]
+    timezone: Option<String>,
+
+    /// Size of the chunks written to BLE badges (default 16), some clone firmwares need 20 or 64
+    #[clap(long, global = true)]
+    chunk_size: Option<NonZeroUsize>,
+
+    /// Write this timestamp instead of the current time for reproducible payloads,
+    /// e.g. `2025-01-01T00:00:00Z` or `zero`
+    #[clap(long, global = true)]
+    timestamp: Option<String>,
 
     /// List all devices visible to a transport and exit
     #[clap(long)]
@@ -52,6 +150,261 @@ struct Args {
     config: Option<PathBuf>,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Convert badges exported by the Badge Magic app into a config (printed to stdout)
+    ImportApp {
+        /// Path to the JSON file exported by the app
+        file: PathBuf,
+    },
+
+    /// Convert a badge shared by the Badge Magic app into a config (printed to stdout)
+    ImportShare {
+        /// Shared link, scanned QR code or exported JSON
+        share: String,
+    },
+
+    /// Convert a config into the export format of the Badge Magic app (printed to stdout)
+    ExportApp {
+        /// File format of the config file (toml, json)
+        #[clap(long)]
+        format: Option<String>,
+
+        /// Path to TOML configuration file
+        config: PathBuf,
+    },
+
+    /// Send the transfers of a recording (made with --record) to the badges again
+    Replay {
+        /// Path to the recording
+        file: PathBuf,
+    },
+
+    /// Compare two raw payloads (e.g. USB or BLE captures) and print the differences
+    Diff {
+        /// First payload
+        a: PathBuf,
+
+        /// Second payload
+        b: PathBuf,
+    },
+
+    /// Show how many bytes of the payload every message uses, compared to the 8192 byte limit
+    Inspect {
+        /// File format of the config file (toml, json)
+        #[clap(long)]
+        format: Option<String>,
+
+        /// Path to TOML configuration file
+        config: PathBuf,
+    },
+
+    /// Convert a PNG image into a message for a config (printed to stdout)
+    Convert {
+        /// Brightness (0 to 1) above which a pixel is lit
+        #[clap(long, default_value_t = 0.5)]
+        threshold: f32,
+
+        /// Light up the dark pixels instead, e.g. for dark logos on a white background
+        #[clap(long)]
+        invert: bool,
+
+        /// Print `bitmap_base64` content instead of a bitstring
+        #[clap(long)]
+        base64: bool,
+
+        /// Path to the PNG image (at most 11 pixels high)
+        image: PathBuf,
+    },
+
+    /// Draw pixel art in the terminal and save it as bitstring message to a new config
+    #[cfg(unix)]
+    Draw {
+        /// Width of a frame in pixels
+        #[clap(long, default_value_t = protocol::DISPLAY_WIDTH)]
+        width: usize,
+
+        /// Path of the config to create
+        config: PathBuf,
+    },
+
+    /// Render a preview of what the badge will display
+    #[clap(group(ArgGroup::new("output").required(true).multiple(true)))]
+    Preview {
+        /// Show the animated badge in a window, reloading the config when it changes
+        #[cfg(feature = "window")]
+        #[clap(long, group = "output")]
+        window: bool,
+
+        /// File format of the config file (toml, json)
+        #[clap(long)]
+        format: Option<String>,
+
+        /// Play the animation in the terminal, as images if the terminal supports inline graphics
+        #[clap(long, group = "output")]
+        terminal: bool,
+
+        /// Inline graphics protocol of the terminal
+        #[clap(long, value_enum, default_value_t = Graphics::Auto, requires = "terminal")]
+        graphics: Graphics,
+
+        /// Write an animated GIF to this path
+        #[clap(long, group = "output")]
+        gif: Option<PathBuf>,
+
+        /// Write the full width bitmaps of all messages as PNG to this path
+        #[clap(long, group = "output")]
+        png: Option<PathBuf>,
+
+        /// Number of image pixels per LED
+        #[clap(long, default_value_t = 8)]
+        scale: u16,
+
+        /// Path to TOML configuration file
+        config: PathBuf,
+    },
+
+    /// Accept payloads from other hosts over TCP and write them to the badge
+    Agent {
+        /// Transport protocol to use
+        #[clap(long)]
+        transport: TransportProtocol,
+
+        /// Address to listen on
+        #[clap(long, default_value = "0.0.0.0:7878")]
+        listen: String,
+
+        /// Shared token the clients have to send, at least 16 bytes
+        #[clap(long, env = "BADGEMAGIC_AGENT_TOKEN", hide_env_values = true)]
+        token: String,
+
+        /// Run as systemd service (socket activation, readiness notification)
+        #[cfg(all(feature = "systemd", unix))]
+        #[clap(long)]
+        systemd: bool,
+    },
+
+    /// Switch between messages at the times of day listed in a CSV schedule
+    Schedule {
+        /// Transport protocol to use
+        #[clap(long)]
+        transport: TransportProtocol,
+
+        /// Write the current entry and exit, e.g. when run by cron
+        #[clap(long)]
+        once: bool,
+
+        /// Path to the schedule CSV
+        schedule: PathBuf,
+    },
+
+    /// Show every line written to a named pipe (FIFO) as text message
+    #[cfg(unix)]
+    Pipe {
+        /// Transport protocol to use
+        #[clap(long)]
+        transport: TransportProtocol,
+
+        /// Path of the named pipe, created if it does not exist
+        fifo: PathBuf,
+    },
+
+    /// Send a config to a remote agent
+    Send {
+        /// Address of the agent (e.g. raspberrypi:7878)
+        #[clap(long)]
+        agent: String,
+
+        /// Shared token of the agent
+        #[clap(long, env = "BADGEMAGIC_AGENT_TOKEN", hide_env_values = true)]
+        token: String,
+
+        /// File format of the config file (toml, json)
+        #[clap(long)]
+        format: Option<String>,
+
+        /// Path to TOML configuration file
+        config: PathBuf,
+    },
+
+    /// Manage the nicknames of saved badges
+    Device {
+        #[clap(subcommand)]
+        command: DeviceCommand,
+    },
+
+    /// Run an HTTP server to update the badge remotely
+    #[cfg(feature = "serve")]
+    Serve {
+        /// Transport protocol to use
+        #[clap(long)]
+        transport: TransportProtocol,
+
+        /// Address to listen on
+        #[clap(long, default_value = "127.0.0.1:8080")]
+        listen: String,
+
+        /// Address to accept WebSocket clients on (e.g. 127.0.0.1:8081)
+        #[clap(long)]
+        websocket: Option<String>,
+
+        /// Run as systemd service (socket activation, readiness notification)
+        #[cfg(all(feature = "systemd", unix))]
+        #[clap(long)]
+        systemd: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum DeviceCommand {
+    /// Save a badge with a nickname
+    Add {
+        /// Transport protocol used to reach the badge
+        #[clap(long)]
+        transport: TransportProtocol,
+
+        /// Nickname of the badge
+        name: String,
+
+        /// Serial number (USB) or address (BLE) of the badge, as shown by --list-devices
+        id: String,
+    },
+
+    /// Change the nickname of a saved badge
+    Rename {
+        /// Current nickname
+        name: String,
+
+        /// New nickname
+        new_name: String,
+    },
+
+    /// Remove a saved badge
+    Remove {
+        /// Nickname of the badge
+        name: String,
+    },
+
+    /// Create or replace a group of saved badges
+    Group {
+        /// Name of the group
+        name: String,
+
+        /// Nicknames of the badges in the group
+        #[clap(required = true)]
+        members: Vec<String>,
+    },
+
+    /// Remove a group, the badges stay saved
+    RemoveGroup {
+        /// Name of the group
+        name: String,
+    },
+
+    /// List the saved badges and groups
+    List,
+}
+
 #[derive(Clone, Deserialize, ValueEnum)]
 #[serde(rename_all = "kebab-case")]
 enum TransportProtocol {
@@ -59,62 +412,789 @@ enum TransportProtocol {
     Ble,
 }
 
-#[derive(Deserialize)]
-#[serde(deny_unknown_fields)]
-struct Config {
-    #[serde(rename = "message")]
-    messages: Vec<Message>,
+/// Return the address or serial number identifying the badge for `transport`
+///
+/// USB badges without a serial number are identified by `path:` and their HID path,
+/// or by `#` and their index, see [`find_usb_device`].
+fn device_target(device: &DeviceConfig, transport: &TransportProtocol) -> Result<String> {
+    match transport {
+        TransportProtocol::Usb => match (&device.serial, &device.usb_path, device.usb_index) {
+            (Some(serial), _, _) => Ok(serial.clone()),
+            (None, Some(path), _) => Ok(format!("path:{path}")),
+            (None, None, Some(index)) => Ok(format!("#{index}")),
+            (None, None, None) => {
+                anyhow::bail!("device is missing a serial number, usb_path or usb_index for USB")
+            }
+        },
+        TransportProtocol::Ble => device
+            .address
+            .clone()
+            .context("device is missing an address for BLE"),
+    }
+}
+
+/// Return the transport and address or serial number of a badge saved with `device add`
+fn saved_device(registry: &Registry, name: &str) -> Result<(TransportProtocol, String)> {
+    Ok(match registry.get(name)? {
+        registry::Device::Usb { serial } => (TransportProtocol::Usb, serial.clone()),
+        registry::Device::Ble { address } => (TransportProtocol::Ble, address.clone()),
+    })
 }
 
-#[derive(Deserialize)]
-struct Message {
-    #[serde(default)]
-    blink: bool,
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            let hint = err
+                .chain()
+                .find_map(|err| err.downcast_ref::<badgemagic::Error>())
+                .and_then(badgemagic::Error::hint);
+            if let Some(hint) = hint {
+                eprintln!("\nHint: {hint}");
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<()> {
+    let mut args = Args::parse();
+
+    if let Some(path) = &args.record {
+        record::start(path).with_context(|| format!("open recording: {}", path.display()))?;
+    }
+    if let Some(chunk_size) = args.chunk_size {
+        BLE_CHUNK_SIZE.get_or_init(|| chunk_size.get());
+    }
+    timezone::init(
+        args.utc,
+        args.timezone.as_deref(),
+        args.timestamp.as_deref(),
+    )?;
+
+    if let Some(command) = args.command.take() {
+        return run_command(command, args.force);
+    }
 
-    #[serde(default)]
-    border: bool,
+    if args.list_devices {
+        let transport = args
+            .transport
+            .expect("transport is required to list devices");
+        return list_devices(&transport);
+    }
 
-    #[serde(default)]
-    speed: Speed,
+    let config = load_config(&args)?;
 
-    #[serde(default)]
-    mode: Mode,
+    let guard = WidthGuard {
+        max_screens: args.max_screens,
+        strict: args.strict,
+    };
+    if args.dry_run {
+        for_each_payload(&config, |payload| {
+            guard.check(payload)?;
+            print_timing(payload);
+            Ok(())
+        })?;
+        return Ok(());
+    }
+
+    if args.all {
+        anyhow::ensure!(
+            config.devices.is_empty(),
+            "--all can not be used with [[device]] sections in the config",
+        );
+        let payload = build_payload(&config, None)?;
+        guard.check(&payload)?;
+        let transports = args.transport.map_or_else(
+            || vec![TransportProtocol::Usb, TransportProtocol::Ble],
+            |transport| vec![transport],
+        );
+        return broadcast(&transports, &payload, args.concurrent);
+    }
 
-    #[serde(flatten)]
-    content: Content,
+    let Some(interval) = args.repeat_every else {
+        let mut cache = WriteCache::load(args.force)?;
+        return write_config(&args, &config, &guard, &mut cache);
+    };
+    // rewrite unchanged payloads, for badges that lost their memory
+    let mut cache = WriteCache::load(args.force || !args.only_changed)?;
+    let mut config = Some(config);
+    loop {
+        let result = match config.take() {
+            Some(config) => Ok(config),
+            None => load_config(&args),
+        }
+        .and_then(|config| write_config(&args, &config, &guard, &mut cache));
+        if let Err(err) = result {
+            eprintln!("Error: {err:?}");
+        }
+        thread::sleep(interval);
+    }
 }
 
-#[derive(Deserialize)]
-#[serde(deny_unknown_fields, untagged)]
-enum Content {
-    Text { text: String },
-    Bitstring { bitstring: String },
-    BitmapBase64 { width: u32, bitmap_base64: String },
-    BitmapFile { width: u32, bitmap_file: PathBuf },
-    // TODO: implement png
-    // PngFile { png_file: PathBuf },
+/// Load the config passed on the command line
+fn load_config(args: &Args) -> Result<Config> {
+    let config_path = args.config.as_deref().unwrap_or(Path::new(""));
+    let mut config = Config::load(config_path, args.format.as_deref())?;
+
+    if args.rotate_180 {
+        let devices = config.devices.iter_mut().flat_map(|d| &mut d.messages);
+        for message in config.messages.iter_mut().chain(devices) {
+            message.rotate_180 = true;
+        }
+    }
+    Ok(config)
 }
 
-fn main() -> Result<()> {
-    let mut args = Args::parse();
+/// Load a config file and render its payload at the local time
+fn load_payload(path: &Path, format: Option<&str>) -> Result<PayloadBuffer> {
+    build_payload(&Config::load(path, format)?, None)
+}
 
-    if args.list_devices {
-        return list_devices(&args.transport);
+/// Render the messages of `config` (or of `device`) at the local time
+///
+/// Characters missing from the fonts are reported, as they are shown as similar looking ones or `?`.
+fn build_payload(config: &Config, device: Option<&DeviceConfig>) -> Result<PayloadBuffer> {
+    let (payload, substituted) =
+        config.build_payload_with_substitutions(device, timezone::now_local())?;
+    if !substituted.is_empty() {
+        let substituted: String = substituted.into_iter().collect();
+        eprintln!("warning: substituted characters missing from the fonts: {substituted}");
+    }
+    Ok(payload)
+}
+
+/// Write the config to the badges selected on the command line
+fn write_config(
+    args: &Args,
+    config: &Config,
+    guard: &WidthGuard,
+    cache: &mut WriteCache,
+) -> Result<()> {
+    if args.device.is_some() || args.group.is_some() {
+        anyhow::ensure!(
+            config.devices.is_empty(),
+            "--device and --group can not be used with [[device]] sections in the config",
+        );
+        let registry = Registry::load(&Registry::default_path()?)?;
+        let payload = build_payload(config, None)?;
+        guard.check(&payload)?;
+        if let Some(name) = &args.device {
+            let (transport, target) = saved_device(&registry, name)?;
+            return cache.write_device_payloads(&transport, vec![(target, payload)]);
+        }
+        if let Some(group) = &args.group {
+            return write_group(&registry, group, &payload, cache);
+        }
+    }
+
+    let transport = args
+        .transport
+        .as_ref()
+        .expect("transport is required without subcommand, device or group");
+
+    if config.devices.is_empty() {
+        let payload = build_payload(config, None)?;
+        guard.check(&payload)?;
+        return cache.write_payload(transport, &payload);
+    }
+
+    anyhow::ensure!(
+        config.messages.is_empty(),
+        "messages must be part of a [[device]] section when devices are configured",
+    );
+    let mut payloads = Vec::new();
+    for device in &config.devices {
+        let target = device_target(device, transport)?;
+        let payload =
+            build_payload(config, Some(device)).with_context(|| format!("device {target}"))?;
+        guard
+            .check(&payload)
+            .with_context(|| format!("device {target}"))?;
+        payloads.push((target, payload));
+    }
+    cache.write_device_payloads(transport, payloads)
+}
+
+/// Parse a duration like `90s`, `30m` or `1h` (seconds without a unit)
+fn parse_duration(value: &str) -> Result<Duration> {
+    let (number, unit) = value
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or((value, ""), |index| value.split_at(index));
+    let number: u64 = number
+        .parse()
+        .with_context(|| format!("invalid duration: {value:?}"))?;
+    let factor = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        _ => anyhow::bail!("invalid duration unit {unit:?}, expected s, m or h"),
+    };
+    let seconds = number.checked_mul(factor).context("duration too long")?;
+    anyhow::ensure!(seconds > 0, "duration must not be zero");
+    Ok(Duration::from_secs(seconds))
+}
+
+fn run_command(command: Command, force: bool) -> Result<()> {
+    match command {
+        Command::ImportApp { file } => {
+            let json = fs::read_to_string(&file)
+                .with_context(|| format!("load app export: {}", file.display()))?;
+            let config = app::Data::from_json(&json)?.into_config()?;
+            print!("{}", toml::to_string_pretty(&config)?);
+        }
+        Command::ImportShare { share } => {
+            let config = app::Data::from_share(&share)?.into_config()?;
+            print!("{}", toml::to_string_pretty(&config)?);
+        }
+        Command::ExportApp { format, config } => {
+            let config = Config::load(&config, format.as_deref())?;
+            println!("{}", app::Data::from_config(&config)?.to_json()?);
+        }
+        Command::Replay { file } => replay(&file)?,
+        Command::Diff { a, b } => diff(&a, &b)?,
+        Command::Inspect { format, config } => {
+            let config = Config::load(&config, format.as_deref())?;
+            for_each_payload(&config, |payload| {
+                print_budget(payload);
+                Ok(())
+            })?;
+        }
+        Command::Convert {
+            threshold,
+            invert,
+            base64,
+            image,
+        } => print!("{}", convert::convert(&image, threshold, invert, base64)?),
+        #[cfg(unix)]
+        Command::Draw { width, config } => draw::draw(width, &config)?,
+        Command::Preview {
+            #[cfg(feature = "window")]
+            window,
+            format,
+            terminal,
+            graphics,
+            gif,
+            png,
+            scale,
+            config,
+        } => {
+            let payload = load_payload(&config, format.as_deref())?;
+            print_timing(&payload);
+            write_preview_images(&payload, scale, gif.as_deref(), png.as_deref())?;
+            if terminal {
+                terminal::play(&payload, scale, graphics)?;
+            }
+            #[cfg(feature = "window")]
+            if window {
+                preview_window(&config, format.as_deref(), payload, scale)?;
+            }
+        }
+        Command::Agent {
+            transport,
+            listen,
+            token,
+            #[cfg(all(feature = "systemd", unix))]
+            systemd,
+        } => {
+            #[cfg(not(all(feature = "systemd", unix)))]
+            let systemd = false;
+            run_service(&listen, systemd, |listener, shutdown| {
+                agent::serve(&transport, &listener, &token, shutdown)
+            })?;
+        }
+        Command::Schedule {
+            transport,
+            once,
+            schedule,
+        } => run_schedule(&transport, &schedule, once, force)?,
+        #[cfg(unix)]
+        Command::Pipe { transport, fifo } => run_pipe(&transport, &fifo, force)?,
+        Command::Send {
+            agent,
+            token,
+            format,
+            config,
+        } => {
+            let payload = load_payload(&config, format.as_deref())?;
+            agent::send(&agent, &token, &payload)?;
+        }
+        Command::Device { command } => run_device_command(command)?,
+        #[cfg(feature = "serve")]
+        Command::Serve {
+            transport,
+            listen,
+            websocket,
+            #[cfg(all(feature = "systemd", unix))]
+            systemd,
+        } => {
+            #[cfg(not(all(feature = "systemd", unix)))]
+            let systemd = false;
+            run_service(&listen, systemd, |listener, shutdown| {
+                serve::run(transport, listener, websocket.as_deref(), shutdown)
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Write every line written to the named pipe at `path` as text message
+///
+/// Failed writes are reported, the next line is written anyway.
+#[cfg(unix)]
+fn run_pipe(transport: &TransportProtocol, path: &Path, force: bool) -> Result<()> {
+    pipe::create(path)?;
+    eprintln!("waiting for lines on {}", path.display());
+    let mut cache = WriteCache::load(force)?;
+    pipe::read(path, |line| {
+        println!("{line}");
+        let config = Config::default().message(badgemagic::config::Message::text(line));
+        let result = build_payload(&config, None)
+            .and_then(|payload| cache.write_payload(transport, &payload));
+        if let Err(err) = result {
+            eprintln!("Error: {err:?}");
+        }
+    })
+}
+
+/// Write the entry of the schedule active at the current time, whenever it changes
+///
+/// Failed writes are reported and retried a minute later.
+fn run_schedule(transport: &TransportProtocol, path: &Path, once: bool, force: bool) -> Result<()> {
+    let schedule = Schedule::load(path)?;
+    let mut cache = WriteCache::load(force)?;
+    let mut written = None;
+    loop {
+        let now = timezone::now_local().time();
+        let (index, entry) = schedule.active(now);
+        if written != Some(index) {
+            println!(
+                "{}: {} (since {})",
+                schedule::format_time(now),
+                entry.label(),
+                schedule::format_time(entry.time)
+            );
+            let result = entry
+                .build_payload()
+                .and_then(|payload| cache.write_payload(transport, &payload));
+            match result {
+                Ok(()) => written = Some(index),
+                Err(err) if once => return Err(err),
+                Err(err) => eprintln!("Error: {err:?}"),
+            }
+        }
+        if once {
+            return Ok(());
+        }
+        let retry = if written == Some(index) { 60 * 60 } else { 60 };
+        thread::sleep(schedule.until_next(now).min(Duration::from_secs(retry)));
+    }
+}
+
+/// Write the GIF and PNG previews of `payload` to the given paths
+fn write_preview_images(
+    payload: &PayloadBuffer,
+    scale: u16,
+    gif: Option<&Path>,
+    png: Option<&Path>,
+) -> Result<()> {
+    let create = |path: &Path| {
+        fs::File::create(path)
+            .map(io::BufWriter::new)
+            .with_context(|| format!("create preview: {}", path.display()))
+    };
+    if let Some(gif) = gif {
+        simulator::write_gif(payload, scale, create(gif)?)?;
+    }
+    if let Some(png) = png {
+        simulator::write_png(payload, scale, create(png)?)?;
+    }
+    Ok(())
+}
+
+/// Play the animation in a window until it is closed
+///
+/// The config is reloaded when the file is modified,
+/// errors are reported and the previous payload is kept.
+#[cfg(feature = "window")]
+fn preview_window(
+    path: &std::path::Path,
+    format: Option<&str>,
+    mut payload: PayloadBuffer,
+    scale: u16,
+) -> Result<()> {
+    let modified = || fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut window = simulator::Window::new(&format!("badgemagic - {}", path.display()), scale)?;
+    let mut last_modified = modified();
+    'outer: while window.is_open() {
+        let frames: Vec<_> = simulator::frames(&payload).collect();
+        for (frame, duration) in frames {
+            window.show(&frame)?;
+            std::thread::sleep(duration);
+            if !window.is_open() {
+                break 'outer;
+            }
+            if modified() != last_modified {
+                last_modified = modified();
+                match load_payload(path, format) {
+                    Ok(new) => payload = new,
+                    Err(err) => eprintln!("Error: {err:?}"),
+                }
+                continue 'outer;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Run a long running command listening on `listen`
+///
+/// As systemd service, the listener can be passed by systemd
+/// and the command is stopped gracefully by `SIGTERM`.
+fn run_service(
+    listen: &str,
+    systemd: bool,
+    run: impl FnOnce(TcpListener, &AtomicBool) -> Result<()>,
+) -> Result<()> {
+    #[cfg(all(feature = "systemd", unix))]
+    if systemd {
+        let shutdown = systemd::shutdown_flag()?;
+        let listener = systemd::listener(listen)?;
+        systemd::notify("READY=1");
+        let result = run(listener, &shutdown);
+        systemd::notify("STOPPING=1");
+        return result;
+    }
+    let _ = systemd;
+    let listener = TcpListener::bind(listen).with_context(|| format!("listen on {listen}"))?;
+    run(listener, &AtomicBool::new(false))
+}
+
+fn run_device_command(command: DeviceCommand) -> Result<()> {
+    let path = Registry::default_path()?;
+    let mut registry = Registry::load(&path)?;
+    match command {
+        DeviceCommand::Add {
+            transport,
+            name,
+            id,
+        } => {
+            let device = match transport {
+                TransportProtocol::Usb => registry::Device::Usb { serial: id },
+                TransportProtocol::Ble => registry::Device::Ble { address: id },
+            };
+            registry.add(name, device)?;
+        }
+        DeviceCommand::Rename { name, new_name } => registry.rename(&name, new_name)?,
+        DeviceCommand::Remove { name } => {
+            registry.remove(&name)?;
+        }
+        DeviceCommand::Group { name, members } => registry.set_group(name, members)?,
+        DeviceCommand::RemoveGroup { name } => registry.remove_group(&name)?,
+        DeviceCommand::List => {
+            for (name, device) in registry.iter() {
+                match device {
+                    registry::Device::Usb { serial } => println!("{name}: usb {serial}"),
+                    registry::Device::Ble { address } => println!("{name}: ble {address}"),
+                }
+            }
+            for (name, members) in registry.groups() {
+                println!("group {name}: {}", members.join(", "));
+            }
+            return Ok(());
+        }
+    }
+    registry.save(&path)
+}
+
+/// Write `payload` to every badge of `group`, reporting the result per badge
+fn write_group(
+    registry: &Registry,
+    group: &str,
+    payload: &PayloadBuffer,
+    cache: &mut WriteCache,
+) -> Result<()> {
+    let members = registry.group(group)?;
+    let mut failed = 0;
+    for name in members {
+        let result = saved_device(registry, name).and_then(|(transport, target)| {
+            cache.write_device_payloads(&transport, vec![(target, payload.clone())])
+        });
+        match result {
+            Ok(()) => eprintln!("{name}: ok"),
+            Err(err) => {
+                failed += 1;
+                eprintln!("{name}: failed: {err:#}");
+            }
+        }
+    }
+    anyhow::ensure!(
+        failed == 0,
+        "writing to {failed} of {} devices failed",
+        members.len()
+    );
+    Ok(())
+}
+
+/// Write the payload to every badge found with `transports` and print the result of each
+///
+/// The cache is not used, every badge is written.
+fn broadcast(
+    transports: &[TransportProtocol],
+    payload: &PayloadBuffer,
+    concurrent: bool,
+) -> Result<()> {
+    let mut results = Vec::new();
+    for transport in transports {
+        let name = transport.to_possible_value().unwrap().get_name().to_owned();
+        let written = match transport {
+            TransportProtocol::Usb => broadcast_usb(payload, concurrent),
+            TransportProtocol::Ble => {
+                with_ble(async |session| broadcast_ble(session, payload, concurrent).await)
+            }
+        };
+        match written {
+            Ok(written) => results.extend(written.into_iter().map(|(id, r)| (name.clone(), id, r))),
+            Err(err) => results.push((name, "-".into(), Err(err))),
+        }
+    }
+
+    anyhow::ensure!(!results.is_empty(), "no badges found");
+    let width = results.iter().map(|(_, id, _)| id.len()).max().unwrap_or(0);
+    println!("{:<9} {:<width$} result", "transport", "device");
+    for (transport, id, result) in &results {
+        match result {
+            Ok(()) => println!("{transport:<9} {id:<width$} ok"),
+            Err(err) => println!("{transport:<9} {id:<width$} failed: {err:#}"),
+        }
+    }
+    let failed = results
+        .iter()
+        .filter(|(_, _, result)| result.is_err())
+        .count();
+    anyhow::ensure!(
+        failed == 0,
+        "writing to {failed} of {} devices failed",
+        results.len()
+    );
+    Ok(())
+}
+
+/// Write the payload to every USB badge, returns the serial number and result of each
+fn broadcast_usb(payload: &PayloadBuffer, concurrent: bool) -> Result<Vec<(String, Result<()>)>> {
+    let devices = UsbDevice::enumerate()?;
+    let write = |device: &UsbDevice| {
+        let id = device
+            .serial_number()
+            .unwrap_or("(no serial number)")
+            .to_owned();
+        let mut payload = payload.clone();
+        payload.set_timestamp(timezone::now());
+        (id, device.write(payload).map_err(anyhow::Error::from))
+    };
+    if !concurrent {
+        return Ok(devices.iter().map(write).collect());
+    }
+    Ok(thread::scope(|scope| {
+        let threads: Vec<_> = devices
+            .iter()
+            .map(|device| scope.spawn(|| write(device)))
+            .collect();
+        threads
+            .into_iter()
+            .map(|thread| thread.join().expect("write thread panicked"))
+            .collect()
+    }))
+}
+
+/// Write the payload to every BLE badge, returns the address and result of each
+async fn broadcast_ble(
+    session: &BleSession,
+    payload: &PayloadBuffer,
+    concurrent: bool,
+) -> Result<Vec<(String, Result<()>)>> {
+    let devices = session.enumerate().await?;
+    let write = |device: BleDevice| {
+        let mut payload = payload.clone();
+        async move {
+            payload.set_timestamp(timezone::now());
+            let result = device.write(payload).await;
+            (device.address(), result.map_err(anyhow::Error::from))
+        }
+    };
+    let mut results = Vec::new();
+    if concurrent {
+        let tasks: Vec<_> = devices
+            .into_iter()
+            .map(|d| tokio::spawn(write(d)))
+            .collect();
+        for task in tasks {
+            results.push(task.await?);
+        }
+    } else {
+        for device in devices {
+            results.push(write(device).await);
+        }
+    }
+    Ok(results)
+}
+
+/// Build the payload of `config` and pass it to `f`, or the payload of each `[[device]]`
+fn for_each_payload(config: &Config, f: impl Fn(&PayloadBuffer) -> Result<()>) -> Result<()> {
+    if config.devices.is_empty() {
+        f(&build_payload(config, None)?)?;
+    }
+    for device in &config.devices {
+        let name = device
+            .address
+            .as_ref()
+            .or(device.serial.as_ref())
+            .or(device.usb_path.as_ref());
+        match (name, device.usb_index) {
+            (Some(name), _) => println!("device {name}:"),
+            (None, Some(index)) => println!("device #{index}:"),
+            (None, None) => println!("device without address:"),
+        }
+        f(&build_payload(config, Some(device))?)?;
+    }
+    Ok(())
+}
+
+/// Limit of the message width, e.g. to catch accidentally pasted long texts
+struct WidthGuard {
+    max_screens: usize,
+    strict: bool,
+}
+
+impl WidthGuard {
+    /// Warn about messages wider than `max_screens` screens, or fail if `strict`
+    fn check(&self, payload: &PayloadBuffer) -> Result<()> {
+        for (index, (style, content)) in (0..).map_while(|i| payload.message(i)).enumerate() {
+            // every screen of a fast mode animation is a frame, they are wide on purpose
+            if style.get_mode() == Mode::Fast {
+                continue;
+            }
+            let columns = content.len() * 8;
+            let screens = columns.div_ceil(simulator::WIDTH);
+            if screens > self.max_screens {
+                let message = format!(
+                    "message {} is {columns} columns ({screens} screens) wide, more than --max-screens {}",
+                    index + 1,
+                    self.max_screens
+                );
+                anyhow::ensure!(!self.strict, "{message}");
+                eprintln!("warning: {message}");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Print the bytes used by the header, every message and the padding of the payload
+fn print_budget(payload: &PayloadBuffer) {
+    const MAX_SIZE: usize = protocol::MAX_PAYLOAD_SIZE;
+    // a screen of `Mode::Fast` is 48 columns wide
+    const SCREEN_SIZE: usize = 6 * 11;
+
+    let mut messages = 0;
+    println!("header: {} bytes", protocol::HEADER_SIZE);
+    for (index, (_, content)) in (0..).map_while(|i| payload.message(i)).enumerate() {
+        messages += 1;
+        println!(
+            "message {}: {} columns, {} bytes",
+            index + 1,
+            content.len() * 8,
+            content.len() * 11
+        );
+    }
+    let size = payload.as_bytes().len();
+    let padded = protocol::padded_len(size);
+    println!("padding: {} bytes", padded - size);
+    println!(
+        "total: {padded} of {MAX_SIZE} bytes ({}%), {messages} of 8 messages",
+        padded * 100 / MAX_SIZE
+    );
+    match MAX_SIZE.checked_sub(padded) {
+        // padding is only added at the end, so the remaining bytes are usable
+        Some(left) => println!(
+            "{left} bytes left, room for {} more screens in fast mode ({SCREEN_SIZE} bytes each)",
+            (MAX_SIZE - size) / SCREEN_SIZE
+        ),
+        None => println!("{} bytes too large", padded - MAX_SIZE),
+    }
+}
+
+/// Print the width of every message and how long one iteration of its animation takes
+fn print_timing(payload: &PayloadBuffer) {
+    for (index, (style, content)) in (0..).map_while(|i| payload.message(i)).enumerate() {
+        println!(
+            "message {}: {} columns, {} at speed {}: {:.1} s per cycle",
+            index + 1,
+            content.len() * 8,
+            format!("{:?}", style.get_mode()).to_lowercase(),
+            u8::from(style.get_speed()),
+            simulator::message_duration(style, content).as_secs_f64()
+        );
     }
+}
 
-    let payload = gnerate_payload(&mut args)?;
+/// Print the differences of two raw payloads
+fn diff(a: &Path, b: &Path) -> Result<()> {
+    let load = |path: &Path| {
+        let data = fs::read(path).with_context(|| format!("load payload: {}", path.display()))?;
+        PayloadBuffer::from_bytes(&data)
+            .with_context(|| format!("parse payload: {}", path.display()))
+    };
+    let diff = badgemagic::diff::diff(&load(a)?, &load(b)?);
+    if diff.is_empty() {
+        eprintln!("payloads are identical");
+    }
+    print!("{diff}");
+    Ok(())
+}
 
-    write_payload(&args.transport, payload)
+/// Send the chunks of every recorded transfer to the same badge again
+fn replay(path: &Path) -> Result<()> {
+    let transfers =
+        record::read(path).with_context(|| format!("read recording: {}", path.display()))?;
+    for transfer in transfers {
+        let chunks = transfer.chunks.iter().map(|(_, chunk)| chunk.as_slice());
+        let target = &transfer.device;
+        match transfer.transport {
+            record::Transport::Usb => {
+                let device = if target.is_empty() {
+                    UsbDevice::single()?
+                } else {
+                    UsbDevice::enumerate()?
+                        .into_iter()
+                        .find(|device| device.serial_number() == Some(target))
+                        .with_context(|| format!("device not found: {target}"))?
+                };
+                for chunk in chunks {
+                    device.write_bytes(chunk)?;
+                }
+            }
+            record::Transport::Ble => with_ble(async |session| {
+                let device = session
+                    .enumerate()
+                    .await?
+                    .into_iter()
+                    .find(|device| device.address().eq_ignore_ascii_case(target))
+                    .with_context(|| format!("device not found: {target}"))?;
+                anyhow::Ok(device.write_chunks(chunks).await?)
+            })?,
+        }
+        eprintln!("replayed {} chunks to {target}", transfer.chunks.len());
+    }
+    Ok(())
 }
 
 fn list_devices(transport: &TransportProtocol) -> Result<()> {
     let devices = match transport {
-        TransportProtocol::Usb => UsbDevice::list_all(),
-        TransportProtocol::Ble => tokio::runtime::Builder::new_current_thread()
-            .enable_all()
-            .build()?
-            .block_on(async { BleDevice::list_all().await }),
-    }?;
+        TransportProtocol::Usb => UsbDevice::list_all()?,
+        TransportProtocol::Ble => with_ble(async |session| Ok(session.list_all().await?))?,
+    };
 
     eprintln!(
         "found {} {} devices",
@@ -128,116 +1208,190 @@ fn list_devices(transport: &TransportProtocol) -> Result<()> {
     Ok(())
 }
 
-fn gnerate_payload(args: &mut Args) -> Result<PayloadBuffer> {
-    let config_path = args.config.take().unwrap_or_default();
-    let config = fs::read_to_string(&config_path)
-        .with_context(|| format!("load config: {config_path:?}"))?;
-    let config: Config = {
-        let extension = args
-            .format
-            .as_deref()
-            .map(AsRef::as_ref)
-            .or(config_path.extension())
-            .context("missing file extension for config file")?;
-        match extension.to_str().unwrap_or_default() {
-            "json" => serde_json::from_str(&config).context("parse config")?,
-            "toml" => toml::from_str(&config).context("parse config")?,
-            _ => anyhow::bail!("unsupported config file extension: {extension:?}"),
-        }
-    };
+/// Size of the chunks written to BLE badges, selected with `--chunk-size`
+static BLE_CHUNK_SIZE: OnceLock<usize> = OnceLock::new();
 
-    let mut payload = PayloadBuffer::new();
+/// Create a BLE session writing chunks of the size selected with `--chunk-size`
+async fn new_ble_session() -> badgemagic::Result<BleSession> {
+    let session = BleSession::new().await?;
+    Ok(match BLE_CHUNK_SIZE.get() {
+        Some(&chunk_size) => session.chunk_size(chunk_size),
+        None => session,
+    })
+}
 
-    for message in config.messages {
-        let mut style = Style::default();
-        if message.blink {
-            style = style.blink();
-        }
-        if message.border {
-            style = style.border();
+/// Run `f` with the BLE session of the process
+///
+/// All BLE operations share one scan, e.g. when writing to the badges of a group one after another.
+fn with_ble<T>(f: impl AsyncFnOnce(&BleSession) -> Result<T>) -> Result<T> {
+    static BLE: OnceLock<(tokio::runtime::Runtime, BleSession)> = OnceLock::new();
+    let (runtime, session) = if let Some(ble) = BLE.get() {
+        ble
+    } else {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let session = runtime.block_on(new_ble_session())?;
+        BLE.get_or_init(|| (runtime, session))
+    };
+    runtime.block_on(f(session))
+}
+
+/// Write the payload to the single badge of `transport`, with the current time as timestamp
+fn write_payload(
+    transport: &TransportProtocol,
+    mut payload: PayloadBuffer,
+) -> Result<(), anyhow::Error> {
+    payload.set_timestamp(timezone::now());
+    match transport {
+        TransportProtocol::Usb => UsbDevice::single()?.write(payload)?,
+        TransportProtocol::Ble => {
+            with_ble(async |session| Ok(session.single().await?.write(payload).await?))?;
         }
-        style = style.speed(message.speed).mode(message.mode);
-        match message.content {
-            Content::Text { text } => {
-                let text = Text::new(
-                    &text,
-                    Point::new(0, 7),
-                    MonoTextStyle::new(&FONT_6X9, BinaryColor::On),
-                );
-                payload.add_message_drawable(style, &text);
-            }
-            Content::Bitstring { bitstring } => {
-                let lines: Vec<_> = bitstring.trim().lines().collect();
+    }
+    Ok(())
+}
 
-                anyhow::ensure!(
-                    lines.len() == 11,
-                    "expected 11 lines in bitstring, found {} lines",
-                    lines.len()
-                );
-                let width = lines[0].len();
-                if lines.iter().any(|l| l.len() != width) {
-                    anyhow::bail!(
-                        "lines should have the same length, got: {:?}",
-                        lines.iter().map(|l| l.len()).collect::<Vec<_>>()
-                    );
-                }
-                let mut buffer = payload.add_message(style, (width + 7) / 8);
-
-                for (y, line) in lines.iter().enumerate() {
-                    for (x, c) in line.chars().enumerate() {
-                        match c {
-                            '_' => {
-                                // off
-                            }
-                            'X' => {
-                                Pixel(
-                                    Point::new(x.try_into().unwrap(), y.try_into().unwrap()),
-                                    BinaryColor::On,
-                                )
-                                .draw(&mut buffer)
-                                .unwrap();
-                            }
-                            _ => anyhow::bail!("invalid bit value for bit ({x}, {y}): {c:?}"),
-                        }
-                    }
+/// Payloads last written to each badge, to skip uploads that would not change anything
+struct WriteCache {
+    path: PathBuf,
+    cache: PayloadCache,
+    force: bool,
+}
+
+impl WriteCache {
+    /// Load the cache, with `force` every payload is written
+    fn load(force: bool) -> Result<Self> {
+        let path = PayloadCache::default_path()?;
+        let cache = PayloadCache::load(&path)?;
+        Ok(Self { path, cache, force })
+    }
+
+    /// Write the payload to the single badge of `transport`, unless it already shows it
+    ///
+    /// The timestamp is set to the current time.
+    fn write_payload(
+        &mut self,
+        transport: &TransportProtocol,
+        payload: &PayloadBuffer,
+    ) -> Result<()> {
+        let mut timestamped = payload.clone();
+        timestamped.set_timestamp(timezone::now());
+        match transport {
+            TransportProtocol::Usb => {
+                let device = UsbDevice::single()?;
+                let key = cache_key(transport, device.serial_number());
+                if self.is_unchanged(&key, payload) {
+                    return Ok(());
                 }
+                device.write(timestamped)?;
+                self.update(&key, payload)
             }
-            Content::BitmapBase64 {
-                width,
-                bitmap_base64: bitmap,
-            } => {
-                let data = if bitmap.ends_with('=') {
-                    base64::engine::general_purpose::STANDARD
-                } else {
-                    base64::engine::general_purpose::STANDARD_NO_PAD
+            TransportProtocol::Ble => with_ble(async |session| {
+                let device = session.single().await?;
+                let key = cache_key(transport, Some(&device.address()));
+                if self.is_unchanged(&key, payload) {
+                    return Ok(());
                 }
-                .decode(bitmap)
-                .context("decode bitmap")?;
-                let image_raw = ImageRawLE::<BinaryColor>::new(&data, width);
-                let image = Image::new(&image_raw, Point::zero());
-                payload.add_message_drawable(style, &image);
-            }
-            Content::BitmapFile { width, bitmap_file } => {
-                let data = fs::read(bitmap_file).context("load bitmap")?;
-                let image_raw = ImageRawLE::<BinaryColor>::new(&data, width);
-                let image = Image::new(&image_raw, Point::zero());
-                payload.add_message_drawable(style, &image);
-            }
+                device.write(timestamped).await?;
+                self.update(&key, payload)
+            }),
         }
     }
 
-    Ok(payload)
+    /// Write each payload the badge does not already show, see [`write_device_payloads`]
+    fn write_device_payloads(
+        &mut self,
+        transport: &TransportProtocol,
+        mut payloads: Vec<(String, PayloadBuffer)>,
+    ) -> Result<()> {
+        payloads.retain(|(target, payload)| {
+            !self.is_unchanged(&cache_key(transport, Some(target)), payload)
+        });
+        if payloads.is_empty() {
+            return Ok(());
+        }
+        write_device_payloads(transport, payloads.clone())?;
+        for (target, payload) in &payloads {
+            self.cache
+                .update(&cache_key(transport, Some(target)), payload);
+        }
+        self.cache.save(&self.path)
+    }
+
+    fn update(&mut self, key: &str, payload: &PayloadBuffer) -> Result<()> {
+        self.cache.update(key, payload);
+        self.cache.save(&self.path)
+    }
+
+    fn is_unchanged(&self, key: &str, payload: &PayloadBuffer) -> bool {
+        let unchanged = !self.force && self.cache.is_current(key, payload);
+        if unchanged {
+            eprintln!("{key}: payload unchanged, skipping upload (use --force to write anyway)");
+        }
+        unchanged
+    }
 }
 
-fn write_payload(
+/// Identify a badge in the cache by its serial number (USB) or address (BLE), e.g. `ble:aa:bb:cc:dd:ee:ff`
+///
+/// `usb` stands for USB badges without a serial number.
+fn cache_key(transport: &TransportProtocol, target: Option<&str>) -> String {
+    let transport = transport.to_possible_value().unwrap();
+    match target {
+        Some(target) => format!("{}:{}", transport.get_name(), target.to_lowercase()),
+        None => transport.get_name().into(),
+    }
+}
+
+/// Find the USB badge with the serial number `target`, or as selected by [`device_target`]
+fn find_usb_device<'a>(devices: &'a [UsbDevice], target: &str) -> Option<&'a UsbDevice> {
+    if let Some(path) = target.strip_prefix("path:") {
+        devices
+            .iter()
+            .find(|device| device.path().to_bytes() == path.as_bytes())
+    } else if let Some(index) = target.strip_prefix('#') {
+        devices.get(index.parse::<usize>().ok()?)
+    } else {
+        devices
+            .iter()
+            .find(|device| device.serial_number() == Some(target))
+    }
+}
+
+/// Write each payload to the device with the given serial number (USB) or address (BLE)
+///
+/// The timestamps are set to the current time.
+fn write_device_payloads(
     transport: &TransportProtocol,
-    payload: PayloadBuffer,
-) -> Result<(), anyhow::Error> {
+    mut payloads: Vec<(String, PayloadBuffer)>,
+) -> Result<()> {
+    for (_, payload) in &mut payloads {
+        payload.set_timestamp(timezone::now());
+    }
     match transport {
-        TransportProtocol::Usb => UsbDevice::single()?.write(payload),
-        TransportProtocol::Ble => tokio::runtime::Builder::new_current_thread()
-            .enable_all()
-            .build()?
-            .block_on(async { BleDevice::single().await?.write(payload).await }),
+        TransportProtocol::Usb => {
+            let devices = UsbDevice::enumerate()?;
+            for (target, payload) in payloads {
+                find_usb_device(&devices, &target)
+                    .with_context(|| format!("device not found: {target}"))?
+                    .write(payload)
+                    .with_context(|| format!("write to device {target}"))?;
+            }
+            Ok(())
+        }
+        TransportProtocol::Ble => with_ble(async |session| {
+            let devices = session.enumerate().await?;
+            for (target, payload) in payloads {
+                devices
+                    .iter()
+                    .find(|device| device.address().eq_ignore_ascii_case(&target))
+                    .with_context(|| format!("device not found: {target}"))?
+                    .write(payload)
+                    .await
+                    .with_context(|| format!("write to device {target}"))?;
+            }
+            Ok(())
+        }),
     }
 }
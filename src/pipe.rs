@@ -0,0 +1,57 @@
+//! Show the lines written to a named pipe (FIFO), e.g. by `echo Hello > /run/badge.fifo`
+
+use std::{
+    ffi::CString,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read},
+    os::unix::{ffi::OsStrExt, fs::FileTypeExt},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+
+/// Create a FIFO at `path`, unless there already is one
+pub fn create(path: &Path) -> Result<()> {
+    match fs::metadata(path) {
+        Ok(metadata) => {
+            anyhow::ensure!(
+                metadata.file_type().is_fifo(),
+                "not a named pipe: {}",
+                path.display()
+            );
+            return Ok(());
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err).with_context(|| format!("open {}", path.display())),
+    }
+    let c_path = CString::new(path.as_os_str().as_bytes()).context("invalid path")?;
+    // SAFETY: c_path is a valid C string
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+        return Err(io::Error::last_os_error())
+            .with_context(|| format!("create named pipe: {}", path.display()));
+    }
+    Ok(())
+}
+
+/// Call `show` with every non-empty line written to the FIFO at `path`
+///
+/// Runs forever, writers may open and close the FIFO as often as they like.
+pub fn read(path: &Path, mut show: impl FnMut(&str)) -> Result<()> {
+    loop {
+        // blocks until a writer opens the FIFO
+        let file = File::open(path).with_context(|| format!("open {}", path.display()))?;
+        read_lines(file, &mut show).with_context(|| format!("read {}", path.display()))?;
+    }
+}
+
+/// Call `show` with every non-empty line until the end of `reader`
+fn read_lines(reader: impl Read, show: &mut impl FnMut(&str)) -> io::Result<()> {
+    for line in BufReader::new(reader).split(b'\n') {
+        let line = String::from_utf8_lossy(&line?).into_owned();
+        let line = line.trim();
+        if !line.is_empty() {
+            show(line);
+        }
+    }
+    Ok(())
+}
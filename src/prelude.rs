@@ -0,0 +1,39 @@
+//! Commonly used types and traits
+//!
+//! ```
+//! # #[cfg(feature = "embedded-graphics")]
+//! # fn main() {
+//! use badgemagic::{embedded_graphics::mono_font::iso_8859_1::FONT_6X9, prelude::*};
+//!
+//! let mut payload = PayloadBuffer::new();
+//! payload.add_message_drawable(
+//!     Style::default().mode(Mode::Center),
+//!     &Text::new(
+//!         "Hello",
+//!         Point::new(0, 8),
+//!         MonoTextStyle::new(&FONT_6X9, BinaryColor::On),
+//!     ),
+//! );
+//! # }
+//! # #[cfg(not(feature = "embedded-graphics"))]
+//! # fn main() {}
+//! ```
+
+#[cfg(feature = "embedded-graphics")]
+pub use crate::protocol::Font;
+pub use crate::protocol::{Mode, PayloadBuffer, Speed, Style};
+
+#[cfg(feature = "usb-hid")]
+pub use crate::usb_hid::Device as UsbDevice;
+
+#[cfg(feature = "ble")]
+pub use crate::ble::{Device as BleDevice, Session as BleSession};
+
+#[cfg(feature = "embedded-graphics")]
+pub use crate::util::{DrawTargetTransformExt, DrawableEffectExt, DrawableLayoutExt};
+
+#[cfg(feature = "embedded-graphics")]
+#[doc(no_inline)]
+pub use embedded_graphics::{
+    mono_font::MonoTextStyle, pixelcolor::BinaryColor, prelude::*, text::Text,
+};
@@ -1,19 +1,27 @@
 //! Protocol used to update the badge
 
-use std::num::TryFromIntError;
+use std::{num::TryFromIntError, time::Duration};
 
 #[cfg(feature = "embedded-graphics")]
 use embedded_graphics::{
     draw_target::DrawTarget,
     geometry::{Dimensions, Point, Size},
+    mono_font::{
+        iso_8859_1::{FONT_4X6, FONT_5X7, FONT_5X8, FONT_6X10, FONT_6X9},
+        MonoFont, MonoTextStyle,
+    },
     pixelcolor::BinaryColor,
     prelude::Pixel,
     primitives::Rectangle,
+    text::{Baseline, Text},
     Drawable,
 };
+#[cfg(feature = "time")]
 use time::OffsetDateTime;
 use zerocopy::{BigEndian, FromBytes, Immutable, IntoBytes, KnownLayout, U16};
 
+use crate::{Error, Result};
+
 /// Message style configuration
 /// ```
 /// use badgemagic::protocol::{Mode, Style};
@@ -99,6 +107,30 @@ impl Style {
         self.mode = mode;
         self
     }
+
+    /// Return whether blink mode is enabled
+    #[must_use]
+    pub fn is_blink(&self) -> bool {
+        self.blink
+    }
+
+    /// Return whether the border is shown
+    #[must_use]
+    pub fn is_border(&self) -> bool {
+        self.border
+    }
+
+    /// Return the update speed of the animations
+    #[must_use]
+    pub fn get_speed(&self) -> Speed {
+        self.speed
+    }
+
+    /// Return the display animation
+    #[must_use]
+    pub fn get_mode(&self) -> Mode {
+        self.mode
+    }
 }
 
 /// Animation update speed
@@ -132,6 +164,67 @@ pub enum Speed {
     Fps15,
 }
 
+impl Speed {
+    const ALL: [Self; 8] = [
+        Self::Fps1_2,
+        Self::Fps1_3,
+        Self::Fps2,
+        Self::Fps2_4,
+        Self::Fps2_8,
+        Self::Fps4_5,
+        Self::Fps7_5,
+        Self::Fps15,
+    ];
+
+    /// Return how long a frame is shown at this speed
+    #[must_use]
+    pub fn frame_duration(self) -> Duration {
+        let tenth_fps = match self {
+            Self::Fps1_2 => 12,
+            Self::Fps1_3 => 13,
+            Self::Fps2 => 20,
+            Self::Fps2_4 => 24,
+            Self::Fps2_8 => 28,
+            Self::Fps4_5 => 45,
+            Self::Fps7_5 => 75,
+            Self::Fps15 => 150,
+        };
+        Duration::from_millis(10_000 / tenth_fps)
+    }
+
+    /// Return how long scrolling by `pixels` columns takes at this speed
+    ///
+    /// Scrolling messages move by one column per frame.
+    /// Content that is `n` columns wide scrolls `n + 44` columns
+    /// to pass the display completely.
+    #[must_use]
+    pub fn scroll_duration(self, pixels: u32) -> Duration {
+        self.frame_duration() * pixels
+    }
+
+    /// Choose the speed that scrolls by `pixels` columns closest to `duration`
+    ///
+    /// This is the inverse of [`Speed::scroll_duration`].
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use badgemagic::protocol::Speed;
+    ///
+    /// // scroll a 20 column name across the display in about 4 seconds
+    /// let speed = Speed::for_scroll(20 + 44, Duration::from_secs(4));
+    /// assert_eq!(speed, Speed::Fps15);
+    /// assert_eq!(speed.scroll_duration(20 + 44), Duration::from_millis(4224));
+    /// ```
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)] // there is always a speed
+    pub fn for_scroll(pixels: u32, duration: Duration) -> Self {
+        Self::ALL
+            .into_iter()
+            .min_by_key(|speed| speed.scroll_duration(pixels).abs_diff(duration))
+            .unwrap()
+    }
+}
+
 impl From<Speed> for u8 {
     fn from(value: Speed) -> Self {
         value as u8
@@ -193,7 +286,129 @@ pub enum Mode {
     Laser,
 }
 
-const MSG_PADDING_ALIGN: usize = 64;
+impl From<Mode> for u8 {
+    fn from(value: Mode) -> Self {
+        value as u8
+    }
+}
+
+impl TryFrom<u8> for Mode {
+    type Error = TryFromIntError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::Left,
+            1 => Self::Right,
+            2 => Self::Up,
+            3 => Self::Down,
+            4 => Self::Center,
+            5 => Self::Fast,
+            6 => Self::Drop,
+            7 => Self::Curtain,
+            8 => Self::Laser,
+            _ => return Err(u8::try_from(-1).unwrap_err()),
+        })
+    }
+}
+
+/// Font used to render text
+#[cfg(feature = "embedded-graphics")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Font {
+    /// 6x10 pixels, the largest font
+    #[cfg_attr(feature = "serde", serde(rename = "6x10"))]
+    Mono6x10,
+
+    /// 6x9 pixels
+    #[default]
+    #[cfg_attr(feature = "serde", serde(rename = "6x9"))]
+    Mono6x9,
+
+    /// 5x8 pixels
+    #[cfg_attr(feature = "serde", serde(rename = "5x8"))]
+    Mono5x8,
+
+    /// 5x7 pixels
+    #[cfg_attr(feature = "serde", serde(rename = "5x7"))]
+    Mono5x7,
+
+    /// 4x6 pixels, the smallest font
+    #[cfg_attr(feature = "serde", serde(rename = "4x6"))]
+    Mono4x6,
+}
+
+#[cfg(feature = "embedded-graphics")]
+impl Font {
+    /// All fonts ordered from largest to smallest
+    pub const ALL: [Self; 5] = [
+        Self::Mono6x10,
+        Self::Mono6x9,
+        Self::Mono5x8,
+        Self::Mono5x7,
+        Self::Mono4x6,
+    ];
+
+    /// The `embedded_graphics` font used to render text
+    #[must_use]
+    pub fn mono_font(self) -> &'static MonoFont<'static> {
+        match self {
+            Self::Mono6x10 => &FONT_6X10,
+            Self::Mono6x9 => &FONT_6X9,
+            Self::Mono5x8 => &FONT_5X8,
+            Self::Mono5x7 => &FONT_5X7,
+            Self::Mono4x6 => &FONT_4X6,
+        }
+    }
+
+    /// Return `text` in this font, centered vertically on the 11 rows of the display
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)] // all fonts are at most 10 pixels high
+    pub fn text(self, text: &str) -> Text<'_, MonoTextStyle<'static, BinaryColor>> {
+        let font = self.mono_font();
+        let top = (11 - i32::try_from(font.character_size.height).unwrap()) / 2;
+        Text::with_baseline(
+            text,
+            Point::new(0, top),
+            MonoTextStyle::new(font, BinaryColor::On),
+            Baseline::Top,
+        )
+    }
+}
+
+/// Number of columns visible on the display at once
+pub const DISPLAY_WIDTH: usize = 44;
+
+/// Number of rows of the display
+pub const DISPLAY_HEIGHT: usize = 11;
+
+/// Payloads are padded with zeros to a multiple of this number of bytes
+pub const MSG_PADDING_ALIGN: usize = 64;
+
+/// Size of the header at the start of every payload
+pub const HEADER_SIZE: usize = size_of::<Header>();
+
+/// Maximum size of a padded payload, the badges brick themselves when writing larger ones
+pub const MAX_PAYLOAD_SIZE: usize = 8192;
+
+/// Size of the HID reports used to write a payload over USB
+pub const USB_REPORT_SIZE: usize = 64;
+
+/// Size of the chunks a payload is split into to write it over BLE
+pub const BLE_CHUNK_SIZE: usize = 16;
+
+/// Return the length of a payload of `len` bytes after padding
+/// ```
+/// use badgemagic::protocol::{padded_len, MAX_PAYLOAD_SIZE};
+///
+/// assert_eq!(padded_len(64), 64);
+/// assert_eq!(padded_len(65), 128);
+/// assert!(padded_len(8193) > MAX_PAYLOAD_SIZE);
+/// ```
+#[must_use]
+pub const fn padded_len(len: usize) -> usize {
+    len.next_multiple_of(MSG_PADDING_ALIGN)
+}
 
 const MAGIC: [u8; 6] = *b"wang\0\0";
 
@@ -210,19 +425,63 @@ struct Header {
     _padding_2: [u8; 20],
 }
 
-#[derive(FromBytes, IntoBytes, Immutable, KnownLayout)]
+impl Header {
+    fn new() -> Self {
+        Self {
+            magic: MAGIC,
+            blink: 0,
+            border: 0,
+            speed_and_mode: [0; 8],
+            message_length: [0.into(); 8],
+            _padding_1: [0; 6],
+            #[cfg(feature = "time")]
+            timestamp: Timestamp::now(),
+            #[cfg(not(feature = "time"))]
+            timestamp: Timestamp::default(),
+            _padding_2: [0; 20],
+        }
+    }
+}
+
+/// Time stored in the payload header
+///
+/// Without the `time` feature, new payloads contain a zeroed timestamp.
+/// ```
+/// use badgemagic::protocol::{PayloadBuffer, Timestamp};
+///
+/// let mut buffer = PayloadBuffer::new();
+/// buffer.set_timestamp(Timestamp {
+///     year: 25,
+///     month: 1,
+///     day: 31,
+///     hour: 12,
+///     minute: 0,
+///     second: 0,
+/// });
+/// assert_eq!(buffer.timestamp().day, 31);
+/// ```
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Hash, FromBytes, IntoBytes, Immutable, KnownLayout,
+)]
 #[repr(C)]
-struct Timestamp {
-    year: u8,
-    month: u8,
-    day: u8,
-    hour: u8,
-    minute: u8,
-    second: u8,
+pub struct Timestamp {
+    /// Year without the century (0-99)
+    pub year: u8,
+    /// Month (1-12)
+    pub month: u8,
+    /// Day of the month (1-31)
+    pub day: u8,
+    /// Hour (0-23)
+    pub hour: u8,
+    /// Minute (0-59)
+    pub minute: u8,
+    /// Second (0-59)
+    pub second: u8,
 }
 
-impl Timestamp {
-    fn new(ts: OffsetDateTime) -> Self {
+#[cfg(feature = "time")]
+impl From<OffsetDateTime> for Timestamp {
+    fn from(ts: OffsetDateTime) -> Self {
         Self {
             #[allow(clippy::cast_possible_truncation)] // clippy does not understand `rem_euclid(100) <= 100`
             year: ts.year().rem_euclid(100) as u8,
@@ -233,9 +492,14 @@ impl Timestamp {
             second: ts.second(),
         }
     }
+}
 
-    fn now() -> Self {
-        Self::new(OffsetDateTime::now_utc())
+impl Timestamp {
+    /// Return the current time (UTC)
+    #[cfg(feature = "time")]
+    #[must_use]
+    pub fn now() -> Self {
+        OffsetDateTime::now_utc().into()
     }
 }
 
@@ -264,9 +528,11 @@ impl Timestamp {
 /// # #[cfg(not(feature = "embedded-graphics"))]
 /// # fn main() {}
 /// ```
+#[derive(Clone)]
 pub struct PayloadBuffer {
     num_messages: u8,
     data: Vec<u8>,
+    draw_mode: DrawMode,
 }
 
 impl Default for PayloadBuffer {
@@ -277,29 +543,182 @@ impl Default for PayloadBuffer {
 
 impl PayloadBuffer {
     /// Create a new empty buffer
+    ///
+    /// The header contains the current time, or a zeroed timestamp without the `time` feature.
     #[must_use]
     pub fn new() -> Self {
         Self {
             num_messages: 0,
-            data: Header {
-                magic: MAGIC,
-                blink: 0,
-                border: 0,
-                speed_and_mode: [0; 8],
-                message_length: [0.into(); 8],
-                _padding_1: [0; 6],
-                timestamp: Timestamp::now(),
-                _padding_2: [0; 20],
+            data: Header::new().as_bytes().into(),
+            draw_mode: DrawMode::default(),
+        }
+    }
+
+    /// Select what happens when content is drawn outside of a message
+    ///
+    /// Applications drawing user provided content can use [`DrawMode::Strict`]
+    /// to report an error instead of panicking.
+    /// ```
+    /// # #[cfg(feature = "embedded-graphics")]
+    /// # fn main() {
+    /// use badgemagic::{
+    ///     embedded_graphics::{
+    ///         geometry::{Point, Size},
+    ///         pixelcolor::BinaryColor,
+    ///         primitives::{PrimitiveStyle, Rectangle, Styled},
+    ///     },
+    ///     protocol::{DrawMode, PayloadBuffer, Style},
+    ///     Error,
+    /// };
+    ///
+    /// // 12 rows are one too many for the display
+    /// let art = Styled::new(
+    ///     Rectangle::new(Point::new(0, 0), Size::new(8, 12)),
+    ///     PrimitiveStyle::with_fill(BinaryColor::On),
+    /// );
+    /// let mut buffer = PayloadBuffer::new().draw_mode(DrawMode::Strict);
+    /// let result = buffer.try_add_message_drawable(Style::default(), &art);
+    /// assert!(matches!(result, Err(Error::OutOfBounds { x: 0, y: 11 })));
+    /// assert_eq!(buffer.num_messages(), 0);
+    ///
+    /// let mut buffer = PayloadBuffer::new().draw_mode(DrawMode::Clip);
+    /// buffer.add_message_drawable(Style::default(), &art);
+    /// assert_eq!(buffer.num_messages(), 1);
+    /// # }
+    /// # #[cfg(not(feature = "embedded-graphics"))]
+    /// # fn main() {}
+    /// ```
+    #[must_use]
+    pub fn draw_mode(mut self, draw_mode: DrawMode) -> Self {
+        self.draw_mode = draw_mode;
+        self
+    }
+
+    /// Create a buffer containing all `messages`
+    ///
+    /// Fails if more than 8 messages are supplied or a message could not be drawn.
+    /// ```
+    /// use badgemagic::{
+    ///     embedded_graphics::{
+    ///         geometry::Point,
+    ///         mono_font::{ascii::FONT_6X9, MonoTextStyle},
+    ///         pixelcolor::BinaryColor,
+    ///         text::Text,
+    ///     },
+    ///     protocol::{Mode, PayloadBuffer, Style},
+    /// };
+    ///
+    /// let text = MonoTextStyle::new(&FONT_6X9, BinaryColor::On);
+    /// let buffer = PayloadBuffer::with_messages([
+    ///     (Style::default(), Text::new("Hello", Point::new(0, 7), text)),
+    ///     (
+    ///         Style::default().mode(Mode::Center),
+    ///         Text::new("World", Point::new(0, 7), text),
+    ///     ),
+    /// ])?;
+    ///
+    /// let messages = vec![(Style::default(), Text::new("!", Point::new(0, 7), text)); 9];
+    /// assert!(matches!(
+    ///     PayloadBuffer::with_messages(messages),
+    ///     Err(badgemagic::Error::TooManyMessages(9))
+    /// ));
+    /// # Ok::<(), badgemagic::Error>(())
+    /// ```
+    #[cfg(feature = "embedded-graphics")]
+    pub fn with_messages<D>(messages: impl IntoIterator<Item = (Style, D)>) -> Result<Self>
+    where
+        D: Drawable<Color = BinaryColor> + Dimensions,
+    {
+        let mut buffer = Self::new();
+        let mut messages = messages.into_iter();
+        while let Some((style, content)) = messages.next() {
+            if buffer.num_messages == 8 {
+                return Err(Error::TooManyMessages(9 + messages.count()));
             }
-            .as_bytes()
-            .into(),
+            buffer.try_add_message_drawable(style, &content)?;
+        }
+        Ok(buffer)
+    }
+
+    /// Parse a payload, e.g. as returned by [`PayloadBuffer::as_bytes`]
+    ///
+    /// Padding after the last message is removed.
+    /// Empty messages at the end of the payload are dropped,
+    /// as the header does not store the number of messages.
+    #[allow(clippy::missing_panics_doc)] // there are at most 8 messages
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let invalid = |message: String| Error::InvalidPayload(message);
+        let (header, _) = Header::ref_from_prefix(data)
+            .map_err(|_| invalid("payload too short for header".into()))?;
+        if header.magic != MAGIC {
+            return Err(invalid("invalid payload magic".into()));
+        }
+
+        let lengths = header.message_length.map(|len| usize::from(len.get()));
+        let num_messages = lengths
+            .iter()
+            .rposition(|&len| len > 0)
+            .map_or(0, |i| i + 1);
+        let len = size_of::<Header>() + lengths.iter().sum::<usize>() * 11;
+        if data.len() < len {
+            return Err(invalid(format!(
+                "payload too short: {} of {len} bytes",
+                data.len()
+            )));
+        }
+        for &speed_and_mode in &header.speed_and_mode[..num_messages] {
+            Speed::try_from(speed_and_mode >> 4).map_err(|_| invalid("invalid speed".into()))?;
+            Mode::try_from(speed_and_mode & 0x0f).map_err(|_| invalid("invalid mode".into()))?;
         }
+
+        Ok(Self {
+            num_messages: u8::try_from(num_messages).unwrap(),
+            data: data[..len].into(),
+            draw_mode: DrawMode::default(),
+        })
+    }
+
+    /// Return the length of the padded payload starting with `data`,
+    /// if `data` contains a complete header
+    #[cfg(feature = "emulator")]
+    pub(crate) fn padded_len(data: &[u8]) -> Option<usize> {
+        let (header, _) = Header::ref_from_prefix(data).ok()?;
+        let messages = header
+            .message_length
+            .iter()
+            .map(|len| usize::from(len.get()))
+            .sum::<usize>();
+        Some(padded_len(HEADER_SIZE + messages * 11))
+    }
+
+    fn header(&self) -> &Header {
+        Header::ref_from_prefix(&self.data).unwrap().0
     }
 
     fn header_mut(&mut self) -> &mut Header {
         Header::mut_from_prefix(&mut self.data).unwrap().0
     }
 
+    /// Return the timestamp stored in the header
+    #[must_use]
+    pub fn timestamp(&self) -> Timestamp {
+        self.header().timestamp
+    }
+
+    /// Replace the timestamp stored in the header
+    pub fn set_timestamp(&mut self, timestamp: Timestamp) {
+        self.header_mut().timestamp = timestamp;
+    }
+
+    /// Remove all messages, keeping the allocation for the next messages
+    ///
+    /// The header is reset like in [`PayloadBuffer::new`], including the timestamp.
+    pub fn clear(&mut self) {
+        self.num_messages = 0;
+        self.data.truncate(size_of::<Header>());
+        *self.header_mut() = Header::new();
+    }
+
     /// Return the current number of messages
     pub fn num_messages(&mut self) -> usize {
         self.num_messages as usize
@@ -308,13 +727,29 @@ impl PayloadBuffer {
     /// Add a messages containing the specified `content`
     ///
     /// ## Panics
-    /// This method panics if it is unable to draw the content.
+    /// This method panics if it is unable to draw the content,
+    /// e.g. when drawing outside of the message (unless the [`DrawMode`] is [`DrawMode::Clip`]).
     #[cfg(feature = "embedded-graphics")]
     pub fn add_message_drawable<O>(
         &mut self,
         style: Style,
         content: &(impl Drawable<Color = BinaryColor, Output = O> + Dimensions),
     ) -> O {
+        self.try_add_message_drawable(style, content).unwrap()
+    }
+
+    /// Add a messages containing the specified `content`, or return the error of drawing it
+    ///
+    /// The message is not added if drawing fails, see [`PayloadBuffer::draw_mode`].
+    ///
+    /// ## Panics
+    /// Panics if the supported number of messages is reached.
+    #[cfg(feature = "embedded-graphics")]
+    pub fn try_add_message_drawable<O>(
+        &mut self,
+        style: Style,
+        content: &(impl Drawable<Color = BinaryColor, Output = O> + Dimensions),
+    ) -> Result<O> {
         #[allow(clippy::cast_possible_wrap)]
         fn saturating_usize_to_isize(n: usize) -> isize {
             usize::min(n, isize::MAX as usize) as isize
@@ -327,8 +762,46 @@ impl PayloadBuffer {
 
         let bounds = content.bounding_box();
         let width = add(bounds.top_left.x, bounds.size.width);
-        let mut message = self.add_message(style, (width + 7) / 8);
-        content.draw(&mut message).unwrap()
+        let mut message = self.add_message(style, width.div_ceil(8));
+        let result = content.draw(&mut message);
+        if result.is_err() {
+            self.remove_last_message();
+        }
+        result
+    }
+
+    /// Remove the message added last, e.g. when drawing it failed
+    #[cfg(feature = "embedded-graphics")]
+    fn remove_last_message(&mut self) {
+        let Some(index) = self.num_messages.checked_sub(1) else {
+            return;
+        };
+        self.num_messages = index;
+        let index = usize::from(index);
+        let header = self.header_mut();
+        let len = usize::from(header.message_length[index].get());
+        header.blink &= !(1 << index);
+        header.border &= !(1 << index);
+        header.speed_and_mode[index] = 0;
+        header.message_length[index] = 0.into();
+        self.data.truncate(self.data.len() - len * 11);
+    }
+
+    /// Add a message containing `text` rendered in `font`
+    ///
+    /// The message is as wide as the text.
+    /// ```
+    /// use badgemagic::protocol::{Font, PayloadBuffer, Style};
+    ///
+    /// let mut buffer = PayloadBuffer::new();
+    /// buffer.add_text(Style::default(), "Hello", Font::default());
+    /// ```
+    ///
+    /// ## Panics
+    /// Panics if the supported number of messages is reached.
+    #[cfg(feature = "embedded-graphics")]
+    pub fn add_text(&mut self, style: Style, text: &str, font: Font) {
+        self.add_message_drawable(style, &font.text(text));
     }
 
     /// Add a message with `count * 8`  columns
@@ -338,13 +811,20 @@ impl PayloadBuffer {
     ///
     /// ## Panics
     /// Panics if the supported number of messages is reached.
-    pub fn add_message(&mut self, style: Style, count: usize) -> MessageBuffer {
+    pub fn add_message(&mut self, style: Style, count: usize) -> MessageBuffer<'_> {
         let index = self.num_messages as usize;
         assert!(
             index < 8,
             "maximum number of supported messages reached: {index} messages",
         );
         self.num_messages += 1;
+        event!(
+            tracing::Level::DEBUG,
+            index,
+            columns = count * 8,
+            ?style,
+            "add message"
+        );
 
         let header = self.header_mut();
 
@@ -359,7 +839,38 @@ impl PayloadBuffer {
 
         let start = self.data.len();
         self.data.resize(start + count * 11, 0);
-        MessageBuffer(FromBytes::mut_from_bytes(&mut self.data[start..]).unwrap())
+        MessageBuffer {
+            data: FromBytes::mut_from_bytes(&mut self.data[start..]).unwrap(),
+            draw_mode: self.draw_mode,
+        }
+    }
+
+    /// Return the style and content of the message at `index`
+    ///
+    /// The content consists of 11 bytes (one per row) for every 8 columns.
+    /// Returns `None` if there is no message at `index`.
+    #[must_use]
+    pub fn message(&self, index: usize) -> Option<(Style, &[[u8; 11]])> {
+        if index >= self.num_messages as usize {
+            return None;
+        }
+        let header = self.header();
+        let bit = 1 << index;
+        let speed_and_mode = header.speed_and_mode[index];
+        let style = Style {
+            blink: header.blink & bit != 0,
+            border: header.border & bit != 0,
+            speed: Speed::try_from(speed_and_mode >> 4).ok()?,
+            mode: Mode::try_from(speed_and_mode & 0x0f).ok()?,
+        };
+        let start = size_of::<Header>()
+            + header.message_length[..index]
+                .iter()
+                .map(|len| usize::from(len.get()) * 11)
+                .sum::<usize>();
+        let end = start + usize::from(header.message_length[index].get()) * 11;
+        let content = FromBytes::ref_from_bytes(&self.data[start..end]).ok()?;
+        Some((style, content))
     }
 
     /// Get the current payload as bytes (without padding)
@@ -377,13 +888,10 @@ impl PayloadBuffer {
         let prev_len = data.len();
 
         // pad msg to align to 64 bytes
-        data.resize(
-            (data.len() + (MSG_PADDING_ALIGN - 1)) & !(MSG_PADDING_ALIGN - 1),
-            0,
-        );
+        data.resize(padded_len(data.len()), 0);
 
         // validate alignment
-        assert_eq!(data.len() % 64, 0);
+        assert_eq!(data.len() % MSG_PADDING_ALIGN, 0);
         assert!(prev_len <= data.len());
 
         data
@@ -393,14 +901,57 @@ impl PayloadBuffer {
 /// A display buffer for a single message.
 ///
 /// Can be used as an `embedded_graphics::DrawTarget`.
-pub struct MessageBuffer<'a>(&'a mut [[u8; 11]]);
+/// Drawing pixels outside of the buffer panics by default,
+/// the [`DrawMode`] of the [`PayloadBuffer`] or `util::DrawTargetTransformExt::clip`
+/// can drop them or return an error instead.
+pub struct MessageBuffer<'a> {
+    data: &'a mut [[u8; 11]],
+    #[cfg_attr(not(feature = "embedded-graphics"), allow(dead_code))]
+    draw_mode: DrawMode,
+}
+
+/// What happens when drawing pixels outside of a message, see [`PayloadBuffer::draw_mode`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DrawMode {
+    /// Panic, to find layout bugs early
+    #[default]
+    Panic,
+
+    /// Silently drop the pixels
+    Clip,
+
+    /// Return [`Error::OutOfBounds`] from drawing
+    Strict,
+}
 
 impl MessageBuffer<'_> {
+    /// Return the number of columns, always a multiple of 8
+    /// ```
+    /// use badgemagic::protocol::{PayloadBuffer, State, Style};
+    ///
+    /// let mut payload = PayloadBuffer::new();
+    /// let mut message = payload.add_message(Style::default(), 2);
+    /// assert_eq!((message.width(), message.height()), (16, 11));
+    /// for x in 0..message.width() {
+    ///     message.set((x, message.height() - 1), State::On).unwrap();
+    /// }
+    /// ```
+    #[must_use]
+    pub fn width(&self) -> usize {
+        self.data.len() * 8
+    }
+
+    /// Return the number of rows, which is the height of the display
+    #[must_use]
+    pub fn height(&self) -> usize {
+        DISPLAY_HEIGHT
+    }
+
     /// Set the state of the pixel at point (`x`, `y`)
     ///
     /// Returns `None` if the pixel was out of bounds.
     pub fn set(&mut self, (x, y): (usize, usize), state: State) -> Option<()> {
-        let byte = self.0.get_mut(x / 8)?.get_mut(y)?;
+        let byte = self.data.get_mut(x / 8)?.get_mut(y)?;
         let bit = 0x80 >> (x % 8);
         match state {
             State::Off => {
@@ -413,6 +964,37 @@ impl MessageBuffer<'_> {
         Some(())
     }
 
+    /// Invert the state of all pixels
+    ///
+    /// Lit pixels are turned off and dark pixels are turned on.
+    pub fn invert(&mut self) {
+        for byte in self.data.iter_mut().flatten() {
+            *byte = !*byte;
+        }
+    }
+
+    /// Rotate the message by 180 degrees
+    ///
+    /// This turns the whole message (including the padding to a multiple of 8 columns)
+    /// upside down, e.g. for badges that are worn upside down.
+    pub fn rotate_180(&mut self) {
+        self.mirror();
+        for column in self.data.iter_mut() {
+            column.reverse();
+        }
+    }
+
+    /// Mirror the message horizontally
+    ///
+    /// This mirrors the whole message (including the padding to a multiple of 8 columns),
+    /// e.g. for badges that are read through a mirror.
+    pub fn mirror(&mut self) {
+        self.data.reverse();
+        for byte in self.data.iter_mut().flatten() {
+            *byte = byte.reverse_bits();
+        }
+    }
+
     #[cfg(feature = "embedded-graphics")]
     fn set_embedded_graphics(&mut self, point: Point, color: BinaryColor) -> Option<()> {
         let x = point.x.try_into().ok()?;
@@ -454,7 +1036,10 @@ impl Dimensions for MessageBuffer<'_> {
     fn bounding_box(&self) -> embedded_graphics::primitives::Rectangle {
         Rectangle::new(
             Point::zero(),
-            Size::new((self.0.len() * 8).try_into().unwrap(), 11),
+            Size::new(
+                self.width().try_into().unwrap(),
+                self.height().try_into().unwrap(),
+            ),
         )
     }
 }
@@ -463,19 +1048,24 @@ impl Dimensions for MessageBuffer<'_> {
 impl DrawTarget for MessageBuffer<'_> {
     type Color = BinaryColor;
 
-    type Error = std::convert::Infallible;
+    type Error = Error;
 
     fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
     where
         I: IntoIterator<Item = Pixel<Self::Color>>,
     {
         for Pixel(point, color) in pixels {
-            #[allow(clippy::manual_assert)]
-            if self.set_embedded_graphics(point, color).is_none() {
-                panic!(
-                    "tried to draw pixel outside the display area (x: {}, y: {})",
-                    point.x, point.y
-                );
+            if self.set_embedded_graphics(point, color).is_some() {
+                continue;
+            }
+            let error = Error::OutOfBounds {
+                x: point.x,
+                y: point.y,
+            };
+            match self.draw_mode {
+                DrawMode::Panic => panic!("{error}"),
+                DrawMode::Clip => {}
+                DrawMode::Strict => return Err(error),
             }
         }
         Ok(())
@@ -484,9 +1074,10 @@ impl DrawTarget for MessageBuffer<'_> {
 
 #[cfg(test)]
 mod test {
-    use std::ops::Range;
+    use std::{ops::Range, time::Duration};
 
-    use super::Speed;
+    use super::{Mode, PayloadBuffer, Speed, State, Style};
+    use crate::Error;
 
     #[test]
     fn speed_to_u8_and_back() {
@@ -499,4 +1090,140 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn speed_for_scroll_inverts_scroll_duration() {
+        for speed in Speed::ALL {
+            assert_eq!(Speed::for_scroll(100, speed.scroll_duration(100)), speed);
+        }
+        assert_eq!(
+            Speed::for_scroll(10, Duration::from_secs(30)),
+            Speed::Fps1_2
+        );
+        assert_eq!(Speed::for_scroll(1000, Duration::ZERO), Speed::Fps15);
+    }
+
+    #[test]
+    fn mode_to_u8_and_back() {
+        const VALID_MODE_VALUES: Range<u8> = 0..9;
+        for i in u8::MIN..u8::MAX {
+            if let Ok(mode) = Mode::try_from(i) {
+                assert_eq!(u8::from(mode), i);
+            } else {
+                assert!(!VALID_MODE_VALUES.contains(&i));
+            }
+        }
+    }
+
+    #[test]
+    fn read_back_messages() {
+        let mut buffer = PayloadBuffer::new();
+        buffer.add_message(Style::default().blink(), 1);
+        buffer
+            .add_message(Style::default().border().mode(Mode::Center), 2)
+            .set((9, 3), State::On);
+
+        let (style, content) = buffer.message(1).unwrap();
+        assert_eq!(style, Style::default().border().mode(Mode::Center));
+        assert_eq!(content.len(), 2);
+        assert_eq!(content[1][3], 0x40);
+        assert!(buffer.message(0).unwrap().0.is_blink());
+        assert!(buffer.message(2).is_none());
+
+        let bytes = buffer.as_bytes().to_vec();
+        let parsed = PayloadBuffer::from_bytes(buffer.into_padded_bytes().as_ref()).unwrap();
+        assert_eq!(parsed.as_bytes(), bytes);
+        assert_eq!(parsed.message(1).unwrap().1[1][3], 0x40);
+        assert!(matches!(
+            PayloadBuffer::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(Error::InvalidPayload(_))
+        ));
+    }
+
+    #[test]
+    fn clear_keeps_allocation() {
+        let mut buffer = PayloadBuffer::new();
+        buffer.add_message(Style::default().blink().mode(Mode::Center), 4);
+        let capacity = buffer.data.capacity();
+
+        buffer.clear();
+        assert_eq!(buffer.num_messages(), 0);
+        assert_eq!(buffer.data.capacity(), capacity);
+        assert_eq!(
+            buffer.as_bytes().len(),
+            PayloadBuffer::new().as_bytes().len()
+        );
+
+        buffer.add_message(Style::default(), 1);
+        assert_eq!(buffer.message(0).unwrap().0, Style::default());
+        assert!(buffer.message(1).is_none());
+    }
+
+    #[test]
+    fn rotate_message_180() {
+        let mut payload = PayloadBuffer::new();
+        let mut message = payload.add_message(Style::default(), 2);
+        message.set((0, 0), State::On).unwrap();
+        message.set((9, 3), State::On).unwrap();
+        message.rotate_180();
+
+        let mut expected = [[0; 11]; 2];
+        expected[1][10] = 0x01;
+        expected[0][7] = 0x02;
+        assert_eq!(message.data, expected);
+    }
+
+    #[cfg(feature = "embedded-graphics")]
+    #[test]
+    fn failed_drawing_removes_message() {
+        use embedded_graphics::{
+            geometry::{Point, Size},
+            pixelcolor::BinaryColor,
+            primitives::{PrimitiveStyle, Rectangle, Styled},
+        };
+
+        use super::DrawMode;
+
+        let fill = PrimitiveStyle::with_fill(BinaryColor::On);
+        let valid = Styled::new(Rectangle::new(Point::zero(), Size::new(8, 11)), fill);
+        let too_high = Styled::new(Rectangle::new(Point::zero(), Size::new(16, 12)), fill);
+
+        let mut expected = PayloadBuffer::new();
+        expected.add_message_drawable(Style::default(), &valid);
+
+        let mut buffer = PayloadBuffer::new().draw_mode(DrawMode::Strict);
+        buffer.add_message_drawable(Style::default(), &valid);
+        let result = buffer.try_add_message_drawable(Style::default().blink().border(), &too_high);
+        assert!(matches!(result, Err(Error::OutOfBounds { .. })));
+        buffer.set_timestamp(expected.timestamp());
+        assert_eq!(buffer.as_bytes(), expected.as_bytes());
+    }
+
+    #[cfg(feature = "embedded-graphics")]
+    #[test]
+    fn strict_drawing_error_propagates() {
+        use embedded_graphics::{
+            draw_target::DrawTargetExt,
+            geometry::{Point, Size},
+            pixelcolor::BinaryColor,
+            primitives::{PrimitiveStyle, Rectangle, Styled},
+            Drawable,
+        };
+
+        use super::DrawMode;
+
+        let dot = Styled::new(
+            Rectangle::new(Point::zero(), Size::new(1, 1)),
+            PrimitiveStyle::with_fill(BinaryColor::On),
+        );
+
+        let mut buffer = PayloadBuffer::new().draw_mode(DrawMode::Strict);
+        let mut message = buffer.add_message(Style::default(), 1);
+        dot.draw(&mut message.translated(Point::new(7, 10)))
+            .unwrap();
+        let result = dot.draw(&mut message.translated(Point::new(8, 3)));
+        assert!(matches!(result, Err(Error::OutOfBounds { x: 8, y: 3 })));
+        let result = dot.draw(&mut message.translated(Point::new(2, -1)));
+        assert!(matches!(result, Err(Error::OutOfBounds { x: 2, y: -1 })));
+    }
 }
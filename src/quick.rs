@@ -0,0 +1,80 @@
+//! Show a text on the badge with a single call
+//!
+//! ```no_run
+//! # fn main() -> badgemagic::Result<()> {
+//! badgemagic::quick::write_text("Hello", Default::default())?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    protocol::{Font, Mode, PayloadBuffer, Speed, Style},
+    Result,
+};
+
+/// Style of the message shown by [`write_text`]
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    /// Display mode, defaults to [`Mode::Center`]
+    pub mode: Mode,
+
+    /// Animation speed
+    pub speed: Speed,
+
+    /// Enable blink mode
+    pub blink: bool,
+
+    /// Show an animated border
+    pub border: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Center,
+            speed: Speed::default(),
+            blink: false,
+            border: false,
+        }
+    }
+}
+
+/// Show `text` as single message on the badge
+///
+/// A badge connected over USB is preferred,
+/// if there is none the badge is searched over Bluetooth Low Energy (if the `ble` feature is enabled).
+pub fn write_text(text: &str, options: Options) -> Result<()> {
+    let mut style = Style::default().mode(options.mode).speed(options.speed);
+    if options.blink {
+        style = style.blink();
+    }
+    if options.border {
+        style = style.border();
+    }
+
+    let mut payload = PayloadBuffer::new();
+    payload.add_text(style, text, Font::default());
+
+    #[cfg(feature = "usb-hid")]
+    let payload = match crate::usb_hid::Device::single() {
+        Ok(device) => return device.write(payload),
+        Err(crate::Error::NoDevice) => payload,
+        Err(err) => return Err(err),
+    };
+    write_ble(payload)
+}
+
+#[cfg(feature = "ble")]
+fn write_ble(payload: PayloadBuffer) -> Result<()> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(crate::Error::transfer("create runtime"))?
+        .block_on(async { crate::ble::Device::single().await?.write(payload).await })
+}
+
+#[cfg(not(feature = "ble"))]
+#[allow(clippy::needless_pass_by_value)]
+fn write_ble(_payload: PayloadBuffer) -> Result<()> {
+    Err(crate::Error::NoDevice)
+}
@@ -0,0 +1,198 @@
+//! Record the data written to badges and read the recordings back
+//!
+//! While recording, every payload write is logged with the device
+//! and every chunk sent to it, e.g. to compare transfers over USB and BLE.
+//! The recording is a text file with one line per event,
+//! starting with the milliseconds since recording started:
+//! ```text
+//! 0 ble write AA:BB:CC:DD:EE:FF
+//! 812 ble chunk 77616e67000000000000000000000000
+//! 815 ble chunk 00000000000000000000000000000000
+//! ```
+//! ```no_run
+//! # fn main() -> std::io::Result<()> {
+//! badgemagic::record::start("session.log".as_ref())?;
+//! // write payloads to the badges
+//! for transfer in badgemagic::record::read("session.log".as_ref())? {
+//!     println!("{} chunks to {}", transfer.chunks.len(), transfer.device);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fmt::Write as _,
+    fs::{self, File, OpenOptions},
+    io::{self, LineWriter, Write as _},
+    path::Path,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// Transport a transfer was recorded on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// USB HID, every chunk is a complete padded payload
+    Usb,
+
+    /// Bluetooth Low Energy, every chunk is written to the badge characteristic
+    Ble,
+}
+
+impl Transport {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Usb => "usb",
+            Self::Ble => "ble",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "usb" => Self::Usb,
+            "ble" => Self::Ble,
+            _ => return None,
+        })
+    }
+}
+
+/// A payload written to a single device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transfer {
+    /// Transport the payload was written with
+    pub transport: Transport,
+
+    /// Serial number (USB) or address (BLE) of the badge, empty if unknown
+    pub device: String,
+
+    /// Chunks in the order they were written, with the time since recording started
+    pub chunks: Vec<(Duration, Vec<u8>)>,
+}
+
+struct Recorder {
+    start: Instant,
+    file: LineWriter<File>,
+}
+
+static RECORDER: Mutex<Option<Recorder>> = Mutex::new(None);
+
+fn recorder() -> MutexGuard<'static, Option<Recorder>> {
+    RECORDER.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Append every following write to the file at `path`
+pub fn start(path: &Path) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    *recorder() = Some(Recorder {
+        start: Instant::now(),
+        file: LineWriter::new(file),
+    });
+    Ok(())
+}
+
+/// Record the start of a payload write to `device`
+pub(crate) fn transfer(transport: Transport, device: &str) {
+    log(transport, "write", device);
+}
+
+/// Record a chunk written to the device of the current transfer
+pub(crate) fn chunk(transport: Transport, data: &[u8]) {
+    if recorder().is_some() {
+        log(transport, "chunk", &hex(data));
+    }
+}
+
+fn log(transport: Transport, kind: &str, value: &str) {
+    if let Some(recorder) = recorder().as_mut() {
+        let elapsed = recorder.start.elapsed().as_millis();
+        let name = transport.name();
+        // recording is best effort, it must not abort the transfer
+        let _ = writeln!(recorder.file, "{elapsed} {name} {kind} {value}");
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().fold(String::new(), |mut out, byte| {
+        let _ = write!(out, "{byte:02x}");
+        out
+    })
+}
+
+/// Read the transfers of a recording
+pub fn read(path: &Path) -> io::Result<Vec<Transfer>> {
+    parse(&fs::read_to_string(path)?)
+}
+
+/// Parse the transfers of a recording
+///
+/// Invalid lines are reported as [`io::ErrorKind::InvalidData`].
+pub fn parse(source: &str) -> io::Result<Vec<Transfer>> {
+    let mut transfers: Vec<Transfer> = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        let invalid = |message: &str| {
+            let message = format!("line {}: {message}", number + 1);
+            io::Error::new(io::ErrorKind::InvalidData, message)
+        };
+        let mut fields = line.splitn(4, ' ');
+        let elapsed = fields
+            .next()
+            .and_then(|elapsed| elapsed.parse().ok())
+            .ok_or_else(|| invalid("invalid time"))?;
+        let transport = fields
+            .next()
+            .and_then(Transport::from_name)
+            .ok_or_else(|| invalid("unknown transport"))?;
+        let kind = fields.next().ok_or_else(|| invalid("missing event"))?;
+        let value = fields.next().unwrap_or_default();
+        let elapsed = Duration::from_millis(elapsed);
+        match kind {
+            "write" => transfers.push(Transfer {
+                transport,
+                device: value.into(),
+                chunks: Vec::new(),
+            }),
+            "chunk" => {
+                let transfer = transfers
+                    .last_mut()
+                    .filter(|transfer| transfer.transport == transport)
+                    .ok_or_else(|| invalid("chunk without write"))?;
+                let data = (0..value.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(value.get(i..i + 2).unwrap_or("invalid"), 16))
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| invalid("invalid chunk"))?;
+                transfer.chunks.push((elapsed, data));
+            }
+            _ => return Err(invalid(&format!("unknown event {kind:?}"))),
+        }
+    }
+    Ok(transfers)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{hex, parse, Transport};
+
+    #[test]
+    fn parse_recording() {
+        let source = format!(
+            "0 ble write AA:BB:CC:DD:EE:FF\n5 ble chunk {}\n9 usb write \n12 usb chunk 00ff\n",
+            hex(b"wang")
+        );
+        let transfers = parse(&source).unwrap();
+        assert_eq!(transfers.len(), 2);
+        assert_eq!(transfers[0].transport, Transport::Ble);
+        assert_eq!(transfers[0].device, "AA:BB:CC:DD:EE:FF");
+        assert_eq!(
+            transfers[0].chunks,
+            [(Duration::from_millis(5), b"wang".to_vec())]
+        );
+        assert_eq!(transfers[1].device, "");
+        assert_eq!(transfers[1].chunks[0].1, [0x00, 0xff]);
+
+        assert!(parse("1 ble chunk 00").is_err());
+        assert!(parse("1 ble write x\n2 ble chunk 0").is_err());
+    }
+}
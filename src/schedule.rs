@@ -0,0 +1,230 @@
+//! Switch between messages at fixed times of the day
+//!
+//! A schedule is a CSV file with the columns `time,config_or_text`:
+//! ```csv
+//! time,config_or_text
+//! 09:00,Come say hi
+//! 12:30,We're at lunch
+//! 13:30,talk.toml
+//! 15:00,"Talk at 14:00, room B"
+//! ```
+//! Every entry is shown from its time until the next entry, the last entry until the first entry of the next day.
+//! Entries ending in `.toml` or `.json` are configs (relative to the schedule), others are shown as text.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use badgemagic::{
+    config::{Config, Message},
+    protocol::PayloadBuffer,
+};
+use time::Time;
+
+/// What an entry shows
+pub enum Content {
+    Config(PathBuf),
+    Text(String),
+}
+
+pub struct Entry {
+    pub time: Time,
+    pub content: Content,
+}
+
+impl Entry {
+    /// Build the payload of the entry, configs are loaded every time to pick up changes
+    pub fn build_payload(&self) -> Result<PayloadBuffer> {
+        match &self.content {
+            Content::Config(path) => crate::build_payload(&Config::load(path, None)?, None),
+            Content::Text(text) => crate::build_payload(
+                &Config::default().message(Message::text(text.as_str())),
+                None,
+            ),
+        }
+    }
+
+    /// Describe the entry for log messages
+    pub fn label(&self) -> String {
+        match &self.content {
+            Content::Config(path) => path.display().to_string(),
+            Content::Text(text) => format!("{text:?}"),
+        }
+    }
+}
+
+/// Entries of a schedule, sorted by time
+pub struct Schedule {
+    entries: Vec<Entry>,
+}
+
+impl Schedule {
+    /// Load the schedule CSV at `path`
+    pub fn load(path: &Path) -> Result<Self> {
+        let csv = fs::read_to_string(path)
+            .with_context(|| format!("load schedule: {}", path.display()))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        Self::parse(&csv, dir).with_context(|| format!("load schedule: {}", path.display()))
+    }
+
+    /// Parse the lines of a schedule, config paths are relative to `dir`
+    ///
+    /// Empty lines, comments (`#`) and a header line are skipped.
+    fn parse(csv: &str, dir: &Path) -> Result<Self> {
+        let mut entries = Vec::new();
+        for (number, line) in csv.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty()
+                || line.starts_with('#')
+                || (number == 0 && line.starts_with("time,"))
+            {
+                continue;
+            }
+            let entry = parse_entry(line, dir).with_context(|| format!("line {}", number + 1))?;
+            entries.push(entry);
+        }
+        anyhow::ensure!(!entries.is_empty(), "schedule has no entries");
+        entries.sort_by_key(|entry| entry.time);
+        if let Some(pair) = entries.windows(2).find(|pair| pair[0].time == pair[1].time) {
+            anyhow::bail!("multiple entries at {}", format_time(pair[0].time));
+        }
+        Ok(Self { entries })
+    }
+
+    /// Return the index and entry shown at `now`
+    pub fn active(&self, now: Time) -> (usize, &Entry) {
+        let index = self
+            .entries
+            .iter()
+            .rposition(|entry| entry.time <= now)
+            // before the first entry, the last entry of the previous day is still shown
+            .unwrap_or(self.entries.len() - 1);
+        (index, &self.entries[index])
+    }
+
+    /// Return the time until the next entry starts
+    pub fn until_next(&self, now: Time) -> Duration {
+        let next = self
+            .entries
+            .iter()
+            .find(|entry| entry.time > now)
+            .unwrap_or(&self.entries[0]);
+        let until = next.time - now;
+        // the next entry is tomorrow
+        let until = if until.is_positive() {
+            until
+        } else {
+            until + time::Duration::DAY
+        };
+        until.try_into().unwrap_or_default()
+    }
+}
+
+fn parse_entry(line: &str, dir: &Path) -> Result<Entry> {
+    let (time, content) = line
+        .split_once(',')
+        .context("expected time,config_or_text")?;
+    let time = parse_time(time.trim())?;
+    let content = content.trim();
+    // quoted fields can contain commas, quotes are doubled
+    let content = match content.strip_prefix('"').and_then(|c| c.strip_suffix('"')) {
+        Some(quoted) => quoted.replace("\"\"", "\""),
+        None => content.to_string(),
+    };
+    anyhow::ensure!(
+        !content.is_empty(),
+        "entry at {} is empty",
+        format_time(time)
+    );
+    let is_config = Path::new(&content)
+        .extension()
+        .is_some_and(|extension| extension == "toml" || extension == "json");
+    let content = if is_config {
+        Content::Config(dir.join(content))
+    } else {
+        Content::Text(content)
+    };
+    Ok(Entry { time, content })
+}
+
+/// Parse a time of the day like `14:00` or `9:30`
+fn parse_time(time: &str) -> Result<Time> {
+    let invalid = || format!("invalid time: {time:?} (expected HH:MM)");
+    let (hour, minute) = time.split_once(':').with_context(invalid)?;
+    let (hour, minute) = (
+        hour.parse().ok().with_context(invalid)?,
+        minute.parse().ok().with_context(invalid)?,
+    );
+    Time::from_hms(hour, minute, 0).with_context(invalid)
+}
+
+pub fn format_time(time: Time) -> String {
+    format!("{:02}:{:02}", time.hour(), time.minute())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::Path, time::Duration};
+
+    use time::Time;
+
+    use super::{Content, Schedule};
+
+    fn at(hour: u8, minute: u8) -> Time {
+        Time::from_hms(hour, minute, 0).unwrap()
+    }
+
+    fn text(schedule: &Schedule, now: Time) -> &str {
+        match &schedule.active(now).1.content {
+            Content::Text(text) => text,
+            Content::Config(path) => panic!("unexpected config: {}", path.display()),
+        }
+    }
+
+    #[test]
+    fn parse_entries() {
+        let csv = "time,config_or_text\n\
+            # comment\n\
+            15:00,\"Talk at 14:00, room \"\"B\"\"\"\n\
+            \n\
+            9:00, Come say hi \n\
+            13:30,talk.toml\n";
+        let schedule = Schedule::parse(csv, Path::new("booth")).unwrap();
+        let times: Vec<_> = schedule.entries.iter().map(|entry| entry.time).collect();
+        assert_eq!(times, [at(9, 0), at(13, 30), at(15, 0)]);
+        assert_eq!(text(&schedule, at(9, 0)), "Come say hi");
+        assert_eq!(text(&schedule, at(16, 0)), "Talk at 14:00, room \"B\"");
+        let Content::Config(path) = &schedule.active(at(14, 0)).1.content else {
+            panic!("expected config");
+        };
+        assert_eq!(path, Path::new("booth/talk.toml"));
+
+        assert!(Schedule::parse("9:00,a\n09:00,b", Path::new("")).is_err());
+        assert!(Schedule::parse("time,config_or_text\n", Path::new("")).is_err());
+        assert!(Schedule::parse("25:00,late", Path::new("")).is_err());
+        assert!(Schedule::parse("9:00,", Path::new("")).is_err());
+    }
+
+    #[test]
+    fn wrap_around_midnight() {
+        let schedule = Schedule::parse("09:00,day\n22:00,night", Path::new("")).unwrap();
+        assert_eq!(text(&schedule, at(8, 59)), "night");
+        assert_eq!(schedule.active(at(0, 0)).0, 1);
+        assert_eq!(text(&schedule, at(12, 0)), "day");
+        assert_eq!(text(&schedule, at(23, 0)), "night");
+
+        let minutes = |minutes: u64| Duration::from_secs(minutes * 60);
+        assert_eq!(schedule.until_next(at(8, 0)), minutes(60));
+        assert_eq!(schedule.until_next(at(9, 0)), minutes(13 * 60));
+        assert_eq!(schedule.until_next(at(23, 30)), minutes(9 * 60 + 30));
+        assert_eq!(schedule.until_next(at(22, 0)), minutes(11 * 60));
+
+        // a single entry is shown all day, until it starts again tomorrow
+        let schedule = Schedule::parse("12:00,always", Path::new("")).unwrap();
+        assert_eq!(text(&schedule, at(6, 0)), "always");
+        assert_eq!(schedule.until_next(at(12, 0)), minutes(24 * 60));
+    }
+}
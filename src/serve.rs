@@ -0,0 +1,480 @@
+//! HTTP daemon to update the badge remotely
+//!
+//! Endpoints:
+//! - `GET /`: web UI to edit, preview and send a text message
+//! - `GET /healthz`: check whether the daemon is running
+//! - `GET /metrics`: upload counters and device state in the Prometheus text format
+//! - `GET /devices`: list the devices visible to the transport
+//! - `POST /text`: show the request body as a single text message
+//! - `POST /config`: show a config (JSON if the content type is `application/json`, TOML otherwise)
+//! - `POST /preview`: render a config as animated GIF
+//!
+//! Device events and commands are also available over WebSocket, see [`websocket`].
+
+use std::{
+    fmt::Write as _,
+    io::Read,
+    net::TcpListener,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use badgemagic::{
+    ble::{Device as BleDevice, Session as BleSession},
+    config::{cache::PayloadCache, Config, Message},
+    protocol::PayloadBuffer,
+    simulator,
+    usb_hid::Device as UsbDevice,
+};
+use serde_json::json;
+use tiny_http::{Header, Method, Request, Response};
+
+use crate::{new_ble_session, TransportProtocol, SHUTDOWN_POLL_INTERVAL};
+
+mod websocket;
+
+/// Maximum size of a request body
+const MAX_BODY_SIZE: u64 = 1024 * 1024;
+
+/// Scale of the GIF preview
+const PREVIEW_SCALE: u16 = 6;
+
+/// Interval in which the last config is checked whether it has to be rendered again
+const REFRESH_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Key of the badge in the payload cache of the daemon
+const CACHE_KEY: &str = "daemon";
+
+const INDEX_HTML: &str = include_str!("serve/index.html");
+
+type DataResponse = Response<std::io::Cursor<Vec<u8>>>;
+
+/// Connection to the badge, kept open between requests
+struct Connection {
+    transport: TransportProtocol,
+    runtime: tokio::runtime::Runtime,
+    usb: Option<UsbDevice>,
+    ble: Option<BleDevice>,
+    /// Created on first use, its scan keeps running so badges are found without scanning again
+    ble_session: Option<BleSession>,
+}
+
+impl Connection {
+    fn new(transport: TransportProtocol) -> Result<Self> {
+        Ok(Self {
+            transport,
+            runtime: tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?,
+            usb: None,
+            ble: None,
+            ble_session: None,
+        })
+    }
+
+    fn list_devices(&mut self) -> Result<Vec<String>> {
+        let devices = match self.transport {
+            TransportProtocol::Usb => UsbDevice::list_all(),
+            TransportProtocol::Ble => self
+                .runtime
+                .block_on(async { ble_session(&mut self.ble_session).await?.list_all().await }),
+        }?;
+        Ok(devices)
+    }
+
+    /// Write `payload` to the badge
+    ///
+    /// The device is looked up on first use and kept for later writes,
+    /// `on_connect` is called with its serial number or address when it is found.
+    /// It is looked up again after a failed write, e.g. if the badge was replaced.
+    fn write(&mut self, mut payload: PayloadBuffer, on_connect: impl FnOnce(&str)) -> Result<()> {
+        payload.set_timestamp(crate::timezone::now());
+        let result = match self.transport {
+            TransportProtocol::Usb => {
+                if self.usb.is_none() {
+                    let device = UsbDevice::single()?;
+                    on_connect(device.serial_number().unwrap_or_default());
+                    self.usb = Some(device);
+                }
+                self.usb.as_ref().unwrap().write(payload)
+            }
+            TransportProtocol::Ble => self.runtime.block_on(async {
+                if self.ble.is_none() {
+                    let device = ble_session(&mut self.ble_session).await?.single().await?;
+                    on_connect(&device.address());
+                    self.ble = Some(device);
+                }
+                self.ble.as_ref().unwrap().write(payload).await
+            }),
+        };
+        if result.is_err() {
+            self.usb = None;
+            self.ble = None;
+        }
+        Ok(result?)
+    }
+}
+
+/// Return the BLE session, creating it on first use
+async fn ble_session(session: &mut Option<BleSession>) -> badgemagic::Result<&BleSession> {
+    match session {
+        Some(session) => Ok(session),
+        None => Ok(session.insert(new_ble_session().await?)),
+    }
+}
+
+/// State shared by the HTTP server and the WebSocket clients
+struct Daemon {
+    connection: Mutex<Connection>,
+    subscribers: Mutex<Vec<mpsc::Sender<String>>>,
+    metrics: Mutex<Metrics>,
+    /// Last config shown, if it changes when it is rendered again
+    live: Mutex<Option<Live>>,
+    /// Last payload written
+    cache: Mutex<PayloadCache>,
+}
+
+/// Config with content that changes over time (e.g. the weather)
+struct Live {
+    /// Shared with the refresh thread, which renders it without holding the lock
+    config: Arc<Config>,
+    interval: Duration,
+    next: Instant,
+}
+
+/// Counters reported by `/metrics`
+#[derive(Default)]
+struct Metrics {
+    uploads: u64,
+    failures: u64,
+    last_duration: Option<Duration>,
+    device: Option<String>,
+}
+
+impl Metrics {
+    /// Format the metrics in the Prometheus text format
+    fn to_prometheus(&self) -> String {
+        let mut text = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: &str| {
+            writeln!(text, "# HELP badgemagic_{name} {help}").unwrap();
+            writeln!(text, "# TYPE badgemagic_{name} {kind}").unwrap();
+            writeln!(text, "badgemagic_{name}{value}").unwrap();
+        };
+        metric(
+            "uploads_total",
+            "counter",
+            "Number of successful uploads",
+            &format!(" {}", self.uploads),
+        );
+        metric(
+            "upload_failures_total",
+            "counter",
+            "Number of failed uploads",
+            &format!(" {}", self.failures),
+        );
+        if let Some(duration) = self.last_duration {
+            metric(
+                "last_upload_duration_seconds",
+                "gauge",
+                "Duration of the last successful upload",
+                &format!(" {}", duration.as_secs_f64()),
+            );
+        }
+        let device = self
+            .device
+            .as_deref()
+            .unwrap_or_default()
+            .replace(['\\', '"'], "_");
+        metric(
+            "device_connected",
+            "gauge",
+            "Whether a device is connected, labeled with its serial number or address",
+            &format!(
+                "{{device=\"{device}\"}} {}",
+                u8::from(self.device.is_some())
+            ),
+        );
+        text
+    }
+}
+
+impl Daemon {
+    /// Send an event to all WebSocket clients
+    fn emit(&self, event: &serde_json::Value) {
+        let event = event.to_string();
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    /// Receive all events emitted from now on
+    fn subscribe(&self) -> mpsc::Receiver<String> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    fn list_devices(&self) -> Result<Vec<String>> {
+        self.connection.lock().unwrap().list_devices()
+    }
+
+    /// Write `payload` to the badge, reporting the progress as events
+    fn write(&self, payload: &PayloadBuffer) -> Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        self.emit(&json!({ "event": "upload_started" }));
+        let start = Instant::now();
+        let result = connection.write(payload.clone(), |device| {
+            self.metrics.lock().unwrap().device = Some(device.into());
+            self.emit(&json!({ "event": "device_connected", "device": device }));
+        });
+        let mut metrics = self.metrics.lock().unwrap();
+        match &result {
+            Ok(()) => {
+                let duration = start.elapsed();
+                metrics.uploads += 1;
+                metrics.last_duration = Some(duration);
+                self.cache.lock().unwrap().update(CACHE_KEY, payload);
+                self.emit(&json!({
+                    "event": "upload_finished",
+                    "duration_ms": duration.as_millis(),
+                }));
+            }
+            Err(err) => {
+                metrics.failures += 1;
+                metrics.device = None;
+                self.emit(&json!({
+                    "event": "upload_failed",
+                    "error": format!("{err:#}"),
+                }));
+            }
+        }
+        result
+    }
+
+    /// Write `config` rendered as `payload`, it is rendered again after its refresh interval
+    fn show(&self, config: Config, payload: &PayloadBuffer) -> Result<()> {
+        *self.live.lock().unwrap() = config.refresh_interval().and_then(|interval| {
+            Some(Live {
+                next: Instant::now().checked_add(interval)?,
+                config: Arc::new(config),
+                interval,
+            })
+        });
+        self.write(payload)
+    }
+
+    /// Render the last config again if its refresh interval passed and write it if the payload changed
+    fn refresh(&self) -> Result<()> {
+        let config = match &mut *self.live.lock().unwrap() {
+            Some(live) if live.next <= Instant::now() => {
+                live.next = Instant::now()
+                    .checked_add(live.interval)
+                    .context("refresh interval too long")?;
+                live.config.clone()
+            }
+            _ => return Ok(()),
+        };
+        // rendering may fetch from the network, requests must not wait for it
+        let payload = build_payload(&config)?;
+        // a config shown meanwhile must not be overwritten by the old one
+        let current = self
+            .live
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|live| Arc::ptr_eq(&live.config, &config));
+        if !current || self.cache.lock().unwrap().is_current(CACHE_KEY, &payload) {
+            return Ok(());
+        }
+        self.write(&payload)
+    }
+}
+
+/// Serve the HTTP API on `listener` until `shutdown` is set
+///
+/// The WebSocket endpoint is served on a separate address, if `websocket` is given.
+pub fn run(
+    transport: TransportProtocol,
+    listener: TcpListener,
+    websocket: Option<&str>,
+    shutdown: &AtomicBool,
+) -> Result<()> {
+    let listen = listener.local_addr()?;
+    let server = tiny_http::Server::from_listener(listener, None)
+        .map_err(|err| anyhow::anyhow!(err))
+        .with_context(|| format!("listen on {listen}"))?;
+    eprintln!("listening on http://{listen}");
+
+    let daemon = Arc::new(Daemon {
+        connection: Mutex::new(Connection::new(transport)?),
+        subscribers: Mutex::default(),
+        metrics: Mutex::default(),
+        live: Mutex::default(),
+        cache: Mutex::default(),
+    });
+    if let Some(websocket) = websocket {
+        websocket::listen(daemon.clone(), websocket, listen)?;
+    }
+    let refresh = daemon.clone();
+    thread::spawn(move || loop {
+        thread::sleep(REFRESH_POLL_INTERVAL);
+        if let Err(err) = refresh.refresh() {
+            eprintln!("refresh: {err:#}");
+        }
+    });
+
+    while !shutdown.load(Ordering::Relaxed) {
+        let Some(mut request) = server.recv_timeout(SHUTDOWN_POLL_INTERVAL)? else {
+            continue;
+        };
+        let response = handle(&daemon, &mut request).unwrap_or_else(|err| {
+            eprintln!("{} {}: {err:#}", request.method(), request.url());
+            json_response(500, &json!({ "error": format!("{err:#}") }))
+        });
+        if let Err(err) = request.respond(response) {
+            eprintln!("failed to send response: {err}");
+        }
+    }
+    Ok(())
+}
+
+fn handle(daemon: &Daemon, request: &mut Request) -> Result<DataResponse> {
+    let url = request.url().to_owned();
+    let path = url.split('?').next().unwrap_or_default();
+    let config = match (request.method(), path) {
+        (Method::Get, "/") => {
+            return Ok(data_response(200, INDEX_HTML, "text/html; charset=utf-8"));
+        }
+        (Method::Get, "/healthz") => {
+            return Ok(json_response(200, &json!({ "status": "ok" })));
+        }
+        (Method::Get, "/metrics") => {
+            let metrics = daemon.metrics.lock().unwrap().to_prometheus();
+            return Ok(data_response(200, metrics, "text/plain; version=0.0.4"));
+        }
+        (Method::Get, "/devices") => {
+            let devices = daemon.list_devices()?;
+            return Ok(json_response(200, &json!({ "devices": devices })));
+        }
+        (Method::Post, "/text") => Config::default().message(Message::text(read_body(request)?)),
+        (Method::Post, "/config" | "/preview") => match read_config(request) {
+            Ok(config) => config,
+            Err(err) => return Ok(bad_request(&err)),
+        },
+        (_, "/" | "/healthz" | "/metrics" | "/devices" | "/text" | "/config" | "/preview") => {
+            return Ok(json_response(
+                405,
+                &json!({ "error": "method not allowed" }),
+            ));
+        }
+        _ => return Ok(json_response(404, &json!({ "error": "not found" }))),
+    };
+
+    let payload = match build_payload(&config) {
+        Ok(payload) => payload,
+        Err(err) => return Ok(bad_request(&err)),
+    };
+    if path == "/preview" {
+        let mut gif = Vec::new();
+        simulator::write_gif(&payload, PREVIEW_SCALE, &mut gif)?;
+        return Ok(data_response(200, gif, "image/gif"));
+    }
+    daemon.show(config, &payload)?;
+    Ok(json_response(200, &json!({ "ok": true })))
+}
+
+fn build_payload(config: &Config) -> Result<PayloadBuffer> {
+    anyhow::ensure!(
+        config.devices.is_empty(),
+        "[[device]] sections are not supported by the daemon"
+    );
+    config.build_payload_at(crate::timezone::now_local())
+}
+
+/// Parse the config in the request body
+///
+/// The body is JSON if the content type is `application/json`, TOML otherwise.
+fn read_config(request: &mut Request) -> Result<Config> {
+    let format = if header(request, "Content-Type")
+        .is_some_and(|value| value.starts_with("application/json"))
+    {
+        "json"
+    } else {
+        "toml"
+    };
+    Config::parse(&read_body(request)?, format)
+}
+
+fn header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+fn read_body(request: &mut Request) -> Result<String> {
+    let mut body = String::new();
+    request
+        .as_reader()
+        .take(MAX_BODY_SIZE)
+        .read_to_string(&mut body)
+        .context("read request body")?;
+    Ok(body)
+}
+
+fn bad_request(err: &anyhow::Error) -> DataResponse {
+    json_response(400, &json!({ "error": format!("{err:#}") }))
+}
+
+fn json_response(status: u16, body: &serde_json::Value) -> DataResponse {
+    data_response(status, body.to_string(), "application/json")
+}
+
+fn data_response(status: u16, data: impl Into<Vec<u8>>, content_type: &str) -> DataResponse {
+    Response::from_data(data)
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", content_type).unwrap())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use tiny_http::{Method, Request, TestRequest};
+
+    use super::{handle, Connection, Daemon};
+    use crate::TransportProtocol;
+
+    fn preview(daemon: &Daemon, body: &'static str) -> u16 {
+        let mut request: Request = TestRequest::new()
+            .with_method(Method::Post)
+            .with_path("/preview")
+            .with_body(body)
+            .into();
+        handle(daemon, &mut request).unwrap().status_code().0
+    }
+
+    #[test]
+    fn preview_reads_no_files() {
+        let daemon = Daemon {
+            connection: Mutex::new(Connection::new(TransportProtocol::Usb).unwrap()),
+            subscribers: Mutex::default(),
+            metrics: Mutex::default(),
+            live: Mutex::default(),
+            cache: Mutex::default(),
+        };
+        assert_eq!(preview(&daemon, "[[message]]\ntext = \"Hi\""), 200);
+        assert_eq!(
+            preview(
+                &daemon,
+                "[[message]]\nwidth = 8\nbitmap_file = \"/etc/passwd\""
+            ),
+            400
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! WebSocket endpoint of the daemon
+//!
+//! All device events are sent to the clients as JSON objects with an `event` field:
+//! `device_connected`, `upload_started`, `upload_finished` and `upload_failed`.
+//!
+//! Clients update the badge by sending a JSON object,
+//! either `{"text": "..."}` with a single text message or `{"config": {...}}` with a config.
+//! Invalid commands are answered with an `error` event.
+//!
+//! Browsers may only connect from the web UI, other clients send no `Origin` header.
+//! At most [`MAX_CLIENTS`] clients are served at the same time.
+
+use std::{
+    io::ErrorKind,
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use badgemagic::config::{Config, Message};
+use serde::Deserialize;
+use serde_json::json;
+use tungstenite::{
+    handshake::server::{ErrorResponse, Request, Response},
+    http::StatusCode,
+    Message as WsMessage,
+};
+
+use super::Daemon;
+
+/// How often events are forwarded to the clients
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Number of clients served at the same time, further connections are closed
+const MAX_CLIENTS: usize = 16;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Command {
+    Text(String),
+    Config(serde_json::Value),
+}
+
+/// Accept WebSocket clients on `listen` in the background
+///
+/// Browsers are only accepted from the web UI served on `http`.
+pub(super) fn listen(daemon: Arc<Daemon>, listen: &str, http: SocketAddr) -> Result<()> {
+    let listener = TcpListener::bind(listen).with_context(|| format!("listen on {listen}"))?;
+    eprintln!("listening on ws://{listen}");
+    let clients = Arc::new(AtomicUsize::new(0));
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    eprintln!("websocket: {err}");
+                    continue;
+                }
+            };
+            if clients.fetch_add(1, Ordering::SeqCst) >= MAX_CLIENTS {
+                clients.fetch_sub(1, Ordering::SeqCst);
+                eprintln!("websocket: too many clients, closing connection");
+                continue;
+            }
+            let daemon = daemon.clone();
+            let clients = clients.clone();
+            thread::spawn(move || {
+                if let Err(err) = handle(&daemon, stream, http) {
+                    eprintln!("websocket: {err:#}");
+                }
+                clients.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+    Ok(())
+}
+
+fn handle(daemon: &Daemon, stream: TcpStream, http: SocketAddr) -> Result<()> {
+    let events = daemon.subscribe();
+    #[allow(clippy::result_large_err)] // the error response is given by tungstenite
+    let check_origin = |request: &Request, response: Response| match request
+        .headers()
+        .get("origin")
+        .map(|origin| origin.to_str())
+    {
+        None => Ok(response),
+        Some(Ok(origin)) if allowed_origin(origin, http) => Ok(response),
+        Some(_) => Err(forbidden()),
+    };
+    let mut socket =
+        tungstenite::accept_hdr(stream, check_origin).map_err(|err| anyhow::anyhow!("{err}"))?;
+    socket.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+    loop {
+        match socket.read() {
+            Ok(WsMessage::Text(text)) => {
+                if let Err(err) = run_command(daemon, &text) {
+                    let error = json!({ "event": "error", "error": format!("{err:#}") });
+                    socket.send(WsMessage::Text(error.to_string()))?;
+                }
+            }
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+            Err(err) => return Err(err.into()),
+        }
+        while let Ok(event) = events.try_recv() {
+            socket.send(WsMessage::Text(event))?;
+        }
+    }
+}
+
+/// Return whether a browser showing `origin` may connect, i.e. it shows the web UI served on `http`
+///
+/// When the HTTP server listens on all addresses, any host with its port is accepted.
+fn allowed_origin(origin: &str, http: SocketAddr) -> bool {
+    let Some((host, port)) = origin
+        .strip_prefix("http://")
+        .and_then(|origin| origin.rsplit_once(':'))
+    else {
+        return false;
+    };
+    if port.parse::<u16>().ok() != Some(http.port()) {
+        return false;
+    }
+    let ip = host.trim_start_matches('[').trim_end_matches(']');
+    http.ip().is_unspecified()
+        || ip.parse::<IpAddr>().ok() == Some(http.ip())
+        || (host == "localhost" && http.ip().is_loopback())
+}
+
+fn forbidden() -> ErrorResponse {
+    let mut response = ErrorResponse::new(Some("origin not allowed".into()));
+    *response.status_mut() = StatusCode::FORBIDDEN;
+    response
+}
+
+fn run_command(daemon: &Daemon, command: &str) -> Result<()> {
+    let config = match serde_json::from_str(command).context("invalid command")? {
+        Command::Text(text) => Config::default().message(Message::text(text)),
+        Command::Config(config) => Config::parse(&config.to_string(), "json")?,
+    };
+    let payload = super::build_payload(&config)?;
+    // upload errors are reported to all clients as events
+    daemon.show(config, &payload).ok();
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::allowed_origin;
+
+    #[test]
+    fn origin_of_the_web_ui() {
+        let http = "127.0.0.1:8080".parse().unwrap();
+        assert!(allowed_origin("http://127.0.0.1:8080", http));
+        assert!(allowed_origin("http://localhost:8080", http));
+        assert!(!allowed_origin("http://localhost:8081", http));
+        assert!(!allowed_origin("http://example.com:8080", http));
+        assert!(!allowed_origin("https://example.com", http));
+        assert!(!allowed_origin("null", http));
+
+        let http = "0.0.0.0:8080".parse().unwrap();
+        assert!(allowed_origin("http://192.168.1.2:8080", http));
+        assert!(!allowed_origin("http://192.168.1.2", http));
+
+        let http = "[::1]:8080".parse().unwrap();
+        assert!(allowed_origin("http://[::1]:8080", http));
+    }
+}
@@ -0,0 +1,634 @@
+//! Virtual badge that emulates the animations of the firmware
+//!
+//! The simulator renders the display frames the badge shows for a payload,
+//! e.g. for previews or visual tests.
+//! The animations are an approximation, the timing of the real firmware differs slightly.
+//! ```
+//! # #[cfg(feature = "embedded-graphics")]
+//! # fn main() {
+//! use badgemagic::{
+//!     embedded_graphics::{
+//!         geometry::{Point, Size},
+//!         pixelcolor::BinaryColor,
+//!         primitives::{PrimitiveStyle, Rectangle, Styled},
+//!     },
+//!     protocol::{Mode, PayloadBuffer, Style},
+//!     simulator,
+//! };
+//!
+//! let mut payload = PayloadBuffer::new();
+//! payload.add_message_drawable(
+//!     Style::default().mode(Mode::Center),
+//!     &Styled::new(
+//!         Rectangle::new(Point::new(0, 0), Size::new(8, 11)),
+//!         PrimitiveStyle::with_fill(BinaryColor::On),
+//!     ),
+//! );
+//! for (frame, duration) in simulator::frames(&payload) {
+//!     println!("{frame}");
+//!     assert!(frame.get(20, 5));
+//! }
+//! # }
+//! # #[cfg(not(feature = "embedded-graphics"))]
+//! # fn main() {}
+//! ```
+
+use std::{fmt, time::Duration};
+
+use crate::protocol::{Mode, PayloadBuffer, Speed, Style, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Number of columns of the display, same as [`DISPLAY_WIDTH`]
+pub const WIDTH: usize = DISPLAY_WIDTH;
+
+/// Number of rows of the display, same as [`DISPLAY_HEIGHT`]
+pub const HEIGHT: usize = DISPLAY_HEIGHT;
+
+/// Number of frames a screen is shown without movement
+const HOLD_FRAMES: usize = 8;
+
+/// Number of columns between the screens of `Mode::Fast`
+const FAST_SCREEN_WIDTH: usize = 48;
+
+/// A single frame of the display
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Frame([[bool; WIDTH]; HEIGHT]);
+
+impl Default for Frame {
+    fn default() -> Self {
+        Self([[false; WIDTH]; HEIGHT])
+    }
+}
+
+impl Frame {
+    /// Return whether the pixel at (`x`, `y`) is lit
+    ///
+    /// Pixels outside of the display are dark.
+    #[must_use]
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.0
+            .get(y)
+            .and_then(|row| row.get(x))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Set the state of the pixel at (`x`, `y`)
+    ///
+    /// Pixels outside of the display are ignored.
+    pub fn set(&mut self, x: usize, y: usize, on: bool) {
+        if let Some(pixel) = self.0.get_mut(y).and_then(|row| row.get_mut(x)) {
+            *pixel = on;
+        }
+    }
+
+    /// Return the rows of the frame from top to bottom
+    #[must_use]
+    pub fn rows(&self) -> &[[bool; WIDTH]; HEIGHT] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Frame {
+    /// Format the frame as bitstring, with `X` for lit and `_` for dark pixels
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in &self.0 {
+            for &on in row {
+                f.write_str(if on { "X" } else { "_" })?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Colors of the rendered LEDs: background, dark LED, lit LED (RGB)
+const PALETTE: [u8; 9] = [0x10, 0x10, 0x10, 0x30, 0x18, 0x18, 0xff, 0x30, 0x20];
+
+/// Render the rows of pixels with `scale` image pixels per LED
+///
+/// Returns the palette index of every image pixel.
+/// LEDs are drawn as round dots if `scale` is at least 3.
+fn render_leds<'a>(rows: impl ExactSizeIterator<Item = &'a [bool]>, scale: usize) -> Vec<u8> {
+    let dot = |dx: usize, dy: usize| {
+        if scale < 3 {
+            return true;
+        }
+        // distance from the center of the LED, in half pixels
+        let (dx, dy) = (2 * dx + 1, 2 * dy + 1);
+        let (dx, dy) = (dx.abs_diff(scale), dy.abs_diff(scale));
+        dx * dx + dy * dy <= (scale - 1) * (scale - 1)
+    };
+    let mut pixels = Vec::new();
+    for row in rows {
+        for dy in 0..scale {
+            for &on in row {
+                pixels.extend((0..scale).map(|dx| match (dot(dx, dy), on) {
+                    (false, _) => 0,
+                    (true, false) => 1,
+                    (true, true) => 2,
+                }));
+            }
+        }
+    }
+    pixels
+}
+
+/// Write one iteration through all messages of `payload` as animated GIF
+///
+/// Every LED is drawn with `scale` by `scale` pixels.
+/// ```
+/// # use badgemagic::{protocol::{PayloadBuffer, Style}, simulator};
+/// let mut payload = PayloadBuffer::new();
+/// payload.add_message(Style::default(), 2);
+/// let mut gif = Vec::new();
+/// simulator::write_gif(&payload, 4, &mut gif).unwrap();
+/// ```
+#[cfg(feature = "gif")]
+pub fn write_gif(
+    payload: &PayloadBuffer,
+    scale: u16,
+    writer: impl std::io::Write,
+) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    #[allow(clippy::cast_possible_truncation)] // the display dimensions fit into u16
+    let (width, height) = (WIDTH as u16, HEIGHT as u16);
+    let (Some(width), Some(height)) = (width.checked_mul(scale), height.checked_mul(scale)) else {
+        anyhow::bail!("scale is too large: {scale}");
+    };
+    anyhow::ensure!(scale > 0, "scale must not be zero");
+    let mut encoder = gif::Encoder::new(writer, width, height, &PALETTE).context("write GIF")?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+
+    // merge identical consecutive frames
+    let mut merged: Vec<(Frame, Duration)> = Vec::new();
+    for (frame, duration) in frames(payload) {
+        match merged.last_mut() {
+            Some((last, total)) if *last == frame => *total += duration,
+            _ => merged.push((frame, duration)),
+        }
+    }
+
+    for (frame, duration) in merged {
+        let pixels = render_leds(frame.rows().iter().map(|row| &row[..]), scale.into());
+        let mut image = gif::Frame::from_indexed_pixels(width, height, pixels, None);
+        image.delay = u16::try_from(duration.as_millis().div_ceil(10)).unwrap_or(u16::MAX);
+        encoder.write_frame(&image).context("write GIF")?;
+    }
+    Ok(())
+}
+
+/// Write the full width bitmaps of all messages of `payload` as PNG
+///
+/// The messages are placed below each other, separated by a blank line.
+/// Every LED is drawn with `scale` by `scale` pixels.
+/// ```
+/// # use badgemagic::{protocol::{PayloadBuffer, Style}, simulator};
+/// let mut payload = PayloadBuffer::new();
+/// payload.add_message(Style::default(), 2);
+/// payload.add_message(Style::default(), 8);
+/// let mut png = Vec::new();
+/// simulator::write_png(&payload, 4, &mut png).unwrap();
+/// ```
+#[cfg(feature = "png")]
+pub fn write_png(
+    payload: &PayloadBuffer,
+    scale: u16,
+    writer: impl std::io::Write,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(scale > 0, "scale must not be zero");
+    let scale = usize::from(scale);
+    let messages: Vec<_> = (0..)
+        .map_while(|index| payload.message(index))
+        .map(|(_, content)| content)
+        .collect();
+    let width = messages.iter().map(|content| content.len() * 8).max();
+    let width = width.unwrap_or_default().max(WIDTH);
+
+    let mut pixels = Vec::new();
+    for (index, content) in messages.iter().enumerate() {
+        if index > 0 {
+            pixels.resize(pixels.len() + width * scale * scale, 0);
+        }
+        let content = Content(content);
+        let rows: Vec<Vec<bool>> = (0..HEIGHT)
+            .map(|y| {
+                (0..width)
+                    .map(|x| content.get(to_isize(x), to_isize(y)))
+                    .collect()
+            })
+            .collect();
+        pixels.extend(render_leds(rows.iter().map(Vec::as_slice), scale));
+    }
+
+    encode_png(width * scale, pixels, writer)
+}
+
+/// Write `frame` as PNG
+///
+/// Every LED is drawn with `scale` by `scale` pixels.
+/// ```
+/// # use badgemagic::simulator::{self, Frame};
+/// let mut png = Vec::new();
+/// simulator::write_frame_png(&Frame::default(), 4, &mut png).unwrap();
+/// ```
+#[cfg(feature = "png")]
+pub fn write_frame_png(
+    frame: &Frame,
+    scale: u16,
+    writer: impl std::io::Write,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(scale > 0, "scale must not be zero");
+    let scale = usize::from(scale);
+    let pixels = render_leds(frame.rows().iter().map(|row| &row[..]), scale);
+    encode_png(WIDTH * scale, pixels, writer)
+}
+
+/// Encode the palette indices `pixels` as PNG, `width` pixels per row
+#[cfg(feature = "png")]
+fn encode_png(
+    width: usize,
+    mut pixels: Vec<u8>,
+    writer: impl std::io::Write,
+) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    let height = pixels.len() / width;
+    let (Ok(width), Ok(height)) = (u32::try_from(width), u32::try_from(height)) else {
+        anyhow::bail!("image is too large");
+    };
+    let mut encoder = png::Encoder::new(writer, width, height.max(1));
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(&PALETTE[..]);
+    if pixels.is_empty() {
+        pixels.resize(width as usize, 0);
+    }
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels))
+        .context("write PNG")
+}
+
+/// Write `frame` as Sixel image, the inline graphics of e.g. xterm, foot and mlterm
+///
+/// Every LED is drawn with `scale` by `scale` pixels.
+/// ```
+/// # use badgemagic::simulator::{self, Frame};
+/// let mut sixel = Vec::new();
+/// simulator::write_sixel(&Frame::default(), 4, &mut sixel).unwrap();
+/// assert!(sixel.starts_with(b"\x1bPq"));
+/// ```
+pub fn write_sixel(
+    frame: &Frame,
+    scale: u16,
+    mut writer: impl std::io::Write,
+) -> std::io::Result<()> {
+    use std::{fmt::Write as _, io};
+
+    if scale == 0 {
+        let message = "scale must not be zero";
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+    }
+    let scale = usize::from(scale);
+    let width = WIDTH * scale;
+    let pixels = render_leds(frame.rows().iter().map(|row| &row[..]), scale);
+
+    let mut out = format!("\x1bPq\"1;1;{width};{}", HEIGHT * scale);
+    for (index, color) in PALETTE.chunks(3).enumerate() {
+        let [r, g, b] = [0, 1, 2].map(|i| u32::from(color[i]) * 100 / 255);
+        let _ = write!(out, "#{index};2;{r};{g};{b}");
+    }
+    // every sixel character is a column of 6 pixels
+    for band in pixels.chunks(width * 6) {
+        for color in 0..3 {
+            let sixels: Vec<u8> = (0..width)
+                .map(|x| {
+                    let rows = band.chunks(width).enumerate();
+                    rows.filter(|(_, row)| row[x] == color)
+                        .fold(0x3f, |sixel, (dy, _)| sixel + (1 << dy))
+                })
+                .collect();
+            let _ = write!(out, "#{color}");
+            for run in sixels.chunk_by(|a, b| a == b) {
+                let sixel = char::from(run[0]);
+                if run.len() > 3 {
+                    let _ = write!(out, "!{}{sixel}", run.len());
+                } else {
+                    out.extend(run.iter().map(|&sixel| char::from(sixel)));
+                }
+            }
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    writer.write_all(out.as_bytes())
+}
+
+/// A desktop window showing the frames of the virtual badge
+#[cfg(feature = "window")]
+pub struct Window {
+    window: minifb::Window,
+    scale: usize,
+}
+
+#[cfg(feature = "window")]
+impl Window {
+    /// Open a window with `scale` by `scale` pixels per LED
+    pub fn new(title: &str, scale: u16) -> anyhow::Result<Self> {
+        use anyhow::Context;
+
+        anyhow::ensure!(scale > 0, "scale must not be zero");
+        let scale = usize::from(scale);
+        let window = minifb::Window::new(
+            title,
+            WIDTH * scale,
+            HEIGHT * scale,
+            minifb::WindowOptions::default(),
+        )
+        .context("open preview window")?;
+        Ok(Self { window, scale })
+    }
+
+    /// Return whether the window is still open
+    #[must_use]
+    pub fn is_open(&self) -> bool {
+        self.window.is_open()
+    }
+
+    /// Show `frame` in the window
+    pub fn show(&mut self, frame: &Frame) -> anyhow::Result<()> {
+        let pixels: Vec<u32> = render_leds(frame.rows().iter().map(|row| &row[..]), self.scale)
+            .into_iter()
+            .map(|index| {
+                let color = &PALETTE[usize::from(index) * 3..][..3];
+                color.iter().fold(0, |rgb, &c| (rgb << 8) | u32::from(c))
+            })
+            .collect();
+        self.window
+            .update_with_buffer(&pixels, WIDTH * self.scale, HEIGHT * self.scale)?;
+        Ok(())
+    }
+}
+
+/// Return how long a frame is shown at `speed`
+#[must_use]
+pub fn frame_duration(speed: Speed) -> Duration {
+    speed.frame_duration()
+}
+
+/// Return how long one iteration of a message takes, e.g. a full scroll cycle
+///
+/// The content is passed as returned by [`PayloadBuffer::message`].
+/// ```
+/// use badgemagic::{
+///     protocol::{Mode, Speed, Style},
+///     simulator,
+/// };
+///
+/// let style = Style::default().mode(Mode::Left).speed(Speed::Fps15);
+/// let short = simulator::message_duration(style, &[[0; 11]; 2]);
+/// let long = simulator::message_duration(style, &[[0; 11]; 20]);
+/// assert!(long > short);
+/// ```
+#[must_use]
+pub fn message_duration(style: Style, content: &[[u8; 11]]) -> Duration {
+    let frames = message_frames(style, content).len();
+    frame_duration(style.get_speed()) * u32::try_from(frames).unwrap_or(u32::MAX)
+}
+
+/// Render one iteration through all messages of `payload`
+///
+/// Every frame is returned together with the time it is shown.
+/// The badge repeats the animation after the last frame.
+pub fn frames(payload: &PayloadBuffer) -> impl Iterator<Item = (Frame, Duration)> + '_ {
+    (0..)
+        .map_while(|index| payload.message(index))
+        .flat_map(|(style, content)| {
+            let duration = frame_duration(style.get_speed());
+            message_frames(style, content)
+                .into_iter()
+                .map(move |frame| (frame, duration))
+        })
+}
+
+/// Render the frames of a single message
+///
+/// The content consists of 11 bytes (one per row) for every 8 columns,
+/// as returned by [`PayloadBuffer::message`].
+#[must_use]
+pub fn message_frames(style: Style, content: &[[u8; 11]]) -> Vec<Frame> {
+    let content = Content(content);
+    let mut frames = match style.get_mode() {
+        Mode::Left => scroll(&content, true),
+        Mode::Right => scroll(&content, false),
+        Mode::Up => screens(&content, WIDTH)
+            .flat_map(|screen| vertical(&screen, true))
+            .collect(),
+        Mode::Down => screens(&content, WIDTH)
+            .flat_map(|screen| vertical(&screen, false))
+            .collect(),
+        Mode::Center => center(&content),
+        Mode::Fast => screens(&content, FAST_SCREEN_WIDTH).collect(),
+        Mode::Drop => screens(&content, WIDTH)
+            .flat_map(|s| drop_rows(&s))
+            .collect(),
+        Mode::Curtain => screens(&content, WIDTH).flat_map(|s| curtain(&s)).collect(),
+        Mode::Laser => screens(&content, WIDTH).flat_map(|s| laser(&s)).collect(),
+    };
+    if frames.is_empty() {
+        frames.push(Frame::default());
+    }
+
+    for (index, frame) in frames.iter_mut().enumerate() {
+        if style.is_blink() && index % 4 >= 2 {
+            *frame = Frame::default();
+        }
+        if style.is_border() {
+            draw_border(frame, index);
+        }
+    }
+    frames
+}
+
+/// Message content with one bit per pixel
+struct Content<'a>(&'a [[u8; 11]]);
+
+impl Content<'_> {
+    fn width(&self) -> usize {
+        self.0.len() * 8
+    }
+
+    fn get(&self, x: isize, y: isize) -> bool {
+        let (Ok(x), Ok(y)) = (usize::try_from(x), usize::try_from(y)) else {
+            return false;
+        };
+        self.0
+            .get(x / 8)
+            .and_then(|block| block.get(y))
+            .is_some_and(|byte| byte & (0x80 >> (x % 8)) != 0)
+    }
+
+    /// Copy the content into a frame, with the content column `x` at the left edge
+    fn frame(&self, x: isize) -> Frame {
+        let mut frame = Frame::default();
+        for (row, y) in frame.0.iter_mut().zip(0..) {
+            for (pixel, column) in row.iter_mut().zip(x..) {
+                *pixel = self.get(column, y);
+            }
+        }
+        frame
+    }
+}
+
+fn to_isize(value: usize) -> isize {
+    isize::try_from(value).unwrap_or(isize::MAX)
+}
+
+/// Move the content through the display, until it left the display on the other side
+fn scroll(content: &Content, to_left: bool) -> Vec<Frame> {
+    let width = to_isize(content.width());
+    let display_width = to_isize(WIDTH);
+    (0..=width + display_width)
+        .map(|step| {
+            if to_left {
+                content.frame(step - display_width)
+            } else {
+                content.frame(width - step)
+            }
+        })
+        .collect()
+}
+
+/// Split the content into screens placed every `screen_width` columns
+fn screens<'a>(content: &'a Content, screen_width: usize) -> impl Iterator<Item = Frame> + 'a {
+    (0..content.width().div_ceil(screen_width))
+        .map(move |index| content.frame(to_isize(index * screen_width)))
+}
+
+fn hold(frame: &Frame) -> impl Iterator<Item = Frame> + '_ {
+    std::iter::repeat_n(frame, HOLD_FRAMES).cloned()
+}
+
+fn shifted(screen: &Frame, dy: isize) -> Frame {
+    let mut frame = Frame::default();
+    for (y, row) in frame.0.iter_mut().enumerate() {
+        let Ok(source) = usize::try_from(to_isize(y) - dy) else {
+            continue;
+        };
+        if let Some(source) = screen.0.get(source) {
+            *row = *source;
+        }
+    }
+    frame
+}
+
+/// Move the screen in from the bottom and out to the top (or the other way around)
+fn vertical(screen: &Frame, up: bool) -> Vec<Frame> {
+    let height = to_isize(HEIGHT);
+    let direction = if up { 1 } else { -1 };
+    let enter = (0..height).map(|step| shifted(screen, (height - step) * direction));
+    let leave = (1..=height).map(|step| shifted(screen, -step * direction));
+    enter.chain(hold(screen)).chain(leave).collect()
+}
+
+/// Show the content centered if it fits, otherwise one screen after another
+fn center(content: &Content) -> Vec<Frame> {
+    if content.width() <= WIDTH {
+        let x = (to_isize(content.width()) - to_isize(WIDTH)) / 2;
+        hold(&content.frame(x)).collect()
+    } else {
+        screens(content, WIDTH)
+            .flat_map(|screen| hold(&screen).collect::<Vec<_>>())
+            .collect()
+    }
+}
+
+/// Drop the rows of the screen from the top, starting with the bottom row
+fn drop_rows(screen: &Frame) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    let mut settled = Frame::default();
+    for row in (0..HEIGHT).rev() {
+        for y in 0..=row {
+            let mut frame = settled.clone();
+            frame.0[y] = screen.0[row];
+            frames.push(frame);
+        }
+        settled.0[row] = screen.0[row];
+    }
+    frames.extend(hold(screen));
+    frames
+}
+
+/// Reveal the screen from the center to both sides
+fn curtain(screen: &Frame) -> Vec<Frame> {
+    let center = WIDTH / 2;
+    let mut frames: Vec<_> = (0..=center)
+        .map(|step| {
+            let mut frame = Frame::default();
+            for (row, source) in frame.0.iter_mut().zip(&screen.0) {
+                row[center - step..center + step]
+                    .copy_from_slice(&source[center - step..center + step]);
+            }
+            frame
+        })
+        .collect();
+    frames.extend(hold(screen));
+    frames
+}
+
+/// Reveal the screen from left to right behind a vertical laser line
+fn laser(screen: &Frame) -> Vec<Frame> {
+    let mut frames: Vec<_> = (0..WIDTH)
+        .map(|step| {
+            let mut frame = Frame::default();
+            for (row, source) in frame.0.iter_mut().zip(&screen.0) {
+                row[..step].copy_from_slice(&source[..step]);
+                row[step] = true;
+            }
+            frame
+        })
+        .collect();
+    frames.extend(hold(screen));
+    frames
+}
+
+/// Draw a dotted border moving around the display
+fn draw_border(frame: &mut Frame, index: usize) {
+    let top = (0..WIDTH).map(|x| (x, 0));
+    let right = (1..HEIGHT).map(|y| (WIDTH - 1, y));
+    let bottom = (0..WIDTH - 1).rev().map(|x| (x, HEIGHT - 1));
+    let left = (1..HEIGHT - 1).rev().map(|y| (0, y));
+    for (position, (x, y)) in top.chain(right).chain(bottom).chain(left).enumerate() {
+        frame.set(x, y, (position + index) % 4 < 2);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{message_frames, HEIGHT, HOLD_FRAMES, WIDTH};
+    use crate::protocol::{Mode, Style};
+
+    #[test]
+    fn center_short_message() {
+        let mut block = [0; 11];
+        block[5] = 0xff;
+        let frames = message_frames(Style::default().mode(Mode::Center), &[block]);
+        assert_eq!(frames.len(), HOLD_FRAMES);
+        let row: Vec<_> = (0..WIDTH).map(|x| frames[0].get(x, 5)).collect();
+        assert_eq!(row.iter().position(|&on| on), Some(18));
+        assert_eq!(row.iter().filter(|&&on| on).count(), 8);
+        assert!(!frames[0].get(18, 4));
+    }
+
+    #[test]
+    fn scroll_left() {
+        let block = [0x80; 11];
+        let frames = message_frames(Style::default(), &[block, [0; 11]]);
+        assert_eq!(frames.len(), 16 + WIDTH + 1);
+        assert!(frames[0].rows().iter().flatten().all(|&on| !on));
+        assert!((0..HEIGHT).all(|y| frames[1].get(WIDTH - 1, y)));
+        assert!((0..HEIGHT).all(|y| frames[WIDTH].get(0, y)));
+    }
+}
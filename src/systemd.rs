@@ -0,0 +1,58 @@
+//! Integration into systemd services
+//!
+//! - socket activation: the first socket passed by systemd is used instead of the listen address
+//! - readiness and shutdown notifications for `Type=notify` services
+//! - graceful shutdown on `SIGTERM` and `SIGINT`
+
+use std::{
+    env,
+    net::TcpListener,
+    os::{fd::FromRawFd, unix::net::UnixDatagram},
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use anyhow::{Context, Result};
+use signal_hook::consts::{SIGINT, SIGTERM};
+
+/// First file descriptor passed by systemd
+const LISTEN_FDS_START: i32 = 3;
+
+/// Return a flag that is set when the service should shut down
+pub fn shutdown_flag() -> Result<Arc<AtomicBool>> {
+    let shutdown = Arc::new(AtomicBool::new(false));
+    for signal in [SIGTERM, SIGINT] {
+        signal_hook::flag::register(signal, shutdown.clone()).context("register signal handler")?;
+    }
+    Ok(shutdown)
+}
+
+/// Return the socket passed by systemd, or bind to `listen` if there is none
+pub fn listener(listen: &str) -> Result<TcpListener> {
+    let activated = env::var("LISTEN_PID").is_ok_and(|pid| pid == std::process::id().to_string())
+        && env::var("LISTEN_FDS").is_ok_and(|fds| fds.parse().is_ok_and(|n: u32| n >= 1));
+    if activated {
+        // SAFETY: systemd passes the listening sockets starting at file descriptor 3,
+        // the descriptor is not used anywhere else in this process
+        return Ok(unsafe { TcpListener::from_raw_fd(LISTEN_FDS_START) });
+    }
+    TcpListener::bind(listen).with_context(|| format!("listen on {listen}"))
+}
+
+/// Send a state (e.g. `READY=1`) to the notification socket of systemd, if there is one
+pub fn notify(state: &str) {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let result = UnixDatagram::unbound().and_then(|socket| {
+        #[cfg(target_os = "linux")]
+        if let Some(name) = path.as_encoded_bytes().strip_prefix(b"@") {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            return socket.send_to_addr(state.as_bytes(), &addr);
+        }
+        socket.send_to(state.as_bytes(), &path)
+    });
+    if let Err(err) = result {
+        eprintln!("failed to notify systemd: {err}");
+    }
+}
@@ -0,0 +1,183 @@
+//! Play the animation of a payload in the terminal
+//!
+//! Terminals with inline graphics show the frames as images with round LEDs,
+//! other terminals get a text rendering with half block characters.
+
+use std::{
+    env,
+    fmt::Write as _,
+    io::{self, IsTerminal, Write},
+    thread,
+};
+
+use anyhow::Result;
+use badgemagic::{
+    protocol::PayloadBuffer,
+    simulator::{self, Frame, HEIGHT, WIDTH},
+};
+use base64::Engine;
+use clap::ValueEnum;
+
+/// Height of a terminal cell in pixels, if the terminal does not report it
+const DEFAULT_CELL_HEIGHT: usize = 20;
+
+/// Inline graphics protocol of the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Graphics {
+    /// Detect the protocol from the environment
+    Auto,
+    /// Kitty graphics protocol (kitty, Ghostty)
+    Kitty,
+    /// Inline images of iTerm2 (also `WezTerm`)
+    Iterm2,
+    /// Sixel images (e.g. foot, mlterm, xterm with sixel support)
+    Sixel,
+    /// Half block characters, supported by every terminal
+    Text,
+}
+
+impl Graphics {
+    /// Guess the protocol from the environment variables set by the terminal
+    ///
+    /// Images are not passed through terminal multiplexers, inside of them text is used.
+    fn detect() -> Self {
+        let var = |name| env::var(name).unwrap_or_default();
+        let (term, program) = (var("TERM"), var("TERM_PROGRAM"));
+        if !io::stdout().is_terminal()
+            || env::var_os("TMUX").is_some()
+            || term.starts_with("screen")
+        {
+            Self::Text
+        } else if env::var_os("KITTY_WINDOW_ID").is_some()
+            || term == "xterm-kitty"
+            || term == "xterm-ghostty"
+        {
+            Self::Kitty
+        } else if program == "iTerm.app" || program == "WezTerm" || var("LC_TERMINAL") == "iTerm2" {
+            Self::Iterm2
+        } else if term.contains("sixel") || term.starts_with("foot") || term == "mlterm" {
+            Self::Sixel
+        } else {
+            Self::Text
+        }
+    }
+}
+
+/// Play one iteration through all messages of `payload`
+///
+/// Every LED is drawn with `scale` by `scale` pixels, if the terminal shows images.
+pub fn play(payload: &PayloadBuffer, scale: u16, graphics: Graphics) -> Result<()> {
+    anyhow::ensure!(scale > 0, "scale must not be zero");
+    let graphics = match graphics {
+        Graphics::Auto => Graphics::detect(),
+        graphics => graphics,
+    };
+    let rows = match graphics {
+        Graphics::Text => HEIGHT.div_ceil(2),
+        _ => (HEIGHT * usize::from(scale)).div_ceil(cell_height().unwrap_or(DEFAULT_CELL_HEIGHT)),
+    };
+
+    let mut stdout = io::stdout().lock();
+    // scroll before saving the cursor position, so every frame is drawn at the same place
+    write!(stdout, "{}\x1b[{rows}A\x1b7", "\n".repeat(rows))?;
+    let mut last = None;
+    for (frame, duration) in simulator::frames(payload) {
+        if last.as_ref() != Some(&frame) {
+            let mut image = Vec::from(*b"\x1b8");
+            match graphics {
+                Graphics::Kitty => image.extend(kitty(&frame, scale)?.bytes()),
+                Graphics::Iterm2 => image.extend(iterm2(&frame, scale)?.bytes()),
+                Graphics::Sixel => simulator::write_sixel(&frame, scale, &mut image)?,
+                Graphics::Auto | Graphics::Text => image.extend(text(&frame).bytes()),
+            }
+            stdout.write_all(&image)?;
+            stdout.flush()?;
+            last = Some(frame);
+        }
+        thread::sleep(duration);
+    }
+    write!(stdout, "\x1b8\x1b[{rows}B")?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Encode the frame as PNG in base64
+fn png_base64(frame: &Frame, scale: u16) -> Result<String> {
+    let mut png = Vec::new();
+    simulator::write_frame_png(frame, scale, &mut png)?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(png))
+}
+
+/// Return the escape sequences of the Kitty graphics protocol showing `frame`
+///
+/// Every frame replaces the image with the same id, so the frames do not pile up.
+fn kitty(frame: &Frame, scale: u16) -> Result<String> {
+    let data = png_base64(frame, scale)?;
+    let chunks: Vec<_> = data.as_bytes().chunks(4096).collect();
+    let mut out = String::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(index + 1 < chunks.len());
+        let chunk = std::str::from_utf8(chunk)?;
+        if index == 0 {
+            let _ = write!(
+                out,
+                "\x1b_Ga=T,f=100,i=1,p=1,q=2,C=1,m={more};{chunk}\x1b\\"
+            );
+        } else {
+            let _ = write!(out, "\x1b_Gm={more};{chunk}\x1b\\");
+        }
+    }
+    Ok(out)
+}
+
+/// Return the escape sequence of an iTerm2 inline image showing `frame`
+fn iterm2(frame: &Frame, scale: u16) -> Result<String> {
+    let data = png_base64(frame, scale)?;
+    Ok(format!(
+        "\x1b]1337;File=inline=1;preserveAspectRatio=1:{data}\x07"
+    ))
+}
+
+/// Render `frame` with half block characters, two rows of LEDs per line
+fn text(frame: &Frame) -> String {
+    // 256 color palette: lit and dark LED
+    let color = |on: bool| if on { 196 } else { 52 };
+    let mut out = String::new();
+    for (line, rows) in frame.rows().chunks(2).enumerate() {
+        if line > 0 {
+            out += "\n";
+        }
+        for x in 0..WIDTH {
+            let _ = write!(out, "\x1b[38;5;{}m", color(rows[0][x]));
+            match rows.get(1) {
+                Some(lower) => {
+                    let _ = write!(out, "\x1b[48;5;{}m▀", color(lower[x]));
+                }
+                None => out += "\x1b[49m▀",
+            }
+        }
+        out += "\x1b[0m";
+    }
+    out
+}
+
+/// Return the height of a terminal cell in pixels, if the terminal reports its size
+#[cfg(unix)]
+fn cell_height() -> Option<usize> {
+    let mut size = std::mem::MaybeUninit::<libc::winsize>::uninit();
+    // SAFETY: TIOCGWINSZ initializes the winsize struct on success
+    let size = unsafe {
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, size.as_mut_ptr()) != 0 {
+            return None;
+        }
+        size.assume_init()
+    };
+    (size.ws_row > 0 && size.ws_ypixel > 0)
+        .then(|| usize::from(size.ws_ypixel) / usize::from(size.ws_row))
+        .filter(|&height| height > 0)
+}
+
+#[cfg(not(unix))]
+fn cell_height() -> Option<usize> {
+    None
+}
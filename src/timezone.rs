@@ -0,0 +1,253 @@
+//! Time zone of the timestamp in the payload header
+//!
+//! Badges that show a clock display the timestamp as is, so it is written in local time.
+
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use badgemagic::protocol::Timestamp;
+use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
+
+#[derive(Clone, Copy)]
+enum Zone {
+    Fixed(UtcOffset),
+    /// The time zone of the system, looked up for every timestamp to follow daylight saving time
+    System,
+}
+
+static ZONE: OnceLock<Zone> = OnceLock::new();
+
+static PINNED: OnceLock<Timestamp> = OnceLock::new();
+
+/// Select the time zone of the timestamps returned by [`now`]
+///
+/// With `utc` the timestamps are in UTC. A `timezone` is either a fixed offset
+/// (`+02:00`, `-05`, `UTC`) or, on Unix, a name of the time zone database (`Europe/Berlin`).
+/// Otherwise the local time zone of the system is used.
+///
+/// A `timestamp` replaces the current time in all timestamps, see [`parse_timestamp`].
+pub fn init(utc: bool, timezone: Option<&str>, timestamp: Option<&str>) -> Result<()> {
+    let zone = match timezone {
+        _ if utc => Zone::Fixed(UtcOffset::UTC),
+        None => Zone::System,
+        Some(timezone) => {
+            if let Some(offset) = parse_offset(timezone) {
+                Zone::Fixed(offset)
+            } else {
+                select_system_zone(timezone)?;
+                Zone::System
+            }
+        }
+    };
+    ZONE.set(zone).ok().context("time zone selected twice")?;
+    if let Some(timestamp) = timestamp {
+        let timestamp = parse_timestamp(timestamp)
+            .with_context(|| format!("invalid timestamp: {timestamp:?}"))?;
+        PINNED
+            .set(timestamp)
+            .ok()
+            .context("timestamp selected twice")?;
+    }
+    Ok(())
+}
+
+/// Return the current time in the selected time zone, or the timestamp passed to [`init`]
+pub fn now() -> Timestamp {
+    PINNED.get().copied().unwrap_or_else(|| now_local().into())
+}
+
+/// Return the current date and time in the selected time zone
+pub fn now_local() -> OffsetDateTime {
+    OffsetDateTime::now_utc().to_offset(offset())
+}
+
+/// Return the current offset of the selected time zone
+fn offset() -> UtcOffset {
+    match ZONE.get().copied().unwrap_or(Zone::System) {
+        Zone::Fixed(offset) => offset,
+        Zone::System => system_offset().unwrap_or(UtcOffset::UTC),
+    }
+}
+
+/// Parse a fixed timestamp like `2025-01-01T12:00:00`, or `zero` for a zeroed timestamp
+///
+/// Without an offset the time is written as is, with an offset (`Z`, `+02:00`)
+/// it is converted to the selected time zone.
+fn parse_timestamp(value: &str) -> Result<Timestamp> {
+    if value.eq_ignore_ascii_case("zero") {
+        return Ok(Timestamp::default());
+    }
+    let (date, time) = value
+        .split_once(['T', ' '])
+        .context("expected date and time, e.g. 2025-01-01T00:00:00")?;
+    let (time, offset) = match time.find(['Z', '+', '-']) {
+        Some(index) => {
+            let offset = parse_offset(&time[index..]).context("invalid offset")?;
+            (&time[..index], Some(offset))
+        }
+        None => (time, None),
+    };
+
+    let mut date = date.splitn(3, '-').map(str::parse::<i32>);
+    let (Some(Ok(year)), Some(Ok(month)), Some(Ok(day))) = (date.next(), date.next(), date.next())
+    else {
+        anyhow::bail!("invalid date, expected YYYY-MM-DD");
+    };
+    let mut time = time.splitn(3, ':').map(str::parse::<u8>);
+    let (Some(Ok(hour)), Some(Ok(minute)), second) = (time.next(), time.next(), time.next()) else {
+        anyhow::bail!("invalid time, expected HH:MM:SS");
+    };
+    let second = second.transpose().context("invalid seconds")?.unwrap_or(0);
+
+    let month = u8::try_from(month)
+        .ok()
+        .and_then(|month| Month::try_from(month).ok())
+        .context("invalid month")?;
+    let day = u8::try_from(day).context("invalid day")?;
+    let mut datetime = PrimitiveDateTime::new(
+        Date::from_calendar_date(year, month, day)?,
+        Time::from_hms(hour, minute, second)?,
+    );
+    if let Some(offset) = offset {
+        let local = datetime.assume_offset(offset).to_offset(self::offset());
+        datetime = PrimitiveDateTime::new(local.date(), local.time());
+    }
+    Ok(datetime.assume_utc().into())
+}
+
+/// Parse a fixed offset like `+02:00`, `-05` or `UTC`
+fn parse_offset(timezone: &str) -> Option<UtcOffset> {
+    if timezone.eq_ignore_ascii_case("utc") || timezone == "Z" {
+        return Some(UtcOffset::UTC);
+    }
+    let (sign, offset) = if let Some(offset) = timezone.strip_prefix('+') {
+        (1, offset)
+    } else {
+        (-1, timezone.strip_prefix('-')?)
+    };
+    let (hours, minutes) = offset.split_once(':').unwrap_or((offset, "0"));
+    let (hours, minutes): (i8, i8) = (hours.parse().ok()?, minutes.parse().ok()?);
+    UtcOffset::from_hms(sign * hours, sign * minutes, 0).ok()
+}
+
+/// Use the time zone called `name` as system time zone
+#[cfg(unix)]
+fn select_system_zone(name: &str) -> Result<()> {
+    use std::{env, path::PathBuf};
+
+    let database =
+        env::var_os("TZDIR").map_or_else(|| PathBuf::from("/usr/share/zoneinfo"), PathBuf::from);
+    anyhow::ensure!(
+        !name.starts_with('/') && database.join(name).is_file(),
+        "unknown time zone: {name}"
+    );
+    // read by `localtime_r`, this runs before any other thread is started
+    env::set_var("TZ", name);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn select_system_zone(name: &str) -> Result<()> {
+    anyhow::bail!("unknown time zone: {name} (use an offset like +02:00)")
+}
+
+#[cfg(unix)]
+fn system_offset() -> Option<UtcOffset> {
+    let mut tm = std::mem::MaybeUninit::uninit();
+    // SAFETY: localtime_r initializes tm on success
+    let tm: libc::tm = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        if libc::localtime_r(&raw const now, tm.as_mut_ptr()).is_null() {
+            return None;
+        }
+        tm.assume_init()
+    };
+    UtcOffset::from_whole_seconds(i32::try_from(tm.tm_gmtoff).ok()?).ok()
+}
+
+/// Without a portable way to get the offset, timestamps are in UTC
+#[cfg(not(unix))]
+fn system_offset() -> Option<UtcOffset> {
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use badgemagic::protocol::Timestamp;
+    use time::{Date, Month, UtcOffset};
+
+    use super::{offset, parse_offset, parse_timestamp};
+
+    #[test]
+    fn offsets() {
+        let hm = |hours, minutes| UtcOffset::from_hms(hours, minutes, 0).ok();
+        assert_eq!(parse_offset("+02:00"), hm(2, 0));
+        assert_eq!(parse_offset("-05"), hm(-5, 0));
+        assert_eq!(parse_offset("-03:30"), hm(-3, -30));
+        assert_eq!(parse_offset("UTC"), Some(UtcOffset::UTC));
+        assert_eq!(parse_offset("utc"), Some(UtcOffset::UTC));
+        assert_eq!(parse_offset("Z"), Some(UtcOffset::UTC));
+        for invalid in [
+            "",
+            "02:00",
+            "+",
+            "-",
+            "+2x",
+            "+02:xx",
+            "+26:00",
+            "Europe/Berlin",
+        ] {
+            assert_eq!(parse_offset(invalid), None, "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn timestamps() {
+        let timestamp = |year, month, day, hour, minute, second| Timestamp {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        };
+        assert_eq!(
+            parse_timestamp("2025-01-02T03:04:05").unwrap(),
+            timestamp(25, 1, 2, 3, 4, 5)
+        );
+        assert_eq!(
+            parse_timestamp("2025-01-02 03:04").unwrap(),
+            timestamp(25, 1, 2, 3, 4, 0)
+        );
+        assert_eq!(parse_timestamp("zero").unwrap(), Timestamp::default());
+        assert_eq!(parse_timestamp("ZERO").unwrap(), Timestamp::default());
+
+        // with an offset the time is converted to the selected time zone
+        let utc = Date::from_calendar_date(2025, Month::January, 2)
+            .unwrap()
+            .with_hms(3, 4, 5)
+            .unwrap()
+            .assume_utc();
+        let expected = Timestamp::from(utc.to_offset(offset()));
+        assert_eq!(parse_timestamp("2025-01-02T03:04:05Z").unwrap(), expected);
+        assert_eq!(
+            parse_timestamp("2025-01-02T05:04:05+02:00").unwrap(),
+            expected
+        );
+        assert_eq!(parse_timestamp("2025-01-01T22:04:05-05").unwrap(), expected);
+
+        for invalid in [
+            "",
+            "yesterday",
+            "2025-01-02",
+            "2025-01-02T03",
+            "2025-13-02T03:04",
+            "2025-02-30T03:04",
+            "2025-01-02T24:00",
+            "2025-01-02T03:04:xx",
+            "2025-01-02T03:04:05+1x",
+        ] {
+            assert!(parse_timestamp(invalid).is_err(), "{invalid:?}");
+        }
+    }
+}
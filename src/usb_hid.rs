@@ -1,11 +1,13 @@
 //! Connect to an LED badge via USB HID
 
-use std::sync::Arc;
+use std::{ffi::CStr, io, sync::Arc};
 
-use anyhow::{Context, Result};
 use hidapi::{DeviceInfo, HidApi, HidDevice};
 
-use crate::protocol::PayloadBuffer;
+use crate::{
+    protocol::{PayloadBuffer, MAX_PAYLOAD_SIZE, MSG_PADDING_ALIGN},
+    record, Error, Result,
+};
 
 enum DeviceType {
     // rename if we add another device type
@@ -31,26 +33,28 @@ pub struct Device {
 impl Device {
     /// Return a list of all usb devies as a string representation
     pub fn list_all() -> Result<Vec<String>> {
-        let api = HidApi::new().context("create hid api")?;
+        let api = HidApi::new().map_err(Error::transfer("create hid api"))?;
         let devices = api.device_list();
 
         Ok(devices
             .map(|info| {
                 format!(
-                    "{:?}: vendor_id={:#06x} product_id={:#06x} manufacturer={:?} product={:?}",
+                    "{:?}: vendor_id={:#06x} product_id={:#06x} manufacturer={:?} product={:?} serial_number={:?}",
                     info.path(),
                     info.vendor_id(),
                     info.product_id(),
                     info.manufacturer_string(),
                     info.product_string(),
+                    info.serial_number(),
                 )
             })
             .collect())
     }
 
     /// Return all supported devices
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug"))]
     pub fn enumerate() -> Result<Vec<Self>> {
-        let api = HidApi::new().context("create hid api")?;
+        let api = HidApi::new().map_err(Error::transfer("create hid api"))?;
         let api = Arc::new(api);
 
         let devices = api.device_list();
@@ -62,8 +66,9 @@ impl Device {
                     type_,
                 })
             })
-            .collect();
+            .collect::<Vec<_>>();
 
+        event!(tracing::Level::DEBUG, count = devices.len(), "found badges");
         Ok(devices)
     }
 
@@ -73,38 +78,82 @@ impl Device {
     /// or if multiple devices would match.
     pub fn single() -> Result<Self> {
         let mut devices = Self::enumerate()?.into_iter();
-        let device = devices.next().context("no device found")?;
-        anyhow::ensure!(devices.next().is_none(), "multiple devices found");
+        let device = devices.next().ok_or(Error::NoDevice)?;
+        if devices.next().is_some() {
+            return Err(Error::MultipleDevices);
+        }
         Ok(device)
     }
 
+    /// Return the serial number of the device, if it has one
+    #[must_use]
+    pub fn serial_number(&self) -> Option<&str> {
+        self.info.serial_number()
+    }
+
+    /// Return the platform specific path of the device, e.g. `/dev/hidraw3` on Linux
+    #[must_use]
+    pub fn path(&self) -> &CStr {
+        self.info.path()
+    }
+
     /// Write a payload to the device
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(serial_number = self.serial_number()))
+    )]
     pub fn write(&self, payload: PayloadBuffer) -> Result<()> {
-        let device = self.info.open_device(&self.api).context("open device")?;
+        self.write_bytes(payload.into_padded_bytes().as_ref())
+    }
+
+    /// Write raw bytes to the device, e.g. to replay a recorded transfer
+    ///
+    /// The data has to be a payload padded to a multiple of 64 bytes.
+    pub fn write_bytes(&self, data: &[u8]) -> Result<()> {
+        let device = self
+            .info
+            .open_device(&self.api)
+            .map_err(Error::transfer("open device"))?;
+        record::transfer(
+            record::Transport::Usb,
+            self.serial_number().unwrap_or_default(),
+        );
         match self.type_ {
-            DeviceType::TheOnlyOneWeSupportForNow => {
-                write_raw(&device, payload.into_padded_bytes().as_ref())
-            }
+            DeviceType::TheOnlyOneWeSupportForNow => write_raw(&device, data),
         }
     }
 }
 
 fn write_raw(device: &HidDevice, data: &[u8]) -> Result<()> {
-    anyhow::ensure!(data.len() % 64 == 0, "payload not padded to 64 bytes");
+    if !data.len().is_multiple_of(MSG_PADDING_ALIGN) {
+        return Err(Error::InvalidPayload(format!(
+            "payload not padded to {MSG_PADDING_ALIGN} bytes"
+        )));
+    }
 
     // the device will brick itself if the payload is too long (more then 8192 bytes)
-    anyhow::ensure!(data.len() <= 8192, "payload too long (max 8192 bytes)");
+    if data.len() > MAX_PAYLOAD_SIZE {
+        return Err(Error::PayloadTooLarge(data.len()));
+    }
 
     // just to be sure
-    assert!(data.len() <= 8192);
-
-    let n = device.write(data).context("write payload")?;
-
-    anyhow::ensure!(
-        n == data.len(),
-        "incomplete write: {n} of {} bytes",
-        data.len()
-    );
+    assert!(data.len() <= MAX_PAYLOAD_SIZE);
+
+    event!(tracing::Level::DEBUG, bytes = data.len(), "writing payload");
+    record::chunk(record::Transport::Usb, data);
+    let n = device
+        .write(data)
+        .map_err(Error::transfer("write payload"))?;
+
+    if n != data.len() {
+        return Err(Error::Transfer {
+            context: "write payload",
+            source: io::Error::new(
+                io::ErrorKind::WriteZero,
+                format!("incomplete write: {n} of {} bytes", data.len()),
+            ),
+        });
+    }
 
     Ok(())
 }
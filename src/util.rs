@@ -1,8 +1,421 @@
 //! Graphics utilities
 
-use embedded_graphics::Drawable;
+use std::convert::Infallible;
 
-use self::layout::ZStack;
+use embedded_graphics::{
+    draw_target::{DrawTarget, DrawTargetExt},
+    geometry::{Dimensions, OriginDimensions, Point, Size},
+    image::{Image, ImageDrawableExt, ImageRawLE},
+    pixelcolor::BinaryColor,
+    primitives::{PointsIter, Rectangle},
+    Drawable, Pixel,
+};
+
+use crate::protocol::{MessageBuffer, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+use self::{
+    effect::{Embolden, Outline, Shadow},
+    layout::{HStack, Marquee, Offset, Padding, Repeat, VStack, ZStack},
+    target::{Clipped, Inverted, Mirrored, Rotated180},
+};
+
+/// Repeat `content` for a seamlessly looping scroll animation
+///
+/// The content is repeated with `gap` blank columns after each copy,
+/// until the message is wider than the display and its width is a multiple of 8.
+/// This way the padding of the message does not add an extra gap,
+/// when the device starts the next iteration of the scroll animation.
+/// ```
+/// # use badgemagic::{
+/// #     embedded_graphics::{
+/// #         geometry::Point, mono_font::{iso_8859_1::FONT_6X9, MonoTextStyle},
+/// #         pixelcolor::BinaryColor, text::Text,
+/// #     },
+/// #     protocol::{PayloadBuffer, Style},
+/// #     util::marquee,
+/// # };
+/// let text = Text::new(
+///     "Hello",
+///     Point::new(0, 7),
+///     MonoTextStyle::new(&FONT_6X9, BinaryColor::On),
+/// );
+/// let mut payload = PayloadBuffer::new();
+/// payload.add_message_drawable(Style::default(), &marquee(text, 6));
+/// ```
+pub fn marquee<T>(content: T, gap: u32) -> Marquee<T>
+where
+    T: Drawable + Dimensions,
+{
+    let period = content.bounding_box().size.width + gap;
+    let mut count = 1;
+    if period > 0 {
+        // smallest count for which the total width is a multiple of 8
+        while !(count * period).is_multiple_of(8) {
+            count += 1;
+        }
+        let step = count;
+        while (count * period) as usize <= DISPLAY_WIDTH {
+            count += step;
+        }
+    }
+    Marquee {
+        content,
+        period,
+        count,
+    }
+}
+
+/// Line style of a `Frame`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FrameStyle {
+    /// Continuous lines
+    #[default]
+    Solid,
+    /// Every other pixel is lit
+    Dotted,
+    /// Two solid lines with a 1 pixel gap in between
+    Double,
+}
+
+/// A border around a drawable
+///
+/// The frame starts at the top left corner of the content,
+/// the content is moved to the inside of the frame.
+/// ```
+/// # use badgemagic::{
+/// #     embedded_graphics::{
+/// #         geometry::Point, mono_font::{iso_8859_1::FONT_5X7, MonoTextStyle},
+/// #         pixelcolor::BinaryColor, text::{Baseline, Text},
+/// #     },
+/// #     protocol::{Mode, PayloadBuffer, Style},
+/// #     util::{Frame, FrameStyle},
+/// # };
+/// let text = Text::with_baseline(
+///     "Hi",
+///     Point::new(0, 0),
+///     MonoTextStyle::new(&FONT_5X7, BinaryColor::On),
+///     Baseline::Top,
+/// );
+/// let mut payload = PayloadBuffer::new();
+/// payload.add_message_drawable(
+///     Style::default().mode(Mode::Center),
+///     &Frame::new(text, FrameStyle::Dotted, 2),
+/// );
+/// ```
+pub struct Frame<T> {
+    content: T,
+    style: FrameStyle,
+    thickness: u32,
+}
+
+impl<T> Frame<T> {
+    /// Draw a frame with lines of `thickness` pixels around `content`
+    pub fn new(content: T, style: FrameStyle, thickness: u32) -> Self {
+        Self {
+            content,
+            style,
+            thickness,
+        }
+    }
+
+    /// Total width of the border on each side
+    fn border_width(&self) -> u32 {
+        match self.style {
+            FrameStyle::Solid | FrameStyle::Dotted => self.thickness,
+            FrameStyle::Double => 2 * self.thickness + 1,
+        }
+    }
+}
+
+impl<T> Dimensions for Frame<T>
+where
+    T: Dimensions,
+{
+    fn bounding_box(&self) -> Rectangle {
+        let bounds = self.content.bounding_box();
+        let border = self.border_width();
+        Rectangle::new(
+            bounds.top_left,
+            bounds.size + Size::new(2 * border, 2 * border),
+        )
+    }
+}
+
+impl<T> Drawable for Frame<T>
+where
+    T: Drawable<Color = BinaryColor> + Dimensions,
+{
+    type Color = BinaryColor;
+
+    type Output = T::Output;
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        let border = i32::try_from(self.border_width()).unwrap();
+        let thickness = i32::try_from(self.thickness).unwrap();
+        let bounds = self.bounding_box();
+        let Some(bottom_right) = bounds.bottom_right() else {
+            return self.content.draw(target);
+        };
+        let pixels = bounds.points().filter(|point| {
+            let distance = [
+                point.x - bounds.top_left.x,
+                point.y - bounds.top_left.y,
+                bottom_right.x - point.x,
+                bottom_right.y - point.y,
+            ]
+            .into_iter()
+            .min()
+            .unwrap();
+            distance < border
+                && match self.style {
+                    FrameStyle::Solid => true,
+                    FrameStyle::Dotted => (point.x + point.y).rem_euclid(2) == 0,
+                    FrameStyle::Double => distance != thickness,
+                }
+        });
+        target.draw_iter(pixels.map(|point| Pixel(point, BinaryColor::On)))?;
+        self.content
+            .draw(&mut target.translated(Point::new(border, border)))
+    }
+}
+
+/// A horizontal strip of equally sized animation frames
+///
+/// The strip is a raw image with 1 bit per pixel and 11 rows (the height of the display),
+/// every row starts at a new byte.
+/// ```
+/// # use badgemagic::{
+/// #     embedded_graphics::geometry::Point,
+/// #     protocol::{Mode, PayloadBuffer, Style},
+/// #     util::{DrawableLayoutExt, SpriteSheet},
+/// # };
+/// // two frames with 8 columns each
+/// let data = [0xf0, 0x0f].repeat(11);
+/// let sheet = SpriteSheet::from_raw(&data, 8);
+/// let mut payload = PayloadBuffer::new();
+/// for frame in sheet.frames() {
+///     payload.add_message_drawable(Style::default().mode(Mode::Center), &frame);
+/// }
+/// ```
+pub struct SpriteSheet<'a> {
+    image: ImageRawLE<'a, BinaryColor>,
+    frame_width: u32,
+}
+
+impl<'a> SpriteSheet<'a> {
+    /// Split the raw image `data` into frames of `frame_width` columns
+    ///
+    /// Columns after the last complete frame are ignored.
+    ///
+    /// # Panics
+    /// Panics if `frame_width` is zero.
+    #[must_use]
+    pub fn from_raw(data: &'a [u8], frame_width: u32) -> Self {
+        assert!(frame_width > 0, "frame width must not be zero");
+        let bytes_per_row = data.len() / DISPLAY_HEIGHT;
+        let width = u32::try_from(bytes_per_row * 8).unwrap();
+        Self {
+            image: ImageRawLE::new(data, width),
+            frame_width,
+        }
+    }
+
+    /// Return the number of frames
+    #[must_use]
+    pub fn len(&self) -> usize {
+        (self.image.size().width / self.frame_width) as usize
+    }
+
+    /// Return whether the sheet contains no frames
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return the frame at `index`
+    #[must_use]
+    pub fn frame(&self, index: usize) -> Option<Sprite<'_, 'a>> {
+        if index >= self.len() {
+            return None;
+        }
+        let x = i32::try_from(index).ok()? * i32::try_from(self.frame_width).ok()?;
+        Some(Sprite {
+            image: &self.image,
+            area: Rectangle::new(
+                Point::new(x, 0),
+                Size::new(self.frame_width, self.image.size().height),
+            ),
+        })
+    }
+
+    /// Iterate over all frames in order
+    pub fn frames(&self) -> impl Iterator<Item = Sprite<'_, 'a>> {
+        (0..self.len()).filter_map(|index| self.frame(index))
+    }
+}
+
+/// A single frame of a `SpriteSheet`, drawn at the top left corner
+pub struct Sprite<'s, 'a> {
+    image: &'s ImageRawLE<'a, BinaryColor>,
+    area: Rectangle,
+}
+
+impl Dimensions for Sprite<'_, '_> {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(Point::zero(), self.area.size)
+    }
+}
+
+impl Drawable for Sprite<'_, '_> {
+    type Color = BinaryColor;
+
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        Image::new(&self.image.sub_image(&self.area), Point::zero()).draw(target)
+    }
+}
+
+/// An off-screen buffer with 11 rows and arbitrary width
+///
+/// Pixels drawn outside of the buffer are dropped.
+/// Compose the content off-screen and copy it into a message with [`Framebuffer::blit_to`].
+/// ```
+/// # use badgemagic::{
+/// #     embedded_graphics::{
+/// #         geometry::{Point, Size}, pixelcolor::BinaryColor,
+/// #         primitives::{Primitive, PrimitiveStyle, Rectangle}, Drawable,
+/// #     },
+/// #     protocol::{PayloadBuffer, Style},
+/// #     util::Framebuffer,
+/// # };
+/// let mut framebuffer = Framebuffer::new(100);
+/// Rectangle::new(Point::new(0, 0), Size::new(100, 11))
+///     .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+///     .draw(&mut framebuffer)
+///     .unwrap();
+///
+/// let mut payload = PayloadBuffer::new();
+/// let mut buffer = payload.add_message(Style::default(), 6);
+/// // show a 48 column wide window of the framebuffer
+/// framebuffer.blit_to(&mut buffer, Point::new(-20, 0));
+/// ```
+#[derive(Clone)]
+pub struct Framebuffer {
+    pub(crate) width: usize,
+    /// The renderer of the config also uses buffers with other heights, e.g. for images
+    pub(crate) rows: Vec<Vec<bool>>,
+}
+
+impl Framebuffer {
+    /// Create an empty buffer with `width` columns
+    #[must_use]
+    pub fn new(width: u32) -> Self {
+        Self::blank(width as usize, DISPLAY_HEIGHT)
+    }
+
+    /// Create an empty buffer with `width` columns and `height` rows
+    pub(crate) fn blank(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            rows: vec![vec![false; width]; height],
+        }
+    }
+
+    /// Return the state of the pixel at `point`
+    ///
+    /// Returns `None` if the pixel is out of bounds.
+    #[must_use]
+    pub fn get(&self, point: Point) -> Option<BinaryColor> {
+        self.pixel(point).map(|pixel| (*pixel).into())
+    }
+
+    /// Turn off all pixels
+    pub fn clear(&mut self) {
+        for row in &mut self.rows {
+            row.fill(false);
+        }
+    }
+
+    /// Copy the buffer into `target`, with its top left corner at `offset`
+    ///
+    /// Lit and dark pixels are copied, pixels outside of `target` are dropped.
+    pub fn blit_to(&self, target: &mut MessageBuffer, offset: Point) {
+        for point in self.bounding_box().points() {
+            let (Ok(x), Ok(y)) = (
+                usize::try_from(point.x + offset.x),
+                usize::try_from(point.y + offset.y),
+            ) else {
+                continue;
+            };
+            let state = self.get(point).unwrap_or(BinaryColor::Off);
+            target.set((x, y), state.into());
+        }
+    }
+
+    fn pixel(&self, point: Point) -> Option<&bool> {
+        let x = usize::try_from(point.x).ok()?;
+        let y = usize::try_from(point.y).ok()?;
+        self.rows.get(y)?.get(x)
+    }
+
+    fn pixel_mut(&mut self, point: Point) -> Option<&mut bool> {
+        let x = usize::try_from(point.x).ok()?;
+        let y = usize::try_from(point.y).ok()?;
+        self.rows.get_mut(y)?.get_mut(x)
+    }
+}
+
+impl OriginDimensions for Framebuffer {
+    fn size(&self) -> Size {
+        Size::new(
+            self.width.try_into().unwrap(),
+            self.rows.len().try_into().unwrap(),
+        )
+    }
+}
+
+impl DrawTarget for Framebuffer {
+    type Color = BinaryColor;
+
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if let Some(pixel) = self.pixel_mut(point) {
+                *pixel = color.is_on();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drawable for Framebuffer {
+    type Color = BinaryColor;
+
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        target.draw_iter(
+            self.bounding_box()
+                .points()
+                .filter(|&point| self.get(point) == Some(BinaryColor::On))
+                .map(|point| Pixel(point, BinaryColor::On)),
+        )
+    }
+}
 
 /// Drawable layout extension
 pub trait DrawableLayoutExt: Drawable + Sized {
@@ -10,19 +423,311 @@ pub trait DrawableLayoutExt: Drawable + Sized {
     fn z_stack<T>(self, other: T) -> ZStack<Self, T> {
         ZStack(self, other)
     }
+
+    /// Place `other` to the right of this drawable, with `gap` blank columns in between
+    ///
+    /// Both drawables are positioned by their bounding boxes,
+    /// the vertical position of `other` is not changed.
+    /// ```
+    /// # use badgemagic::{
+    /// #     embedded_graphics::{
+    /// #         geometry::{Point, Size}, mono_font::{iso_8859_1::FONT_6X9, MonoTextStyle},
+    /// #         pixelcolor::BinaryColor, primitives::{PrimitiveStyle, Rectangle, Styled},
+    /// #         text::{Baseline, Text},
+    /// #     },
+    /// #     protocol::{PayloadBuffer, Style},
+    /// #     util::DrawableLayoutExt,
+    /// # };
+    /// let icon = Styled::new(
+    ///     Rectangle::new(Point::new(0, 2), Size::new(7, 7)),
+    ///     PrimitiveStyle::with_fill(BinaryColor::On),
+    /// );
+    /// let text = Text::with_baseline(
+    ///     "Hello",
+    ///     Point::zero(),
+    ///     MonoTextStyle::new(&FONT_6X9, BinaryColor::On),
+    ///     Baseline::Top,
+    /// );
+    /// let mut payload = PayloadBuffer::new();
+    /// payload.add_message_drawable(Style::default(), &icon.hstack(text, 2));
+    /// ```
+    fn hstack<T>(self, other: T, gap: u32) -> HStack<Self, T>
+    where
+        Self: Dimensions,
+        T: Dimensions,
+    {
+        let a = self.bounding_box();
+        let b = other.bounding_box();
+        let right = a.top_left.x + i32::try_from(a.size.width + gap).unwrap();
+        HStack(self, other, Point::new(right - b.top_left.x, 0))
+    }
+
+    /// Place `other` below this drawable, with `gap` blank rows in between
+    ///
+    /// Both drawables are positioned by their bounding boxes,
+    /// the horizontal position of `other` is not changed.
+    /// The display only has 11 rows, so both drawables and the gap need to fit into them.
+    /// ```
+    /// # use badgemagic::{
+    /// #     embedded_graphics::{
+    /// #         geometry::Point, mono_font::{iso_8859_1::FONT_4X6, MonoTextStyle},
+    /// #         pixelcolor::BinaryColor, text::{Baseline, Text},
+    /// #     },
+    /// #     protocol::{PayloadBuffer, Style},
+    /// #     util::DrawableLayoutExt,
+    /// # };
+    /// let style = MonoTextStyle::new(&FONT_4X6, BinaryColor::On);
+    /// let hello = Text::with_baseline("Hello", Point::zero(), style, Baseline::Top);
+    /// let world = Text::with_baseline("World", Point::zero(), style, Baseline::Top);
+    /// let mut payload = PayloadBuffer::new();
+    /// payload.add_message_drawable(Style::default(), &hello.vstack(world, 0));
+    /// ```
+    fn vstack<T>(self, other: T, gap: u32) -> VStack<Self, T>
+    where
+        Self: Dimensions,
+        T: Dimensions,
+    {
+        let a = self.bounding_box();
+        let b = other.bounding_box();
+        let bottom = a.top_left.y + i32::try_from(a.size.height + gap).unwrap();
+        VStack(self, other, Point::new(0, bottom - b.top_left.y))
+    }
+
+    /// Move this drawable by `by` pixels
+    ///
+    /// Unlike `Transform::translate` this works for every drawable,
+    /// by shifting the coordinate space of the draw target.
+    fn offset(self, by: Point) -> Offset<Self> {
+        Offset(self, by)
+    }
+
+    /// Center this drawable horizontally within the columns `0..width`
+    ///
+    /// Usually `width` is 44, the number of columns visible on the display at once.
+    /// Content wider than `width` starts at column 0.
+    /// ```
+    /// # use badgemagic::{
+    /// #     embedded_graphics::{
+    /// #         geometry::Point, mono_font::{iso_8859_1::FONT_6X9, MonoTextStyle},
+    /// #         pixelcolor::BinaryColor, text::{Baseline, Text},
+    /// #     },
+    /// #     protocol::{Mode, PayloadBuffer, Style},
+    /// #     util::DrawableLayoutExt,
+    /// # };
+    /// let text = Text::with_baseline(
+    ///     "Hi",
+    ///     Point::zero(),
+    ///     MonoTextStyle::new(&FONT_6X9, BinaryColor::On),
+    ///     Baseline::Top,
+    /// );
+    /// let mut payload = PayloadBuffer::new();
+    /// payload.add_message_drawable(Style::default().mode(Mode::Center), &text.align_center(44));
+    /// ```
+    fn align_center(self, width: u32) -> Offset<Self>
+    where
+        Self: Dimensions,
+    {
+        let bounds = self.bounding_box();
+        let free = width.saturating_sub(bounds.size.width) / 2;
+        let x = i32::try_from(free).unwrap() - bounds.top_left.x;
+        Offset(self, Point::new(x, 0))
+    }
+
+    /// Align this drawable to the right edge of the columns `0..width`
+    ///
+    /// Usually `width` is 44, the number of columns visible on the display at once.
+    /// Content wider than `width` starts at column 0.
+    fn align_right(self, width: u32) -> Offset<Self>
+    where
+        Self: Dimensions,
+    {
+        let bounds = self.bounding_box();
+        let free = width.saturating_sub(bounds.size.width);
+        let x = i32::try_from(free).unwrap() - bounds.top_left.x;
+        Offset(self, Point::new(x, 0))
+    }
+
+    /// Draw this drawable `count` times side by side, with `gap` blank columns in between
+    ///
+    /// The bounding box covers all copies,
+    /// so `add_message_drawable` allocates the full width automatically.
+    /// ```
+    /// # use badgemagic::{
+    /// #     embedded_graphics::{
+    /// #         geometry::{Point, Size}, pixelcolor::BinaryColor,
+    /// #         primitives::{PrimitiveStyle, Rectangle, Styled},
+    /// #     },
+    /// #     protocol::{PayloadBuffer, Style},
+    /// #     util::DrawableLayoutExt,
+    /// # };
+    /// let dot = Styled::new(
+    ///     Rectangle::new(Point::new(0, 4), Size::new(3, 3)),
+    ///     PrimitiveStyle::with_fill(BinaryColor::On),
+    /// );
+    /// let mut payload = PayloadBuffer::new();
+    /// payload.add_message_drawable(Style::default(), &dot.repeat_x(20, 2));
+    /// ```
+    fn repeat_x(self, count: u32, gap: u32) -> Repeat<Self>
+    where
+        Self: Dimensions,
+    {
+        Repeat {
+            period: self.bounding_box().size.width + gap,
+            content: self,
+            count,
+            gap,
+        }
+    }
+
+    /// Add blank space around this drawable
+    ///
+    /// The padded drawable starts at the top left corner of the original one,
+    /// the content is moved `left` columns to the right and `top` rows down.
+    /// ```
+    /// # use badgemagic::{
+    /// #     embedded_graphics::{
+    /// #         geometry::{Point, Size}, pixelcolor::BinaryColor,
+    /// #         primitives::{PrimitiveStyle, Rectangle, Styled},
+    /// #     },
+    /// #     util::DrawableLayoutExt,
+    /// # };
+    /// use badgemagic::embedded_graphics::geometry::Dimensions;
+    ///
+    /// let icon = Styled::new(
+    ///     Rectangle::new(Point::zero(), Size::new(7, 7)),
+    ///     PrimitiveStyle::with_fill(BinaryColor::On),
+    /// );
+    /// let padded = icon.padding(2, 1, 2, 1);
+    /// assert_eq!(padded.bounding_box().size, Size::new(9, 11));
+    /// ```
+    fn padding(self, top: u32, right: u32, bottom: u32, left: u32) -> Padding<Self> {
+        Padding {
+            content: self,
+            top,
+            right,
+            bottom,
+            left,
+        }
+    }
 }
 
 impl<T> DrawableLayoutExt for T where T: Drawable {}
 
+/// Drawable effect extension
+///
+/// Effects draw the content into an intermediate buffer
+/// and post-process the pixels before passing them on.
+pub trait DrawableEffectExt: Drawable<Color = BinaryColor> + Dimensions + Sized {
+    /// Draw a 1 pixel outline around the content, leaving the content itself dark
+    fn outline(self) -> Outline<Self> {
+        Outline(self)
+    }
+
+    /// Draw the content twice, with the second copy shifted 1 pixel to the right
+    fn embolden(self) -> Embolden<Self> {
+        Embolden(self)
+    }
+
+    /// Draw a shadow 1 pixel to the bottom right of the content
+    fn shadow(self) -> Shadow<Self> {
+        Shadow(self)
+    }
+}
+
+impl<T> DrawableEffectExt for T where T: Drawable<Color = BinaryColor> + Dimensions {}
+
+/// Draw target transform extension
+///
+/// The adapters transform every pixel before passing it on to the wrapped target,
+/// so any drawable can be flipped without modifying it.
+/// Mirroring and rotation use the bounding box of the wrapped target.
+/// To move drawables use [`DrawTargetExt::translated`].
+/// ```
+/// # use badgemagic::{
+/// #     embedded_graphics::{
+/// #         geometry::Point, mono_font::{iso_8859_1::FONT_6X9, MonoTextStyle},
+/// #         pixelcolor::BinaryColor, text::Text, Drawable,
+/// #     },
+/// #     protocol::{PayloadBuffer, Style},
+/// #     util::DrawTargetTransformExt,
+/// # };
+/// let text = Text::new(
+///     "Hello",
+///     Point::new(0, 7),
+///     MonoTextStyle::new(&FONT_6X9, BinaryColor::On),
+/// );
+/// let mut payload = PayloadBuffer::new();
+/// let mut buffer = payload.add_message(Style::default(), 4);
+/// text.draw(&mut buffer.rotated_180()).unwrap();
+/// ```
+pub trait DrawTargetTransformExt: DrawTarget<Color = BinaryColor> + Sized {
+    /// Draw on pixels as off and off pixels as on
+    fn inverted(&mut self) -> Inverted<'_, Self> {
+        Inverted(self)
+    }
+
+    /// Mirror horizontally
+    fn mirrored(&mut self) -> Mirrored<'_, Self> {
+        Mirrored(self)
+    }
+
+    /// Rotate by 180 degrees
+    fn rotated_180(&mut self) -> Rotated180<'_, Self> {
+        Rotated180(self)
+    }
+
+    /// Silently drop pixels outside the bounding box
+    ///
+    /// Drawing outside of a `MessageBuffer` panics by default,
+    /// while most graphics code expects out of bounds pixels to be clipped.
+    /// ```
+    /// # use badgemagic::{
+    /// #     embedded_graphics::{
+    /// #         geometry::{Point, Size}, pixelcolor::BinaryColor,
+    /// #         primitives::{Primitive, PrimitiveStyle, Rectangle}, Drawable,
+    /// #     },
+    /// #     protocol::{PayloadBuffer, Style},
+    /// #     util::DrawTargetTransformExt,
+    /// # };
+    /// let mut payload = PayloadBuffer::new();
+    /// let mut buffer = payload.add_message(Style::default(), 1);
+    /// Rectangle::new(Point::new(-4, 4), Size::new(20, 20))
+    ///     .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+    ///     .draw(&mut buffer.clip())
+    ///     .unwrap();
+    /// ```
+    fn clip(&mut self) -> Clipped<'_, Self> {
+        Clipped(self)
+    }
+}
+
+impl<T> DrawTargetTransformExt for T where T: DrawTarget<Color = BinaryColor> {}
+
 pub mod layout {
     //! Types used by `DrawableLayoutExt `
 
     use embedded_graphics::{
-        geometry::{Dimensions, Point},
+        draw_target::{DrawTarget, DrawTargetExt},
+        geometry::{Dimensions, Point, Size},
         primitives::Rectangle,
         Drawable,
     };
 
+    /// Smallest rectangle containing both `a` and `b` (empty rectangles are ignored)
+    fn union(a: Rectangle, b: Rectangle) -> Rectangle {
+        if b.is_zero_sized() {
+            return a;
+        }
+        if a.is_zero_sized() {
+            return b;
+        }
+        let left = i32::min(a.top_left.x, b.top_left.x);
+        let top = i32::min(a.top_left.y, b.top_left.y);
+        let right = i32::max(a.bottom_right().unwrap().x, b.bottom_right().unwrap().x);
+        let bottom = i32::max(a.bottom_right().unwrap().y, b.bottom_right().unwrap().y);
+        Rectangle::with_corners(Point::new(left, top), Point::new(right, bottom))
+    }
+
     pub struct ZStack<A, B>(pub(super) A, pub(super) B);
 
     impl<A, B> Dimensions for ZStack<A, B>
@@ -31,13 +736,7 @@ pub mod layout {
         B: Dimensions,
     {
         fn bounding_box(&self) -> Rectangle {
-            let a = self.0.bounding_box();
-            let b = self.1.bounding_box();
-            let left = i32::min(a.top_left.x, b.top_left.x);
-            let top = i32::min(a.top_left.y, b.top_left.y);
-            let right = i32::max(a.bottom_right().unwrap().x, b.bottom_right().unwrap().x);
-            let bottom = i32::max(a.bottom_right().unwrap().y, b.bottom_right().unwrap().y);
-            Rectangle::with_corners(Point::new(left, top), Point::new(right, bottom))
+            union(self.0.bounding_box(), self.1.bounding_box())
         }
     }
 
@@ -59,4 +758,929 @@ pub mod layout {
             Ok((a, b))
         }
     }
+
+    macro_rules! impl_stack {
+        ($($(#[$attr:meta])* $name:ident),*) => {$(
+            $(#[$attr])*
+            pub struct $name<A, B>(pub(super) A, pub(super) B, pub(super) Point);
+
+            impl<A, B> Dimensions for $name<A, B>
+            where
+                A: Dimensions,
+                B: Dimensions,
+            {
+                fn bounding_box(&self) -> Rectangle {
+                    let mut b = self.1.bounding_box();
+                    b.top_left += self.2;
+                    union(self.0.bounding_box(), b)
+                }
+            }
+
+            impl<A, B> Drawable for $name<A, B>
+            where
+                A: Drawable,
+                B: Drawable<Color = A::Color>,
+            {
+                type Color = A::Color;
+
+                type Output = (A::Output, B::Output);
+
+                fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+                where
+                    D: DrawTarget<Color = Self::Color>,
+                {
+                    let a = self.0.draw(target)?;
+                    let b = self.1.draw(&mut target.translated(self.2))?;
+                    Ok((a, b))
+                }
+            }
+        )*};
+    }
+
+    impl_stack!(
+        /// Two drawables side by side, the second one is moved by the offset
+        HStack,
+        /// Two drawables on top of each other, the second one is moved by the offset
+        VStack
+    );
+
+    /// A drawable moved by an offset
+    pub struct Offset<T>(pub(super) T, pub(super) Point);
+
+    impl<T> Dimensions for Offset<T>
+    where
+        T: Dimensions,
+    {
+        fn bounding_box(&self) -> Rectangle {
+            let mut bounds = self.0.bounding_box();
+            bounds.top_left += self.1;
+            bounds
+        }
+    }
+
+    impl<T> Drawable for Offset<T>
+    where
+        T: Drawable,
+    {
+        type Color = T::Color;
+
+        type Output = T::Output;
+
+        fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+        where
+            D: DrawTarget<Color = Self::Color>,
+        {
+            self.0.draw(&mut target.translated(self.1))
+        }
+    }
+
+    /// A drawable with blank space around it
+    pub struct Padding<T> {
+        pub(super) content: T,
+        pub(super) top: u32,
+        pub(super) right: u32,
+        pub(super) bottom: u32,
+        pub(super) left: u32,
+    }
+
+    impl<T> Padding<T> {
+        fn content_offset(&self) -> Point {
+            Point::new(
+                i32::try_from(self.left).unwrap(),
+                i32::try_from(self.top).unwrap(),
+            )
+        }
+    }
+
+    impl<T> Dimensions for Padding<T>
+    where
+        T: Dimensions,
+    {
+        fn bounding_box(&self) -> Rectangle {
+            let bounds = self.content.bounding_box();
+            Rectangle::new(
+                bounds.top_left,
+                bounds.size + Size::new(self.left + self.right, self.top + self.bottom),
+            )
+        }
+    }
+
+    impl<T> Drawable for Padding<T>
+    where
+        T: Drawable,
+    {
+        type Color = T::Color;
+
+        type Output = T::Output;
+
+        fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+        where
+            D: DrawTarget<Color = Self::Color>,
+        {
+            self.content
+                .draw(&mut target.translated(self.content_offset()))
+        }
+    }
+
+    /// A drawable repeated horizontally
+    pub struct Repeat<T> {
+        pub(super) content: T,
+        pub(super) period: u32,
+        pub(super) count: u32,
+        pub(super) gap: u32,
+    }
+
+    impl<T> Dimensions for Repeat<T>
+    where
+        T: Dimensions,
+    {
+        fn bounding_box(&self) -> Rectangle {
+            let bounds = self.content.bounding_box();
+            let width = (self.period * self.count).saturating_sub(self.gap);
+            Rectangle::new(bounds.top_left, Size::new(width, bounds.size.height))
+        }
+    }
+
+    impl<T> Drawable for Repeat<T>
+    where
+        T: Drawable,
+    {
+        type Color = T::Color;
+
+        type Output = ();
+
+        fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+        where
+            D: DrawTarget<Color = Self::Color>,
+        {
+            let period = i32::try_from(self.period).unwrap();
+            for i in 0..i32::try_from(self.count).unwrap() {
+                self.content
+                    .draw(&mut target.translated(Point::new(i * period, 0)))?;
+            }
+            Ok(())
+        }
+    }
+
+    pub struct Marquee<T> {
+        pub(super) content: T,
+        pub(super) period: u32,
+        pub(super) count: u32,
+    }
+
+    impl<T> Dimensions for Marquee<T>
+    where
+        T: Dimensions,
+    {
+        fn bounding_box(&self) -> Rectangle {
+            let bounds = self.content.bounding_box();
+            Rectangle::new(
+                bounds.top_left,
+                Size::new(self.period * self.count, bounds.size.height),
+            )
+        }
+    }
+
+    impl<T> Drawable for Marquee<T>
+    where
+        T: Drawable,
+    {
+        type Color = T::Color;
+
+        type Output = ();
+
+        fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+        where
+            D: DrawTarget<Color = Self::Color>,
+        {
+            let period = i32::try_from(self.period).unwrap();
+            for i in 0..i32::try_from(self.count).unwrap() {
+                self.content
+                    .draw(&mut target.translated(Point::new(i * period, 0)))?;
+            }
+            Ok(())
+        }
+    }
+}
+
+pub mod effect {
+    //! Types used by `DrawableEffectExt`
+
+    use std::{collections::HashSet, convert::Infallible};
+
+    use embedded_graphics::{
+        draw_target::DrawTarget,
+        geometry::{Dimensions, Point, Size},
+        pixelcolor::BinaryColor,
+        primitives::Rectangle,
+        transform::Transform,
+        Drawable, Pixel,
+    };
+
+    /// Collects all pixels that are on after drawing the content
+    struct Collector {
+        bounding_box: Rectangle,
+        pixels: HashSet<Point>,
+    }
+
+    impl Collector {
+        fn collect<T>(content: &T) -> (T::Output, HashSet<Point>)
+        where
+            T: Drawable<Color = BinaryColor> + Dimensions,
+        {
+            let mut collector = Self {
+                bounding_box: content.bounding_box(),
+                pixels: HashSet::new(),
+            };
+            let output = match content.draw(&mut collector) {
+                Ok(output) => output,
+                Err(never) => match never {},
+            };
+            (output, collector.pixels)
+        }
+    }
+
+    impl Dimensions for Collector {
+        fn bounding_box(&self) -> Rectangle {
+            self.bounding_box
+        }
+    }
+
+    impl DrawTarget for Collector {
+        type Color = BinaryColor;
+
+        type Error = Infallible;
+
+        fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Pixel<Self::Color>>,
+        {
+            for Pixel(point, color) in pixels {
+                match color {
+                    BinaryColor::Off => self.pixels.remove(&point),
+                    BinaryColor::On => self.pixels.insert(point),
+                };
+            }
+            Ok(())
+        }
+    }
+
+    /// Grow a rectangle by `left`, `top`, `right` and `bottom` pixels
+    fn grow(rect: Rectangle, left: u32, top: u32, right: u32, bottom: u32) -> Rectangle {
+        Rectangle::new(
+            rect.top_left - Point::new(left.try_into().unwrap(), top.try_into().unwrap()),
+            rect.size + Size::new(left + right, top + bottom),
+        )
+    }
+
+    macro_rules! impl_transform {
+        ($($name:ident),*) => {$(
+            impl<T> Transform for $name<T>
+            where
+                T: Transform,
+            {
+                fn translate(&self, by: Point) -> Self {
+                    Self(self.0.translate(by))
+                }
+
+                fn translate_mut(&mut self, by: Point) -> &mut Self {
+                    self.0.translate_mut(by);
+                    self
+                }
+            }
+        )*};
+    }
+
+    impl_transform!(Outline, Embolden, Shadow);
+
+    pub struct Outline<T>(pub(super) T);
+
+    impl<T> Dimensions for Outline<T>
+    where
+        T: Dimensions,
+    {
+        fn bounding_box(&self) -> Rectangle {
+            grow(self.0.bounding_box(), 1, 1, 1, 1)
+        }
+    }
+
+    impl<T> Drawable for Outline<T>
+    where
+        T: Drawable<Color = BinaryColor> + Dimensions,
+    {
+        type Color = BinaryColor;
+
+        type Output = T::Output;
+
+        fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+        where
+            D: DrawTarget<Color = Self::Color>,
+        {
+            let (output, pixels) = Collector::collect(&self.0);
+            let mut outline = HashSet::new();
+            for point in &pixels {
+                for dx in -1..=1 {
+                    for dy in -1..=1 {
+                        let neighbor = *point + Point::new(dx, dy);
+                        if !pixels.contains(&neighbor) {
+                            outline.insert(neighbor);
+                        }
+                    }
+                }
+            }
+            target.draw_iter(
+                outline
+                    .into_iter()
+                    .map(|point| Pixel(point, BinaryColor::On)),
+            )?;
+            Ok(output)
+        }
+    }
+
+    pub struct Embolden<T>(pub(super) T);
+
+    impl<T> Dimensions for Embolden<T>
+    where
+        T: Dimensions,
+    {
+        fn bounding_box(&self) -> Rectangle {
+            grow(self.0.bounding_box(), 0, 0, 1, 0)
+        }
+    }
+
+    impl<T> Drawable for Embolden<T>
+    where
+        T: Drawable<Color = BinaryColor> + Dimensions,
+    {
+        type Color = BinaryColor;
+
+        type Output = T::Output;
+
+        fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+        where
+            D: DrawTarget<Color = Self::Color>,
+        {
+            let (output, pixels) = Collector::collect(&self.0);
+            target.draw_iter(
+                pixels
+                    .iter()
+                    .flat_map(|&point| [point, point + Point::new(1, 0)])
+                    .map(|point| Pixel(point, BinaryColor::On)),
+            )?;
+            Ok(output)
+        }
+    }
+
+    pub struct Shadow<T>(pub(super) T);
+
+    impl<T> Dimensions for Shadow<T>
+    where
+        T: Dimensions,
+    {
+        fn bounding_box(&self) -> Rectangle {
+            grow(self.0.bounding_box(), 0, 0, 1, 1)
+        }
+    }
+
+    impl<T> Drawable for Shadow<T>
+    where
+        T: Drawable<Color = BinaryColor> + Dimensions,
+    {
+        type Color = BinaryColor;
+
+        type Output = T::Output;
+
+        fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+        where
+            D: DrawTarget<Color = Self::Color>,
+        {
+            let (output, pixels) = Collector::collect(&self.0);
+            let shadow = pixels
+                .iter()
+                .map(|&point| point + Point::new(1, 1))
+                .filter(|point| !pixels.contains(point));
+            target.draw_iter(
+                shadow
+                    .chain(pixels.iter().copied())
+                    .map(|point| Pixel(point, BinaryColor::On)),
+            )?;
+            Ok(output)
+        }
+    }
+}
+
+pub mod dither {
+    //! Convert grayscale images to monochrome
+    //!
+    //! Grayscale images are given as one byte per pixel in rows from top to bottom,
+    //! where 0 is dark and 255 is fully lit.
+    //! ```
+    //! use badgemagic::{
+    //!     embedded_graphics::geometry::{Dimensions, Size},
+    //!     util::dither::{dither, Method},
+    //! };
+    //!
+    //! // horizontal gradient
+    //! let gray: Vec<u8> = (0..11).flat_map(|_| (0..44).map(|x| x * 5)).collect();
+    //! let image = dither(&gray, 44, Method::FloydSteinberg);
+    //! assert_eq!(image.bounding_box().size, Size::new(44, 11));
+    //! ```
+
+    use embedded_graphics::{
+        draw_target::DrawTarget,
+        geometry::{Dimensions, Point, Size},
+        pixelcolor::BinaryColor,
+        primitives::Rectangle,
+        Drawable, Pixel,
+    };
+
+    /// Dithering algorithm
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum Method {
+        /// Error diffusion, best for photos
+        #[default]
+        FloydSteinberg,
+        /// Ordered dithering with a 4x4 Bayer matrix, best for gradients and logos
+        Ordered,
+        /// No dithering, pixels brighter than the threshold are lit
+        Threshold(u8),
+    }
+
+    const BAYER_4X4: [[u16; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+    /// Convert the grayscale image `gray` with `width` columns to monochrome
+    ///
+    /// # Panics
+    /// Panics if `width` is zero.
+    #[must_use]
+    pub fn dither(gray: &[u8], width: u32, method: Method) -> Dithered {
+        assert!(width > 0, "width must not be zero");
+        let columns = width as usize;
+        let pixels = match method {
+            Method::FloydSteinberg => floyd_steinberg(gray, columns),
+            Method::Ordered => gray
+                .iter()
+                .enumerate()
+                .map(|(i, &value)| {
+                    let threshold = BAYER_4X4[(i / columns) % 4][(i % columns) % 4];
+                    // compare value / 256 > (threshold + 0.5) / 16
+                    u16::from(value) * 32 > threshold * 512 + 256
+                })
+                .collect(),
+            Method::Threshold(threshold) => gray.iter().map(|&value| value > threshold).collect(),
+        };
+        Dithered { width, pixels }
+    }
+
+    fn floyd_steinberg(gray: &[u8], columns: usize) -> Vec<bool> {
+        let mut values: Vec<i16> = gray.iter().map(|&value| i16::from(value)).collect();
+        let mut pixels = Vec::with_capacity(values.len());
+        for i in 0..values.len() {
+            let x = i % columns;
+            let on = values[i] >= 128;
+            let error = values[i] - if on { 255 } else { 0 };
+            let mut spread = |index: usize, weight: i16| {
+                if let Some(value) = values.get_mut(index) {
+                    *value += error * weight / 16;
+                }
+            };
+            if x + 1 < columns {
+                spread(i + 1, 7);
+                spread(i + columns + 1, 1);
+            }
+            if x > 0 {
+                spread(i + columns - 1, 3);
+            }
+            spread(i + columns, 5);
+            pixels.push(on);
+        }
+        pixels
+    }
+
+    /// A dithered monochrome image, drawn at the origin
+    pub struct Dithered {
+        width: u32,
+        pixels: Vec<bool>,
+    }
+
+    #[cfg(feature = "image")]
+    impl Dithered {
+        /// Convert a grayscale image of the `image` crate
+        ///
+        /// # Panics
+        /// Panics if the image is empty.
+        #[must_use]
+        pub fn from_gray_image(image: &::image::GrayImage, method: Method) -> Self {
+            dither(image.as_raw(), image.width(), method)
+        }
+
+        /// Convert any image of the `image` crate
+        ///
+        /// Transparent pixels are treated as dark.
+        /// ```
+        /// # use badgemagic::{
+        /// #     protocol::{PayloadBuffer, Style},
+        /// #     util::dither::{Dithered, Method},
+        /// # };
+        /// let image = image::DynamicImage::new_luma8(44, 11);
+        /// let mut payload = PayloadBuffer::new();
+        /// payload.add_message_drawable(
+        ///     Style::default(),
+        ///     &Dithered::from_image(&image, Method::Ordered),
+        /// );
+        /// ```
+        ///
+        /// # Panics
+        /// Panics if the image is empty.
+        #[must_use]
+        pub fn from_image(image: &::image::DynamicImage, method: Method) -> Self {
+            let image = image.to_luma_alpha8();
+            let gray: Vec<u8> = image
+                .pixels()
+                .map(|pixel| {
+                    let [luma, alpha] = pixel.0;
+                    u8::try_from(u16::from(luma) * u16::from(alpha) / 255).unwrap()
+                })
+                .collect();
+            dither(&gray, image.width(), method)
+        }
+    }
+
+    impl Dimensions for Dithered {
+        fn bounding_box(&self) -> Rectangle {
+            let height = self.pixels.len().div_ceil(self.width as usize);
+            Rectangle::new(
+                Point::zero(),
+                Size::new(self.width, u32::try_from(height).unwrap()),
+            )
+        }
+    }
+
+    impl Drawable for Dithered {
+        type Color = BinaryColor;
+
+        type Output = ();
+
+        fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+        where
+            D: DrawTarget<Color = Self::Color>,
+        {
+            let width = self.width as usize;
+            target.draw_iter(
+                self.pixels
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &on)| on)
+                    .map(|(i, _)| {
+                        let x = i32::try_from(i % width).unwrap();
+                        let y = i32::try_from(i / width).unwrap();
+                        Pixel(Point::new(x, y), BinaryColor::On)
+                    }),
+            )
+        }
+    }
+}
+
+pub mod target {
+    //! Types used by `DrawTargetTransformExt`
+
+    use embedded_graphics::{
+        draw_target::DrawTarget,
+        geometry::{Dimensions, Point},
+        pixelcolor::BinaryColor,
+        primitives::Rectangle,
+        Pixel,
+    };
+
+    /// Mirror `point` horizontally (and vertically if `vertical` is set) inside `area`
+    fn flip(area: Rectangle, point: Point, vertical: bool) -> Point {
+        let bottom_right = area.top_left + area.size - Point::new(1, 1);
+        Point::new(
+            area.top_left.x + bottom_right.x - point.x,
+            if vertical {
+                area.top_left.y + bottom_right.y - point.y
+            } else {
+                point.y
+            },
+        )
+    }
+
+    macro_rules! impl_transform {
+        ($name:ident, |$target:ident, $pixel:pat_param| $transform:expr) => {
+            impl<T> Dimensions for $name<'_, T>
+            where
+                T: DrawTarget<Color = BinaryColor>,
+            {
+                fn bounding_box(&self) -> Rectangle {
+                    self.0.bounding_box()
+                }
+            }
+
+            impl<T> DrawTarget for $name<'_, T>
+            where
+                T: DrawTarget<Color = BinaryColor>,
+            {
+                type Color = BinaryColor;
+
+                type Error = T::Error;
+
+                fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+                where
+                    I: IntoIterator<Item = Pixel<Self::Color>>,
+                {
+                    let $target = self.0.bounding_box();
+                    self.0
+                        .draw_iter(pixels.into_iter().map(|$pixel| $transform))
+                }
+            }
+        };
+    }
+
+    pub struct Inverted<'a, T>(pub(super) &'a mut T);
+
+    impl_transform!(Inverted, |_area, Pixel(point, color)| Pixel(
+        point,
+        color.invert()
+    ));
+
+    pub struct Mirrored<'a, T>(pub(super) &'a mut T);
+
+    impl_transform!(Mirrored, |area, Pixel(point, color)| Pixel(
+        flip(area, point, false),
+        color
+    ));
+
+    pub struct Rotated180<'a, T>(pub(super) &'a mut T);
+
+    impl_transform!(Rotated180, |area, Pixel(point, color)| Pixel(
+        flip(area, point, true),
+        color
+    ));
+
+    pub struct Clipped<'a, T>(pub(super) &'a mut T);
+
+    impl<T> Dimensions for Clipped<'_, T>
+    where
+        T: DrawTarget<Color = BinaryColor>,
+    {
+        fn bounding_box(&self) -> Rectangle {
+            self.0.bounding_box()
+        }
+    }
+
+    impl<T> DrawTarget for Clipped<'_, T>
+    where
+        T: DrawTarget<Color = BinaryColor>,
+    {
+        type Color = BinaryColor;
+
+        type Error = T::Error;
+
+        fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Pixel<Self::Color>>,
+        {
+            let area = self.0.bounding_box();
+            self.0.draw_iter(
+                pixels
+                    .into_iter()
+                    .filter(|Pixel(point, _)| area.contains(*point)),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use embedded_graphics::{
+        draw_target::{DrawTarget, DrawTargetExt},
+        geometry::{Dimensions, Point, Size},
+        pixelcolor::BinaryColor,
+        primitives::{PointsIter, Rectangle},
+        Drawable, Pixel,
+    };
+
+    use super::{
+        marquee, DrawTargetTransformExt, DrawableEffectExt, DrawableLayoutExt, Frame, FrameStyle,
+        Framebuffer, SpriteSheet,
+    };
+    use crate::protocol::{DrawMode, PayloadBuffer, Style};
+
+    /// A 3x3 drawable with the pixels at `points` lit
+    fn shape(points: &[(i32, i32)]) -> Framebuffer {
+        let mut shape = Framebuffer::blank(3, 3);
+        let pixels = points
+            .iter()
+            .map(|&(x, y)| Pixel(Point::new(x, y), BinaryColor::On));
+        shape.draw_iter(pixels).unwrap();
+        shape
+    }
+
+    /// Return the pixels lit by `drawable` row by row, including the ones left and above of it
+    fn lit(drawable: &impl Drawable<Color = BinaryColor>) -> Vec<(i32, i32)> {
+        let mut target = Framebuffer::blank(64, 16);
+        drawable
+            .draw(&mut target.translated(Point::new(1, 1)))
+            .unwrap();
+        target
+            .bounding_box()
+            .points()
+            .filter(|&point| target.get(point) == Some(BinaryColor::On))
+            .map(|point| (point.x - 1, point.y - 1))
+            .collect()
+    }
+
+    #[test]
+    fn outline() {
+        let outline = shape(&[(1, 1)]).outline();
+        assert_eq!(
+            lit(&outline),
+            [
+                (0, 0),
+                (1, 0),
+                (2, 0),
+                (0, 1),
+                (2, 1),
+                (0, 2),
+                (1, 2),
+                (2, 2)
+            ]
+        );
+        assert_eq!(
+            outline.bounding_box(),
+            Rectangle::new(Point::new(-1, -1), Size::new(5, 5))
+        );
+    }
+
+    #[test]
+    fn embolden() {
+        let bold = shape(&[(0, 0), (1, 1)]).embolden();
+        assert_eq!(lit(&bold), [(0, 0), (1, 0), (1, 1), (2, 1)]);
+        assert_eq!(
+            bold.bounding_box(),
+            Rectangle::new(Point::zero(), Size::new(4, 3))
+        );
+    }
+
+    #[test]
+    fn shadow() {
+        let shadow = shape(&[(0, 0), (1, 0)]).shadow();
+        assert_eq!(lit(&shadow), [(0, 0), (1, 0), (1, 1), (2, 1)]);
+        assert_eq!(
+            shadow.bounding_box(),
+            Rectangle::new(Point::zero(), Size::new(4, 4))
+        );
+    }
+
+    #[test]
+    fn hstack() {
+        let stack = shape(&[(0, 0), (2, 2)]).hstack(shape(&[(1, 0)]), 2);
+        assert_eq!(lit(&stack), [(0, 0), (6, 0), (2, 2)]);
+        assert_eq!(
+            stack.bounding_box(),
+            Rectangle::new(Point::zero(), Size::new(8, 3))
+        );
+    }
+
+    #[test]
+    fn vstack() {
+        let stack = shape(&[(0, 0)]).vstack(shape(&[(2, 1)]), 1);
+        assert_eq!(lit(&stack), [(0, 0), (2, 5)]);
+        assert_eq!(
+            stack.bounding_box(),
+            Rectangle::new(Point::zero(), Size::new(3, 7))
+        );
+    }
+
+    #[test]
+    fn offset() {
+        let moved = shape(&[(0, 0), (2, 1)]).offset(Point::new(-1, 2));
+        assert_eq!(lit(&moved), [(-1, 2), (1, 3)]);
+        assert_eq!(
+            moved.bounding_box(),
+            Rectangle::new(Point::new(-1, 2), Size::new(3, 3))
+        );
+    }
+
+    #[test]
+    fn padding() {
+        let padded = shape(&[(0, 0)]).padding(1, 2, 3, 4);
+        assert_eq!(lit(&padded), [(4, 1)]);
+        assert_eq!(
+            padded.bounding_box(),
+            Rectangle::new(Point::zero(), Size::new(9, 7))
+        );
+    }
+
+    #[test]
+    fn repeat_x() {
+        let row = shape(&[(0, 1)]).repeat_x(3, 1);
+        assert_eq!(lit(&row), [(0, 1), (4, 1), (8, 1)]);
+        assert_eq!(
+            row.bounding_box(),
+            Rectangle::new(Point::zero(), Size::new(11, 3))
+        );
+    }
+
+    #[test]
+    fn frame() {
+        let solid = Frame::new(shape(&[(1, 1)]), FrameStyle::Solid, 1);
+        assert_eq!(
+            solid.bounding_box(),
+            Rectangle::new(Point::zero(), Size::new(5, 5))
+        );
+        let pixels = lit(&solid);
+        assert_eq!(pixels.len(), 16 + 1);
+        assert!(pixels.contains(&(2, 2)) && !pixels.contains(&(1, 1)));
+
+        let dotted = Frame::new(shape(&[]), FrameStyle::Dotted, 1);
+        assert_eq!(
+            lit(&dotted),
+            [
+                (0, 0),
+                (2, 0),
+                (4, 0),
+                (0, 2),
+                (4, 2),
+                (0, 4),
+                (2, 4),
+                (4, 4)
+            ]
+        );
+
+        let double = Frame::new(shape(&[(0, 0)]), FrameStyle::Double, 1);
+        assert_eq!(
+            double.bounding_box(),
+            Rectangle::new(Point::zero(), Size::new(9, 9))
+        );
+        let pixels = lit(&double);
+        assert!(pixels.contains(&(2, 2)) && !pixels.contains(&(1, 1)));
+        assert!(pixels.contains(&(3, 3)) && pixels.contains(&(6, 6)));
+        assert_eq!(pixels.len(), 32 + 16 + 1);
+    }
+
+    #[test]
+    fn sprite_sheet() {
+        // 16 columns with 2 bytes per row, the last column is not a complete frame
+        let mut data = [0; 2 * 11];
+        data[0] = 0x80; // row 0, column 0
+        data[2] = 0x02; // row 1, column 6
+        data[5] = 0x08; // row 2, column 12
+        data[7] = 0x01; // row 3, column 15
+        let sheet = SpriteSheet::from_raw(&data, 5);
+        assert_eq!(sheet.len(), 3);
+        assert!(sheet.frame(3).is_none());
+        let frames: Vec<_> = sheet.frames().map(|frame| lit(&frame)).collect();
+        assert_eq!(frames, [[(0, 0)], [(1, 1)], [(2, 2)]]);
+        assert_eq!(
+            sheet.frame(1).unwrap().bounding_box(),
+            Rectangle::new(Point::zero(), Size::new(5, 11))
+        );
+    }
+
+    #[test]
+    fn transform_adapters() {
+        let content = shape(&[(0, 0), (1, 0)]);
+
+        let mut target = Framebuffer::blank(3, 3);
+        content.draw(&mut target.mirrored()).unwrap();
+        assert_eq!(lit(&target), [(1, 0), (2, 0)]);
+
+        let mut target = Framebuffer::blank(3, 3);
+        content.draw(&mut target.rotated_180()).unwrap();
+        assert_eq!(lit(&target), [(1, 2), (2, 2)]);
+
+        let all: Vec<_> = (0..3).flat_map(|y| (0..3).map(move |x| (x, y))).collect();
+        let mut target = shape(&all);
+        content.draw(&mut target.inverted()).unwrap();
+        assert_eq!(lit(&target), all[2..]);
+    }
+
+    #[test]
+    fn clip() {
+        let content = shape(&[(0, 0), (1, 1), (2, 2)]).offset(Point::new(6, -1));
+        let mut payload = PayloadBuffer::new().draw_mode(DrawMode::Strict);
+        let mut buffer = payload.add_message(Style::default(), 1);
+        assert!(content.draw(&mut buffer).is_err());
+        content.draw(&mut buffer.clip()).unwrap();
+        let (_, columns) = payload.message(0).unwrap();
+        // only the pixel at (7, 0) is inside the 8 columns of the message
+        assert_eq!(columns, [[0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]]);
+    }
+
+    #[test]
+    fn marquee_loops_seamlessly() {
+        // copies every 4 columns, until the width is a multiple of 8 wider than the display
+        let looped = marquee(shape(&[(0, 1)]), 1);
+        let copies: Vec<_> = (0..12).map(|i| (4 * i, 1)).collect();
+        assert_eq!(lit(&looped), copies);
+        assert_eq!(
+            looped.bounding_box(),
+            Rectangle::new(Point::zero(), Size::new(48, 3))
+        );
+
+        let looped = marquee(shape(&[(0, 1)]), 5);
+        assert_eq!(lit(&looped).len(), 6);
+        assert_eq!(looped.bounding_box().size.width, 48);
+    }
 }